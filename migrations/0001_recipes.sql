@@ -0,0 +1,11 @@
+-- the recipe book
+CREATE TABLE recipes (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    -- the whole `Recipe`, as JSON
+    recipe TEXT NOT NULL,
+    -- seconds since the Unix epoch
+    saved_at INTEGER NOT NULL
+);
+
+CREATE INDEX recipes_saved_at ON recipes (saved_at);
@@ -0,0 +1,11 @@
+-- what happened to a recipe after it was saved
+ALTER TABLE recipes ADD COLUMN rating INTEGER CHECK (rating BETWEEN 1 AND 5);
+ALTER TABLE recipes ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+
+CREATE TABLE cooked (
+    recipe_id TEXT NOT NULL REFERENCES recipes (id) ON DELETE CASCADE,
+    -- seconds since the Unix epoch
+    cooked_at INTEGER NOT NULL
+);
+
+CREATE INDEX cooked_recipe_id ON cooked (recipe_id);
@@ -0,0 +1,23 @@
+-- accounts, and whose everything is
+CREATE TABLE users (
+    id TEXT PRIMARY KEY NOT NULL,
+    -- normalized, see `user::normalize_email`
+    email TEXT NOT NULL UNIQUE,
+    -- argon2, in the PHC string format
+    password_hash TEXT NOT NULL,
+    -- seconds since the Unix epoch
+    created_at INTEGER NOT NULL
+);
+
+-- recipes saved before there were accounts have none, the first account gets them
+ALTER TABLE recipes ADD COLUMN user_id TEXT REFERENCES users (id) ON DELETE CASCADE;
+
+CREATE INDEX recipes_user_id ON recipes (user_id, saved_at);
+
+CREATE TABLE preferences (
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- as JSON
+    value TEXT NOT NULL,
+    PRIMARY KEY (user_id, name)
+);
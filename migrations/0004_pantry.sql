@@ -0,0 +1,10 @@
+-- everyone's pantry, including what was taken out of it, see `pantry::Ingredient`
+CREATE TABLE pantry (
+    id TEXT NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- the whole `pantry::Ingredient` as JSON
+    ingredient TEXT NOT NULL,
+    -- milliseconds since the Unix epoch, the same as in `ingredient`
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, id)
+);
@@ -0,0 +1,2 @@
+-- the prompt template a saved recipe was generated with, as `name@version`
+ALTER TABLE recipes ADD COLUMN template TEXT;
@@ -0,0 +1,4 @@
+-- a shared recipe can be read by anyone with its token, see `Db::share_recipe`
+ALTER TABLE recipes ADD COLUMN share_token TEXT;
+
+CREATE UNIQUE INDEX recipes_share_token ON recipes (share_token);
@@ -0,0 +1,11 @@
+-- the prompt template behind each answer, so saving a recipe from it names the generation and
+-- not the template, see `Db::record_generation`
+CREATE TABLE generations (
+    id TEXT PRIMARY KEY NOT NULL,
+    -- as `name@version`
+    template TEXT NOT NULL,
+    -- seconds since the Unix epoch
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX generations_created_at ON generations (created_at);
@@ -4,21 +4,174 @@ use leptos_router::*;
 
 use leptos_use::storage::{use_local_storage, JsonCodec};
 use uuid::Uuid;
-use std::{env::var, fmt::{Display, self}, borrow::Borrow};
 
-use crate::recipe::{self, Recipe};
+use crate::book::{self, SavedRecipe};
+use crate::diet::{Allergen, Diet, DietaryProfile};
+use crate::pantry::{self, Ingredient};
+use crate::recipe::{self, ParseReport, UnitSystem};
+use crate::user::{storage_key, User};
 
+/// Where `stream_recipes` is served.
+pub const STREAM_RECIPES_PATH: &str = "/api/recipes/stream";
+
+const PANTRY_KEY: &str = "ingredients";
+const DIETARY_PROFILE_KEY: &str = "dietary-profile";
+const RECIPE_OPTIONS_KEY: &str = "recipe-options";
+const UNIT_SYSTEM_KEY: &str = "unit-system";
+
+/// What server functions that need an account answer without one.
+const LOG_IN_FIRST: &str = "Log in first";
+
+/// The recipes being generated, filled in while the answer streams in.
+#[derive(Copy, Clone)]
+struct RecipesCtx {
+    report: RwSignal<Option<Result<ParseReport, String>>>,
+    /// The prompt template behind `report`, as `name@version`.
+    template: RwSignal<Option<String>>,
+    /// What recipes in `report` are saved with, see `Generation::id`.
+    generation: RwSignal<Option<Uuid>>,
+    pending: RwSignal<bool>,
+    source: StoredValue<Option<web_sys::EventSource>>,
+}
+
+impl RecipesCtx {
+    fn new() -> RecipesCtx {
+        RecipesCtx { report: create_rw_signal(None), template: create_rw_signal(None), generation: create_rw_signal(None), pending: create_rw_signal(false), source: store_value(None) }
+    }
+
+    /// Asks `STREAM_RECIPES_PATH` for recipes and adds each one to `report` as soon as it's there.
+    fn generate(self, request: &RecipeRequest) {
+        use wasm_bindgen::{closure::Closure, JsCast};
+
+        self.close();
+
+        let url = format!(
+            "{}?request={}",
+            STREAM_RECIPES_PATH,
+            String::from(js_sys::encode_uri_component(&serde_json::to_string(request).unwrap_or_default())),
+        );
+        let source = match web_sys::EventSource::new(&url) {
+            Ok(source) => source,
+            Err(_) => return self.report.set(Some(Err("Could not reach the server".to_owned()))),
+        };
+        self.report.set(Some(Ok(ParseReport::default())));
+        self.template.set(None);
+        self.generation.set(None);
+        self.pending.set(true);
+
+        let listen = |event: &str, on_data: Box<dyn Fn(&str)>| {
+            let closure = Closure::<dyn Fn(web_sys::MessageEvent)>::new(move |ev: web_sys::MessageEvent| {
+                on_data(&ev.data().as_string().unwrap_or_default())
+            });
+            let _ = source.add_event_listener_with_callback(event, closure.as_ref().unchecked_ref());
+            closure.forget();
+        };
+
+        listen("recipe", Box::new(move |data| {
+            if let Ok(r) = serde_json::from_str::<recipe::Recipe>(data) {
+                self.report.update(|report| if let Some(Ok(report)) = report { report.recipes.push(r) });
+            }
+        }));
+        listen("done", Box::new(move |data| {
+            match serde_json::from_str::<Generation>(data) {
+                Ok(generation) => {
+                    self.report.set(Some(Ok(generation.report)));
+                    self.template.set(Some(generation.template));
+                    self.generation.set(generation.id);
+                }
+                Err(e) => self.report.set(Some(Err(e.to_string()))),
+            }
+            self.close();
+        }));
+        listen("failed", Box::new(move |data| {
+            self.report.set(Some(Err(serde_json::from_str(data).unwrap_or_else(|_| data.to_owned()))));
+            self.close();
+        }));
+
+        // the browser's own "error" means the connection dropped, left alone it would reconnect
+        // and start over
+        let on_error = Closure::<dyn Fn(web_sys::Event)>::new(move |_| {
+            if self.pending.get_untracked() {
+                self.report.set(Some(Err("Lost the connection to the server".to_owned())));
+                self.close();
+            }
+        });
+        source.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        on_error.forget();
+
+        self.source.set_value(Some(source));
+    }
+
+    fn close(self) {
+        self.source.update_value(|source| {
+            if let Some(source) = source.take() {
+                source.close();
+            }
+        });
+        self.pending.set(false);
+    }
+}
+
+/// Who is logged in, and the actions that change it.
 #[derive(Copy, Clone)]
-struct GetRecipesCtx(Action<GenerateRecipes, Result<Vec<Recipe>, ServerFnError>>);
+struct UserCtx {
+    /// `Some(None)` when nobody is.
+    user: Resource<(usize, usize, usize), Option<User>>,
+    register: Action<Register, Result<User, ServerFnError>>,
+    login: Action<Login, Result<User, ServerFnError>>,
+    logout: Action<Logout, Result<(), ServerFnError>>,
+}
+
+impl UserCtx {
+    fn new() -> UserCtx {
+        let register = create_server_action::<Register>();
+        let login = create_server_action::<Login>();
+        let logout = create_server_action::<Logout>();
+        let user = create_resource(
+            move || (register.version()(), login.version()(), logout.version()()),
+            |_| async {
+                get_user().await.unwrap_or_else(|e| {
+                    log!("could not find out who is logged in: {}", e);
+                    None
+                })
+            },
+        );
+        UserCtx { user, register, login, logout }
+    }
+}
+
+/// Renders `view` for whoever is logged in once that's known, and again when it changes.
+fn with_user<V: IntoView>(view: impl Fn(Option<User>) -> V + 'static) -> impl IntoView {
+    let UserCtx { user, .. } = expect_context::<UserCtx>();
+    let view = store_value(view);
+
+    view! {
+        <Transition fallback=move || view! { <div class="mt-20 flex flex-row justify-center p-2"><SpinnerIcon /></div> }>
+            {move || user().map(|user| view.with_value(|view| view(user)))}
+        </Transition>
+    }
+}
+
+/// Milliseconds since the Unix epoch, by the browser's clock.
+fn now_millis() -> i64 {
+    js_sys::Date::now() as i64
+}
+
+/// What a server function said went wrong, without the wrapping meant for developers.
+fn error_message(e: &ServerFnError) -> String {
+    match e {
+        ServerFnError::ServerError(message) => message.clone(),
+        e => e.to_string(),
+    }
+}
 
 #[component]
 pub fn App() -> impl IntoView {
     // Provides context that manages stylesheets, titles, meta tags, etc.
     provide_meta_context();
 
-    let get_recipes = create_server_action::<GenerateRecipes>();
-
-    provide_context(GetRecipesCtx(get_recipes));
+    provide_context(RecipesCtx::new());
+    provide_context(UserCtx::new());
 
     view! {
         // id=leptos means cargo-leptos will hot-reload this stylesheet
@@ -37,11 +190,19 @@ pub fn App() -> impl IntoView {
         <Title text="Cookie"/>
 
         <Router>
+            {with_user(|user| user.map(|user| view! { <GuestData user=user /> }))}
             <Navbar />
             <main>
                 <Routes>
                     <Route path="/" view=move || view! { <Redirect path="lab" /> }/>
                     <Route path="/lab" view=Lab/>
+                    <Route path="/book" view=Book/>
+                    // rendered whole on the server so the title and description are in the page's head
+                    <Route path="/book/:id" view=RecipeDetail ssr=SsrMode::Async/>
+                    <Route path="/shared/:token" view=SharedRecipe ssr=SsrMode::Async/>
+                    <Route path="/settings" view=Settings/>
+                    <Route path="/login" view=LoginPage/>
+                    <Route path="/register" view=RegisterPage/>
                     <Route path="/*any" view=NotFound/>
                 </Routes>
             </main>
@@ -50,6 +211,16 @@ pub fn App() -> impl IntoView {
 }
 
 
+/// Sets the status of a page while it's rendered on the server. In the browser it's too late
+/// for that, so there it does nothing.
+#[cfg_attr(not(feature = "ssr"), allow(unused_variables))]
+fn set_status(status: u16) {
+    #[cfg(feature = "ssr")]
+    if let Some(resp) = use_context::<leptos_actix::ResponseOptions>() {
+        resp.set_status(actix_web::http::StatusCode::from_u16(status).expect("a valid status"));
+    }
+}
+
 #[component]
 fn NotFound() -> impl IntoView {
     // set an HTTP status code 404
@@ -73,46 +244,522 @@ fn NotFound() -> impl IntoView {
 
 
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
-struct Ingredient {
-    id: Uuid,
-    name: String,
-    quantity: Option<String>,
-    certainty: Option<String>,
+/// What kind of recipes to ask for, besides what's in the pantry.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq)]
+struct RecipeOptions {
+    cuisine: String,
+    /// In minutes.
+    time_budget: Option<u32>,
+    servings: Option<u32>,
+    /// How many recipes, `None` for the default.
+    count: Option<u32>,
 }
 
-impl Display for Ingredient {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.name)
+const MAX_RECIPES: u32 = 10;
+const MAX_TIME_BUDGET: u32 = 24 * 60;
+const MAX_SERVINGS: u32 = 100;
+
+#[cfg(feature = "ssr")]
+impl RecipeOptions {
+    /// Whether the numbers are ones worth asking a model for. Requests come straight from the
+    /// browser, so the server checks them again.
+    fn check(&self) -> Result<(), String> {
+        let in_range = |n: Option<u32>, max: u32| n.is_none_or(|n| (1..=max).contains(&n));
+        if !in_range(self.count, MAX_RECIPES) {
+            Err(format!("Ask for between 1 and {} recipes", MAX_RECIPES))
+        } else if !in_range(self.time_budget, MAX_TIME_BUDGET) {
+            Err(format!("The time to cook has to be between 1 and {} minutes", MAX_TIME_BUDGET))
+        } else if !in_range(self.servings, MAX_SERVINGS) {
+            Err(format!("Servings have to be between 1 and {}", MAX_SERVINGS))
+        } else {
+            Ok(())
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-struct RecipeItem {
-    id: Uuid,
-    text: String,
+/// Everything that goes into the prompt.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq)]
+struct RecipeRequest {
+    ingredients: Vec<Ingredient>,
+    profile: DietaryProfile,
+    options: RecipeOptions,
 }
 
-
-fn main() {
-    mount_to_body(|| view! { <App/> })
+/// The recipes from one answer, and the prompt template that asked for them as `name@version`.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Generation {
+    report: ParseReport,
+    template: String,
+    /// What the server remembers `template` under, so a recipe saved from the answer is kept
+    /// with the template the server asked with. `None` when that couldn't be recorded.
+    id: Option<Uuid>,
 }
 
+
 #[component]
 fn Lab() -> impl IntoView {
 
-    view! {
+    with_user(|user| view! {
         <div class="mt-20 flex flex-col md:flex-row gap-2 lg:gap-8 px-2 md:px-5 lg:px-12" >
             <div class="w-full md:w-2/5" >
-                <Pantry/>
+                <Pantry user=user.clone() />
             </div>
 
             <div class="w-full md:w-3/5" >
-                <RecipeList />
+                <RecipeList user=user />
+            </div>
+        </div>
+    })
+
+}
+
+#[component]
+fn Book() -> impl IntoView {
+    with_user(|user| match user {
+        Some(_) => view! { <UserBook/> }.into_view(),
+        None => view! {
+            <div class="mt-20 flex flex-col gap-2 px-2 md:px-5 lg:px-12 md:w-3/5 mx-auto" >
+                <div class="w-full p-2 bg-white border border-gray-200 rounded-lg shadow md:p-4 dark:bg-gray-800 dark:border-gray-700">
+                    <h5 class="text-xl font-medium text-gray-900 dark:text-white">"Book"</h5>
+                    <p class="my-5 text-gray-300">
+                        <A href="/login" class="text-blue-600 dark:text-blue-500 hover:underline">"Log in"</A>
+                        " or "
+                        <A href="/register" class="text-blue-600 dark:text-blue-500 hover:underline">"register"</A>
+                        " to keep a recipe book."
+                    </p>
+                </div>
+            </div>
+        }.into_view(),
+    })
+}
+
+/// The book of whoever is logged in.
+#[component]
+fn UserBook() -> impl IntoView {
+    let import = create_server_action::<ImportRecipes>();
+    let delete = create_server_action::<DeleteRecipe>();
+    let recipes = create_resource(move || (import.version()(), delete.version()()), |_| list_recipes());
+
+    // before there was a server-side book it lived in the browser, move what's left of it over
+    let (local_book, set_local_book, _) = use_local_storage::<Vec<recipe::Recipe>, JsonCodec>("book");
+    create_effect(move |_| {
+        let leftovers = local_book();
+        if leftovers.is_empty() {
+            return;
+        }
+        spawn_local(async move {
+            for r in leftovers {
+                if let Err(e) = save_recipe(r, None).await {
+                    log!("could not move a recipe to the server: {}", e);
+                    return;
+                }
+            }
+            set_local_book(vec![]);
+            recipes.refetch();
+        });
+    });
+
+    let recipe_list = move || recipes().map(|recipes| match recipes {
+        Err(e) => view! { <p class="my-5 text-red-400">{e.to_string()}</p> }.into_view(),
+        Ok(recipes) if recipes.is_empty() => view! { <p class="my-5 text-gray-300">"No recipes saved yet..."</p> }.into_view(),
+        Ok(recipes) => view! {
+            <ul role="list" class="w-full divide-y divide-gray-200 dark:divide-gray-700" >
+                {recipes.into_iter().map(|saved| view! { <BookEntry saved=saved delete=delete /> }).collect_view()}
+            </ul>
+        }.into_view(),
+    });
+
+    view! {
+        <div class="mt-20 flex flex-col gap-2 px-2 md:px-5 lg:px-12 md:w-3/5 mx-auto" >
+            <div class="w-full p-2 bg-white border border-gray-200 rounded-lg shadow md:p-4 dark:bg-gray-800 dark:border-gray-700 text-white">
+                <h5 class="text-xl font-medium text-gray-900 dark:text-white">"Book"</h5>
+                <Transition fallback=move || view! { <div class="flex flex-row justify-center p-2"><SpinnerIcon /></div> }>
+                    {recipe_list}
+                </Transition>
+                {move || delete.value()().and_then(Result::err).map(|e| view! { <p class="text-sm text-red-400">{error_message(&e)}</p> })}
+            </div>
+            <RecipeImport import=import />
+        </div>
+    }
+}
+
+/// One recipe in the book, linking to its page.
+#[component]
+fn BookEntry(saved: SavedRecipe, delete: Action<DeleteRecipe, Result<(), ServerFnError>>) -> impl IntoView {
+    let r = saved.recipe;
+
+    view! {
+        <li class="py-3 flex flex-row items-center justify-between gap-2">
+            <A href=format!("/book/{}", saved.id) class="hover:underline">
+                <p class="text-sm font-semibold text-gray-900 dark:text-white">{r.name.into_view()}</p>
+                <p class="text-sm text-gray-500 dark:text-gray-400">
+                    {format!("{} ingredients, {} steps", r.ingredients.len(), r.instructions.len())}
+                    {saved.rating.map(|n| view! { <span class="ml-2 text-yellow-400">{stars(n)}</span> })}
+                    {saved.cooked.last().map(|at| format!(", cooked {} times, last on {}", saved.cooked.len(), book::date(*at)))}
+                </p>
+                {(!saved.notes.is_empty()).then(|| view! {
+                    <p class="text-sm italic text-gray-500 dark:text-gray-400 line-clamp-1">{saved.notes.clone()}</p>
+                })}
+            </A>
+            <div on:click=move |_| delete.dispatch(DeleteRecipe { id: saved.id })>
+                <DeleteButton />
+            </div>
+        </li>
+    }
+}
+
+/// A recipe from the book on its own page at `/book/:id`.
+#[component]
+fn RecipeDetail() -> impl IntoView {
+    let params = use_params_map();
+    let id = move || params.with(|p| p.get("id").and_then(|id| Uuid::parse_str(id).ok()));
+    let saved = create_resource(id, |id| async move {
+        match id {
+            Some(id) => get_recipe(id).await,
+            None => Ok(None),
+        }
+    });
+
+    let detail = move || saved().map(|saved| match saved {
+        Err(ServerFnError::ServerError(e)) if e == LOG_IN_FIRST => {
+            set_status(401);
+            view! {
+                <p class="my-5 text-gray-300">
+                    <A href="/login" class="text-blue-600 dark:text-blue-500 hover:underline">"Log in"</A>
+                    " to see the recipes in your book."
+                </p>
+            }.into_view()
+        }
+        Err(e) => view! { <p class="my-5 text-red-400">{e.to_string()}</p> }.into_view(),
+        Ok(None) => {
+            set_status(404);
+            view! {
+                <Title text="Not Found"/>
+                <p class="my-5 text-gray-300">"There is no such recipe in the book."</p>
+            }.into_view()
+        }
+        Ok(Some(saved)) => with_user(move |user| view! { <SavedRecipeView saved=saved.clone() user=user /> }).into_view(),
+    });
+
+    view! {
+        <div class="mt-20 flex flex-col gap-2 px-2 md:px-5 lg:px-12 md:w-3/5 mx-auto" >
+            <A href="/book" class="text-sm text-blue-600 dark:text-blue-500 hover:underline">"Back to the book"</A>
+            <div class="w-full p-2 bg-white border border-gray-200 rounded-lg shadow md:p-4 dark:bg-gray-800 dark:border-gray-700 text-white">
+                <Suspense fallback=move || view! { <div class="flex flex-row justify-center p-2"><SpinnerIcon /></div> }>
+                    {detail}
+                </Suspense>
             </div>
         </div>
     }
+}
+
+/// A recipe shared from someone's book, read-only and for anyone with the link.
+#[component]
+fn SharedRecipe() -> impl IntoView {
+    let params = use_params_map();
+    let recipe = create_resource(move || params.with(|p| p.get("token").cloned().unwrap_or_default()), get_shared_recipe);
+
+    let detail = move || recipe().map(|recipe| match recipe {
+        Err(e) => view! { <p class="my-5 text-red-400">{e.to_string()}</p> }.into_view(),
+        Ok(None) => {
+            set_status(404);
+            view! {
+                <Title text="Not Found"/>
+                <p class="my-5 text-gray-300">"This recipe isn't shared, or not anymore."</p>
+            }.into_view()
+        }
+        Ok(Some(recipe)) => {
+            let about = format!("{} steps", recipe.instructions.len());
+            with_user(move |user| view! { <RecipeView recipe=recipe.clone() about=about.clone() user=user /> }).into_view()
+        }
+    });
+
+    view! {
+        <div class="mt-20 flex flex-col gap-2 px-2 md:px-5 lg:px-12 md:w-3/5 mx-auto" >
+            <div class="w-full p-2 bg-white border border-gray-200 rounded-lg shadow md:p-4 dark:bg-gray-800 dark:border-gray-700 text-white">
+                <Suspense fallback=move || view! { <div class="flex flex-row justify-center p-2"><SpinnerIcon /></div> }>
+                    {detail}
+                </Suspense>
+            </div>
+        </div>
+    }
+}
+
+#[component]
+fn SavedRecipeView(saved: SavedRecipe, user: Option<User>) -> impl IntoView {
+    let about = format!("Saved {}, {} steps", saved.saved_on(), saved.recipe.instructions.len())
+        + &saved.template.as_ref().map(|t| format!(", from the {} prompt", t)).unwrap_or_default();
+
+    view! {
+        <RecipeView recipe=saved.recipe.clone() about=about user=user />
+        <ShareLink id=saved.id token=saved.share_token.clone() />
+        <RecipeActions recipe=saved.recipe.clone() saved=saved />
+    }
+}
+
+/// A recipe on a page of its own, with `about` it under the name.
+#[component]
+fn RecipeView(recipe: recipe::Recipe, about: String, user: Option<User>) -> impl IntoView {
+    let (unit_system, set_unit_system) = use_unit_system(user.as_ref());
+    let (servings, set_servings) = create_signal(recipe.servings.unwrap_or(recipe::DEFAULT_SERVINGS));
+
+    let name = recipe::fragment_text(&recipe.name);
+    let description = format!(
+        "{} with {}",
+        name,
+        recipe.ingredients.iter().map(|i| i.name.as_str()).collect::<Vec<_>>().join(", "),
+    );
+
+    let r = recipe.clone();
+    let scaled = move || {
+        let r = r.with_servings(servings());
+        match unit_system() {
+            Some(system) => r.to_system(system),
+            None => r,
+        }
+    };
+
+    view! {
+        <Title text=name.clone()/>
+        <Meta name="description" content=description.clone()/>
+        <Meta property="og:title" content=name/>
+        <Meta property="og:description" content=description/>
+        <Meta property="og:type" content="article"/>
+        <RecipeJsonLd recipes=vec![recipe.clone()] />
+
+        <div class="flex flex-row items-start justify-between gap-2">
+            <h1 class="text-2xl font-semibold text-gray-900 dark:text-white">{recipe.name.clone().into_view()}</h1>
+            <UnitSystemSelect unit_system=unit_system set_unit_system=set_unit_system />
+        </div>
+        <p class="text-sm text-gray-500 dark:text-gray-400">{about}</p>
+
+        <div class="mt-4 flex flex-row items-center justify-between gap-2">
+            <h2 class="text-lg font-medium text-gray-900 dark:text-white">"Ingredients"</h2>
+            <ServingsStepper servings=servings set_servings=set_servings />
+        </div>
+        <ul class="mt-2 list-disc list-inside text-gray-900 dark:text-gray-300">{
+            let r = scaled.clone();
+            move || r().ingredients.iter().map(|i| view! { <li>{i.to_string()}</li> }).collect_view()
+        }</ul>
+
+        <h2 class="mt-4 text-lg font-medium text-gray-900 dark:text-white">"Steps"</h2>
+        <ol class="mt-2 list-decimal list-inside text-gray-900 dark:text-gray-300">{
+            move || scaled().instructions.into_iter().map(|i| view! { <li class="mb-1">{i.into_view()}</li> }).collect_view()
+        }</ol>
+    }
+}
 
+/// Where a shared recipe can be read without an account.
+fn share_path(token: &str) -> String {
+    format!("/shared/{}", token)
+}
+
+/// Gives a recipe in the book a public link, or takes it away again.
+#[component]
+fn ShareLink(id: Uuid, token: Option<String>) -> impl IntoView {
+    let token = create_rw_signal(token);
+    let (error, set_error) = create_signal(None::<String>);
+    let change = move |share: bool| spawn_local(async move {
+        let result = match share {
+            true => share_recipe(id).await.map(Some),
+            false => unshare_recipe(id).await.map(|_| None),
+        };
+        match result {
+            Ok(t) => {
+                token.set(t);
+                set_error(None);
+            }
+            Err(e) => set_error(Some(error_message(&e))),
+        }
+    });
+
+    view! {
+        <div class="mt-4 flex flex-row flex-wrap items-center gap-2 text-sm text-gray-500 dark:text-gray-400">
+            {move || match token() {
+                Some(t) => view! {
+                    <A href=share_path(&t) class="text-blue-600 dark:text-blue-500 hover:underline">"Public link"</A>
+                    <span>"Anyone with it can read the recipe, but not your notes."</span>
+                    <button class="text-blue-600 dark:text-blue-500 hover:underline" on:click=move |_| change(false)>"Stop sharing"</button>
+                }.into_view(),
+                None => view! {
+                    <button class="text-blue-600 dark:text-blue-500 hover:underline" on:click=move |_| change(true)>"Share"</button>
+                }.into_view(),
+            }}
+            {move || error().map(|e| view! { <span class="text-red-400">{e}</span> })}
+        </div>
+    }
+}
+
+/// "★★★☆☆" for 3 stars.
+fn stars(rating: u8) -> String {
+    "★".repeat(rating.into()) + &"☆".repeat(5usize.saturating_sub(rating.into()))
+}
+
+/// Saving, rating, logging when it was cooked and notes for one recipe. All but saving need the
+/// recipe in the book, so they save it first when it isn't yet.
+#[component]
+fn RecipeActions(
+    recipe: recipe::Recipe,
+    /// What the book already has on it.
+    #[prop(optional)]
+    saved: Option<SavedRecipe>,
+    /// The `Generation::id` of the answer it's from, to keep its prompt template with it.
+    #[prop(optional, into)]
+    generation: MaybeSignal<Option<Uuid>>,
+) -> impl IntoView {
+    let recipe = store_value(recipe);
+    let generation = store_value(generation);
+    let id = create_rw_signal(saved.as_ref().map(|s| s.id));
+    let rating = create_rw_signal(saved.as_ref().and_then(|s| s.rating));
+    let cooked = create_rw_signal(saved.as_ref().map(|s| s.cooked.clone()).unwrap_or_default());
+    let notes = create_rw_signal(saved.map(|s| s.notes).unwrap_or_default());
+    let (pending, set_pending) = create_signal(false);
+    let (error, set_error) = create_signal(None::<String>);
+
+    let saved_id = move || async move {
+        match id.get_untracked() {
+            Some(id) => Ok(id),
+            None => {
+                let saved = save_recipe(recipe.get_value(), generation.with_value(|g| g.get_untracked())).await?;
+                id.set(Some(saved.id));
+                Ok::<_, ServerFnError>(saved.id)
+            }
+        }
+    };
+    let done = move |result: Result<(), ServerFnError>| {
+        set_pending(false);
+        set_error(result.err().map(|e| error_message(&e)));
+    };
+
+    let on_save = move |_| {
+        set_pending(true);
+        spawn_local(async move { done(saved_id().await.map(|_| ())) });
+    };
+    let on_rate = move |stars: u8| {
+        // picking the current rating again takes it back
+        let stars = Some(stars).filter(|s| rating.get_untracked() != Some(*s));
+        set_pending(true);
+        spawn_local(async move {
+            let result = match saved_id().await {
+                Ok(id) => rate_recipe(id, stars).await.map(|_| rating.set(stars)),
+                Err(e) => Err(e),
+            };
+            done(result);
+        });
+    };
+    let on_cooked = move |_| {
+        set_pending(true);
+        spawn_local(async move {
+            let result = match saved_id().await {
+                Ok(id) => log_cooked(id).await.map(|at| cooked.update(|c| c.push(at))),
+                Err(e) => Err(e),
+            };
+            done(result);
+        });
+    };
+    let on_notes = move |ev| {
+        let text = event_target_value(&ev);
+        set_pending(true);
+        spawn_local(async move {
+            let result = match saved_id().await {
+                Ok(id) => set_recipe_notes(id, text.clone()).await.map(|_| notes.set(text)),
+                Err(e) => Err(e),
+            };
+            done(result);
+        });
+    };
+
+    let button_class = "px-2 py-1 rounded-lg bg-gray-200 dark:bg-gray-700 hover:bg-gray-300 dark:hover:bg-gray-600 disabled:opacity-50";
+
+    view! {
+        <div class="mt-2 mb-4 flex flex-col gap-2 text-sm text-gray-500 dark:text-gray-400">
+            <div class="flex flex-row flex-wrap items-center gap-3">
+                {move || match id() {
+                    Some(id) => view! {
+                        <A href=format!("/book/{}", id) class="text-blue-600 dark:text-blue-500 hover:underline">"In the book"</A>
+                    }.into_view(),
+                    None => view! {
+                        <button type="button" class=button_class disabled=pending on:click=on_save>"Save to book"</button>
+                    }.into_view(),
+                }}
+                <div class="flex flex-row" title="Rating">
+                    {(1..=5u8).map(|n| view! {
+                        <button
+                            type="button"
+                            class="text-lg leading-none disabled:opacity-50"
+                            class:text-yellow-400=move || rating().is_some_and(|r| n <= r)
+                            disabled=pending
+                            on:click=move |_| on_rate(n)
+                        >
+                            {move || if rating().is_some_and(|r| n <= r) { "★" } else { "☆" }}
+                            <span class="sr-only">{format!("{} stars", n)}</span>
+                        </button>
+                    }).collect_view()}
+                </div>
+                <button type="button" class=button_class disabled=pending on:click=on_cooked>"Cooked it"</button>
+                {move || cooked.with(|c| (!c.is_empty()).then(|| {
+                    format!("Cooked on {}", c.iter().map(|at| book::date(*at)).collect::<Vec<_>>().join(", "))
+                }))}
+            </div>
+            <textarea
+                class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg block w-full p-2 dark:bg-gray-700 dark:border-gray-600 dark:placeholder-gray-400 dark:text-white"
+                rows="2"
+                placeholder="Notes"
+                prop:value=notes
+                on:change=on_notes
+            ></textarea>
+            {move || error().map(|e| view! { <p class="text-red-400">{e}</p> })}
+        </div>
+    }
+}
+
+/// Reads a saved recipe page in the browser and has the server pull the recipes out of it.
+#[component]
+fn RecipeImport(import: Action<ImportRecipes, Result<Vec<SavedRecipe>, ServerFnError>>) -> impl IntoView {
+    let (read_error, set_read_error) = create_signal(None::<String>);
+
+    let on_change = move |ev: ev::Event| {
+        let input = event_target::<web_sys::HtmlInputElement>(&ev);
+        let Some(file) = input.files().and_then(|files| files.get(0)) else { return };
+        input.set_value("");
+
+        spawn_local(async move {
+            match wasm_bindgen_futures::JsFuture::from(file.text()).await {
+                Ok(html) => {
+                    set_read_error(None);
+                    import.dispatch(ImportRecipes { html: html.as_string().unwrap_or_default() });
+                }
+                Err(_) => set_read_error(Some(format!("Could not read {}", file.name()))),
+            }
+        });
+    };
+
+    view! {
+        <div class="w-full p-2 bg-white border border-gray-200 rounded-lg shadow md:p-4 dark:bg-gray-800 dark:border-gray-700 text-white">
+            <label class="block mb-2 text-sm font-medium text-gray-900 dark:text-white" for="import-file">
+                "Import a saved recipe page"
+            </label>
+            <input
+                id="import-file"
+                type="file"
+                accept=".html,.htm,text/html"
+                class="block w-full text-sm text-gray-900 border border-gray-300 rounded-lg cursor-pointer bg-gray-50 dark:text-gray-400 dark:bg-gray-700 dark:border-gray-600"
+                disabled=import.pending()
+                on:change=on_change
+            />
+            {move || import.pending()().then(|| view! { <SpinnerIcon /> })}
+            {move || read_error().map(|e| view! { <p class="text-red-400">{e}</p> })}
+            {move || match import.value()() {
+                Some(Err(e)) => Some(view! { <p class="text-red-400">{e.to_string()}</p> }.into_view()),
+                Some(Ok(recipes)) => Some(view! {
+                    <p class="text-sm text-green-400">
+                        {format!("Saved {}", recipes.iter().map(|r| recipe::fragment_text(&r.recipe.name)).collect::<Vec<_>>().join(", "))}
+                    </p>
+                }.into_view()),
+                None => None,
+            }}
+        </div>
+    }
 }
 
 #[component]
@@ -146,29 +793,146 @@ fn SpinnerIcon() -> impl IntoView {
 }
 
 
+/// Moves what was kept in the browser before logging in into the account, the way `UserBook`
+/// does with the book. The pantry and profile are merged with the ones saved with it, and the
+/// recipe options and units kept unless the account has its own.
+#[component]
+fn GuestData(user: User) -> impl IntoView {
+    let (guest_pantry, _, forget_pantry) = use_local_storage::<Vec<Ingredient>, JsonCodec>(PANTRY_KEY);
+    let (guest_profile, _, forget_profile) = use_local_storage::<DietaryProfile, JsonCodec>(DIETARY_PROFILE_KEY);
+    let (guest_options, _, forget_options) = use_local_storage::<RecipeOptions, JsonCodec>(RECIPE_OPTIONS_KEY);
+    let (_, set_pantry, _) = use_local_storage::<Vec<Ingredient>, JsonCodec>(storage_key(PANTRY_KEY, Some(&user)));
+    let (_, set_profile, _) = use_local_storage::<DietaryProfile, JsonCodec>(storage_key(DIETARY_PROFILE_KEY, Some(&user)));
+    let (options, set_options, _) = use_local_storage::<RecipeOptions, JsonCodec>(storage_key(RECIPE_OPTIONS_KEY, Some(&user)));
+    let (guest_units, _, forget_units) = use_local_storage::<Option<UnitSystem>, JsonCodec>(UNIT_SYSTEM_KEY);
+    let (_, set_units, _) = use_local_storage::<Option<UnitSystem>, JsonCodec>(storage_key(UNIT_SYSTEM_KEY, Some(&user)));
+
+    // the guest copies are only forgotten once the server has them
+    create_effect(move |_| {
+        let guest = pantry::in_stock(&guest_pantry());
+        if guest.is_empty() {
+            return;
+        }
+        let forget_pantry = forget_pantry.clone();
+        spawn_local(async move {
+            match sync_pantry(guest).await {
+                Ok(saved) => {
+                    set_pantry.update(|c| *c = pantry::merge(c, &saved));
+                    forget_pantry();
+                }
+                Err(e) => log!("could not move the pantry to the account: {}", e),
+            }
+        });
+    });
+    create_effect(move |_| {
+        let guest = guest_profile();
+        if guest.is_empty() {
+            return;
+        }
+        let forget_profile = forget_profile.clone();
+        spawn_local(async move {
+            let merged = async {
+                let mut profile = get_dietary_profile().await?.unwrap_or_default();
+                profile.merge(&guest);
+                set_dietary_profile(profile.clone()).await?;
+                Ok::<_, ServerFnError>(profile)
+            };
+            match merged.await {
+                Ok(profile) => {
+                    set_profile(profile);
+                    forget_profile();
+                }
+                Err(e) => log!("could not move the dietary profile to the account: {}", e),
+            }
+        });
+    });
+    create_effect(move |_| {
+        let guest = guest_options();
+        if guest == RecipeOptions::default() {
+            return;
+        }
+        if options.get_untracked() == RecipeOptions::default() {
+            set_options(guest);
+        }
+        forget_options();
+    });
+    create_effect(move |_| {
+        let Some(guest) = guest_units() else { return };
+        let forget_units = forget_units.clone();
+        spawn_local(async move {
+            let kept = async {
+                let units = match get_unit_system().await? {
+                    Some(units) => units,
+                    None => {
+                        set_unit_system(Some(guest)).await?;
+                        guest
+                    }
+                };
+                Ok::<_, ServerFnError>(units)
+            };
+            match kept.await {
+                Ok(units) => {
+                    set_units(Some(units));
+                    forget_units();
+                }
+                Err(e) => log!("could not move the units to the account: {}", e),
+            }
+        });
+    });
+}
+
 #[component]
-fn Pantry() -> impl IntoView {
-    let (ingredients, set_ingredients, _) = use_local_storage::<Vec<Ingredient>, JsonCodec>("ingredients");
+fn Pantry(user: Option<User>) -> impl IntoView {
+    // with an account this is a copy of the pantry saved with it, for when the server can't be reached
+    let (cache, set_cache, _) = use_local_storage::<Vec<Ingredient>, JsonCodec>(storage_key(PANTRY_KEY, user.as_ref()));
+    let ingredients = Signal::derive(move || cache.with(|c| pantry::in_stock(c)));
+    let (profile, _, _) = use_local_storage::<DietaryProfile, JsonCodec>(storage_key(DIETARY_PROFILE_KEY, user.as_ref()));
+    let (options, set_options, _) = use_local_storage::<RecipeOptions, JsonCodec>(storage_key(RECIPE_OPTIONS_KEY, user.as_ref()));
 
     log!("ingredients: {:?}", ingredients.get_untracked());
 
+    // the whole cache goes every time, so changes made offline go along with the next one. Each
+    // answer comes back with what was sent for it, as answers can overtake each other.
+    let sync = create_action(move |changes: &Vec<Ingredient>| {
+        let sent = changes.clone();
+        async move { sync_pantry(sent.clone()).await.map(|saved| (sent, saved)) }
+    });
+    let signed_in = user.is_some();
+    let send_changes = move || if signed_in {
+        sync.dispatch(cache.get_untracked());
+    };
+    create_effect(move |_| {
+        // the answer is the server's word on what was sent, but not on what changed here since
+        if let Some(Ok((sent, saved))) = sync.value().get() {
+            set_cache.update(|c| *c = pantry::synced(c, &sent, &saved));
+        }
+    });
+    // effects only run in the browser, and this one only once
+    create_effect(move |_| send_changes());
+    let online = window_event_listener(ev::online, move |_| send_changes());
+    on_cleanup(move || online.remove());
+
     let on_ingredient_add = move |i: Ingredient| {
-        set_ingredients.update(|data| data.push(i));
+        set_cache.update(|data| data.push(i));
+        send_changes();
     };
 
+    let on_ingredient_remove = move |id: Uuid| {
+        set_cache.update(|data| pantry::remove(data, id, now_millis()));
+        send_changes();
+    };
 
-    let get_recipes = expect_context::<GetRecipesCtx>().0;
+
+    let recipes_ctx = expect_context::<RecipesCtx>();
 
     let handle_ingredients_submit = move |ev: MouseEvent| {
         ev.prevent_default();
 
         log!("handle_ingredients_submit running");
 
-        get_recipes.dispatch(GenerateRecipes { ingredients: ingredients() });
+        recipes_ctx.generate(&RecipeRequest { ingredients: ingredients(), profile: profile(), options: options() });
     };
 
-    provide_context(set_ingredients);
-
     view! {
         <div class="w-full flex flex-col">
             <div class="w-full p-2 bg-white border border-gray-200 rounded-lg shadow md:p-4 dark:bg-gray-800 dark:border-gray-700 text-white">
@@ -180,33 +944,99 @@ fn Pantry() -> impl IntoView {
                                 when=move || { ingredients.with(|ings| !ings.is_empty()) }
                                 fallback=|| view! { <p class="my-5 text-gray-300">"There seems to be nothing here..."</p> }
                             >
-                                <IngredientList ingredients=ingredients />
+                                <IngredientList ingredients=ingredients on_remove=Callback::new(on_ingredient_remove) />
                             </Show>
+                            {move || matches!(sync.value().get(), Some(Err(_))).then(|| view! {
+                                <p class="text-sm text-yellow-400">"Offline, the pantry is kept on this device until the server can be reached"</p>
+                            })}
                         </ClientOnly>
                     </div>
 
-                    <IngredientInput on_add=on_ingredient_add />
+                    <IngredientInput on_add=Callback::new(on_ingredient_add) />
                 </div>
 
+                <ClientOnly>
+                    <RecipeOptionsInput options=options set_options=set_options />
+                </ClientOnly>
+
             </div>
-            <Button loading={get_recipes.pending().into()} class="mt-2".to_owned() on:click=handle_ingredients_submit >"Mix it together"</Button>
+            <Button loading={Signal::from(recipes_ctx.pending).into()} class="mt-2".to_owned() on:click=handle_ingredients_submit >"Mix it together"</Button>
         </div>
     }
 }
 
 #[component]
-fn IngredientItem(
-    ingredient: Ingredient
+fn RecipeOptionsInput(
+    options: Signal<RecipeOptions>,
+    set_options: WriteSignal<RecipeOptions>,
 ) -> impl IntoView {
+    let number = |ev| event_target_value(&ev).trim().parse::<u32>().ok().filter(|n| *n > 0);
+    let shown = |n: Option<u32>| n.map(|n| n.to_string()).unwrap_or_default();
+    let input_class = "bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 block w-full p-2 dark:bg-gray-600 dark:border-gray-500 dark:placeholder-gray-400 dark:text-white";
+    let label_class = "text-sm text-gray-900 dark:text-gray-300";
 
-    let ingredient_setter = use_context::<WriteSignal<Vec<Ingredient>>>();
+    view! {
+        <div class="grid grid-cols-2 gap-2 mt-4">
+            <label class=label_class>
+                "Cuisine"
+                <input
+                    type="text"
+                    class=input_class
+                    placeholder="any"
+                    prop:value=move || options.with(|o| o.cuisine.clone())
+                    on:change=move |ev| set_options.update(|o| o.cuisine = event_target_value(&ev).trim().to_owned())
+                />
+            </label>
+            <label class=label_class>
+                "Minutes to cook"
+                <input
+                    type="number"
+                    min="1"
+                    max=MAX_TIME_BUDGET
+                    class=input_class
+                    placeholder="any"
+                    prop:value=move || shown(options.with(|o| o.time_budget))
+                    on:change=move |ev| set_options.update(|o| o.time_budget = number(ev))
+                />
+            </label>
+            <label class=label_class>
+                "Servings"
+                <input
+                    type="number"
+                    min="1"
+                    max=MAX_SERVINGS
+                    class=input_class
+                    placeholder="any"
+                    prop:value=move || shown(options.with(|o| o.servings))
+                    on:change=move |ev| set_options.update(|o| o.servings = number(ev))
+                />
+            </label>
+            <label class=label_class>
+                "Recipes"
+                <input
+                    type="number"
+                    min="1"
+                    max=MAX_RECIPES
+                    class=input_class
+                    placeholder="3"
+                    prop:value=move || shown(options.with(|o| o.count))
+                    on:change=move |ev| set_options.update(|o| o.count = number(ev))
+                />
+            </label>
+        </div>
+    }
+}
+
+#[component]
+fn IngredientItem(
+    ingredient: Ingredient,
+    #[prop(into)] on_remove: Callback<Uuid>,
+) -> impl IntoView {
 
     let handle_delete = move |ev: MouseEvent| {
         ev.prevent_default();
 
-        if let Some(setter) = ingredient_setter {
-            setter.update(|ings|  ings.retain(|i| i.id != ingredient.id));
-        }
+        on_remove(ingredient.id);
     };
 
     view! {
@@ -220,7 +1050,7 @@ fn IngredientItem(
                         {ingredient.name}
                     </p>
                     <p class="text-sm text-gray-500 truncate dark:text-gray-400">
-                        {ingredient.quantity}
+                        {ingredient.quantity.map(|q| q.to_string())}
                     </p>
                 </div>
                 <span class="inline-flex items-center bg-green-100 text-green-800 text-xs font-medium px-2.5 py-0.5 rounded-full dark:bg-green-900 dark:text-green-300">
@@ -237,16 +1067,16 @@ fn IngredientItem(
 }
 
 #[component]
-fn IngredientList(ingredients: Signal<Vec<Ingredient>>) -> impl IntoView {
+fn IngredientList(ingredients: Signal<Vec<Ingredient>>, #[prop(into)] on_remove: Callback<Uuid>) -> impl IntoView {
     view! {
 
         <ul role="list" class="w-full divide-y divide-gray-200 dark:divide-gray-700" >
             <For
                 each=ingredients
-                key=|i| i.id.clone()
+                key=|i| i.id
                 let:child
             >
-            <IngredientItem ingredient=child />
+            <IngredientItem ingredient=child on_remove=on_remove />
         </For>
         </ul>
     }
@@ -263,7 +1093,9 @@ fn IngredientInput(#[prop(into)] on_add: Callback<Ingredient>) -> impl IntoView
         let input = input_el().expect("<input> to exist");
         let value = input.value();
 
-        on_add(Ingredient {id: Uuid::new_v4(), name: value.trim().to_owned(), quantity: None, certainty: None });
+        let parsed = recipe::parse_ingredient(&value);
+
+        on_add(Ingredient::new(parsed.name, parsed.quantity, now_millis()));
 
         input.set_value("");
     };
@@ -288,6 +1120,9 @@ fn IngredientInput(#[prop(into)] on_add: Callback<Ingredient>) -> impl IntoView
 
 #[component]
 fn Navbar() -> impl IntoView {
+    let UserCtx { logout, .. } = expect_context::<UserCtx>();
+    let (menu_open, set_menu_open) = create_signal(false);
+
     view! {
         <nav class="bg-white border-gray-200 dark:bg-gray-900">
             <div class="max-w-screen-xl flex flex-wrap items-center justify-between mx-auto p-4">
@@ -299,57 +1134,71 @@ fn Navbar() -> impl IntoView {
                     </span>
                 </a>
                 <div class="flex items-center md:order-2 space-x-3 md:space-x-0 rtl:space-x-reverse">
-                    // TODO(filip): make dropwodn menu work
-                    <button
-                        type="button"
-                        class="flex text-sm bg-gray-800 rounded-full md:me-0 focus:ring-4 focus:ring-gray-300 dark:focus:ring-gray-600"
-                        id="user-menu-button"
-                        aria-expanded="false"
-                        data-dropdown-toggle="user-dropdown"
-                        data-dropdown-placement="bottom"
-                    >
-                        <span class="sr-only">"Open user menu"</span>
-                        // <img class="w-8 h-8 rounded-full" src="" alt="user photo"/>
-                        <svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 200 200" class="w-8 h-8 rounded-full">
-                          <circle cx="100" cy="70" r="50" fill="none" stroke="#f8f8f8" stroke-width="2" />
-                          <path d="M50,70 L100,20 L150,70 Z" fill="#f8f8f8" stroke="#f8f8f8" stroke-width="2" />
-                          <circle cx="80" cy="60" r="5" fill="black" />
-                          <circle cx="120" cy="60" r="5" fill="black" />
-                          <path d="M80,80 Q100,90 120,80" fill="#f8f8f8" stroke="#f8f8f8" />
-                          <rect x="95" y="120" width="10" height="20" fill="#f8f8f8" stroke="#f8f8f8" />
-                        </svg>
-                    </button>
-                    // Dropdown menu
-                    <div
-                        class="z-50 hidden my-4 text-base list-none bg-white divide-y divide-gray-100 rounded-lg shadow dark:bg-gray-700 dark:divide-gray-600"
-                        id="user-dropdown"
-                    >
-                        <div class="px-4 py-3">
-                            <span class="block text-sm text-gray-900 dark:text-white">
-                                "Bonnie Green"
-                            </span>
-                            <span class="block text-sm  text-gray-500 truncate dark:text-gray-400">
-                                "name@flowbite.com"
-                            </span>
+                    <div class="relative">
+                        <button
+                            type="button"
+                            class="flex text-sm bg-gray-800 rounded-full md:me-0 focus:ring-4 focus:ring-gray-300 dark:focus:ring-gray-600"
+                            id="user-menu-button"
+                            aria-expanded=move || menu_open().to_string()
+                            on:click=move |_| set_menu_open.update(|open| *open = !*open)
+                        >
+                            <span class="sr-only">"Open user menu"</span>
+                            // <img class="w-8 h-8 rounded-full" src="" alt="user photo"/>
+                            <svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 200 200" class="w-8 h-8 rounded-full">
+                              <circle cx="100" cy="70" r="50" fill="none" stroke="#f8f8f8" stroke-width="2" />
+                              <path d="M50,70 L100,20 L150,70 Z" fill="#f8f8f8" stroke="#f8f8f8" stroke-width="2" />
+                              <circle cx="80" cy="60" r="5" fill="black" />
+                              <circle cx="120" cy="60" r="5" fill="black" />
+                              <path d="M80,80 Q100,90 120,80" fill="#f8f8f8" stroke="#f8f8f8" />
+                              <rect x="95" y="120" width="10" height="20" fill="#f8f8f8" stroke="#f8f8f8" />
+                            </svg>
+                        </button>
+                        // Dropdown menu
+                        <div
+                            class="absolute right-0 z-50 my-4 text-base list-none bg-white divide-y divide-gray-100 rounded-lg shadow dark:bg-gray-700 dark:divide-gray-600"
+                            class:hidden=move || !menu_open()
+                            id="user-dropdown"
+                            on:click=move |_| set_menu_open(false)
+                        >
+                            {with_user(move |user| view! {
+                                {user.clone().map(|user| view! {
+                                    <div class="px-4 py-3">
+                                        <span class="block text-sm text-gray-500 truncate dark:text-gray-400">
+                                            {user.email}
+                                        </span>
+                                    </div>
+                                })}
+                                <ul class="py-2" aria-labelledby="user-menu-button">
+                                    <li>
+                                        <A
+                                            href="settings"
+                                            class="block px-4 py-2 text-sm text-gray-700 hover:bg-gray-100 dark:hover:bg-gray-600 dark:text-gray-200 dark:hover:text-white"
+                                        >
+                                            "Settings"
+                                        </A>
+                                    </li>
+                                    {match user {
+                                        Some(_) => view! {
+                                            <li>
+                                                <ActionForm action=logout>
+                                                    <button type="submit" class="w-full text-left block px-4 py-2 text-sm text-gray-700 hover:bg-gray-100 dark:hover:bg-gray-600 dark:text-gray-200 dark:hover:text-white">
+                                                        "Sign out"
+                                                    </button>
+                                                </ActionForm>
+                                            </li>
+                                        }.into_view(),
+                                        None => view! {
+                                            <li>
+                                                <A href="login" class="block px-4 py-2 text-sm text-gray-700 hover:bg-gray-100 dark:hover:bg-gray-600 dark:text-gray-200 dark:hover:text-white">"Log in"</A>
+                                            </li>
+                                            <li>
+                                                <A href="register" class="block px-4 py-2 text-sm text-gray-700 hover:bg-gray-100 dark:hover:bg-gray-600 dark:text-gray-200 dark:hover:text-white">"Register"</A>
+                                            </li>
+                                        }.into_view(),
+                                    }}
+                                </ul>
+                            })}
                         </div>
-                        <ul class="py-2" aria-labelledby="user-menu-button">
-                            <li>
-                                <a
-                                    href="#"
-                                    class="block px-4 py-2 text-sm text-gray-700 hover:bg-gray-100 dark:hover:bg-gray-600 dark:text-gray-200 dark:hover:text-white"
-                                >
-                                    "Settings"
-                                </a>
-                            </li>
-                            <li>
-                                <a
-                                    href="#"
-                                    class="block px-4 py-2 text-sm text-gray-700 hover:bg-gray-100 dark:hover:bg-gray-600 dark:text-gray-200 dark:hover:text-white"
-                                >
-                                    "Sign out"
-                                </a>
-                            </li>
-                        </ul>
                     </div>
                     <button
                         data-collapse-toggle="navbar-user"
@@ -397,6 +1246,14 @@ fn Navbar() -> impl IntoView {
                                 "Book"
                             </A>
                         </li>
+                        <li>
+                            <A
+                                href="settings"
+                                class="block py-2 px-3 rounded aria-current:text-white aria-current:bg-blue-700 aria-current:md:bg-transparent aria-current:md:text-blue-700 aria-current:md:dark:text-blue-500 text-gray-900 md:p-0 hover:bg-gray-100 md:hover:bg-transparent md:hover:text-blue-700 dark:text-white md:dark:hover:text-blue-500 dark:hover:bg-gray-700 dark:hover:text-white md:dark:hover:bg-transparent dark:border-gray-700"
+                            >
+                                "Settings"
+                            </A>
+                        </li>
                     </ul>
                 </div>
             </div>
@@ -404,6 +1261,207 @@ fn Navbar() -> impl IntoView {
     }
 }
 
+#[component]
+fn Settings() -> impl IntoView {
+    with_user(|user| view! { <DietaryProfileForm user=user /> })
+}
+
+#[component]
+fn DietaryProfileForm(user: Option<User>) -> impl IntoView {
+    let (profile, set_profile, _) = use_local_storage::<DietaryProfile, JsonCodec>(storage_key(DIETARY_PROFILE_KEY, user.as_ref()));
+
+    // with an account the profile is kept on the server, and the browser only has a copy of it
+    let logged_in = user.is_some();
+    let saved = create_resource(|| (), move |_| async move {
+        match logged_in {
+            true => get_dietary_profile().await,
+            false => Ok(None),
+        }
+    });
+    create_effect(move |_| {
+        if let Some(Ok(Some(p))) = saved() {
+            set_profile(p);
+        }
+    });
+    let save = create_server_action::<SetDietaryProfile>();
+    let update = move |change: &dyn Fn(&mut DietaryProfile)| {
+        set_profile.update(|p| change(p));
+        if logged_in {
+            save.dispatch(SetDietaryProfile { profile: profile.get_untracked() });
+        }
+    };
+
+    let on_dislikes_change = move |ev| {
+        let dislikes: Vec<String> = event_target_value(&ev).split(',').map(|d| d.trim().to_owned()).filter(|d| !d.is_empty()).collect();
+        update(&|p| p.dislikes = dislikes.clone());
+    };
+
+    view! {
+        <div class="mt-20 flex flex-col gap-2 px-2 md:px-5 lg:px-12 md:w-3/5 mx-auto" >
+            <div class="w-full p-2 bg-white border border-gray-200 rounded-lg shadow md:p-4 dark:bg-gray-800 dark:border-gray-700 text-white">
+                <h5 class="text-xl font-medium text-gray-900 dark:text-white">"Dietary profile"</h5>
+                <ClientOnly>
+                    <fieldset class="mt-4">
+                        <legend class="text-sm font-medium text-gray-900 dark:text-white">"Allergies and intolerances"</legend>
+                        <div class="grid grid-cols-2 md:grid-cols-3 gap-1 mt-1">
+                            {Allergen::ALL.into_iter().map(|a| view! {
+                                <label class="flex items-center gap-2 text-sm capitalize text-gray-900 dark:text-gray-300">
+                                    <input
+                                        type="checkbox"
+                                        prop:checked=move || profile.with(|p| p.allergens.contains(&a))
+                                        on:change=move |_| update(&|p| DietaryProfile::toggle(&mut p.allergens, a))
+                                    />
+                                    {a.to_string()}
+                                </label>
+                            }).collect_view()}
+                        </div>
+                    </fieldset>
+                    <fieldset class="mt-4">
+                        <legend class="text-sm font-medium text-gray-900 dark:text-white">"Diets"</legend>
+                        <div class="grid grid-cols-2 md:grid-cols-3 gap-1 mt-1">
+                            {Diet::ALL.into_iter().map(|d| view! {
+                                <label class="flex items-center gap-2 text-sm capitalize text-gray-900 dark:text-gray-300">
+                                    <input
+                                        type="checkbox"
+                                        prop:checked=move || profile.with(|p| p.diets.contains(&d))
+                                        on:change=move |_| update(&|p| DietaryProfile::toggle(&mut p.diets, d))
+                                    />
+                                    {d.to_string()}
+                                </label>
+                            }).collect_view()}
+                        </div>
+                    </fieldset>
+                    <label class="block mt-4 mb-1 text-sm font-medium text-gray-900 dark:text-white" for="dislikes">
+                        "Ingredients you don't like"
+                    </label>
+                    <input
+                        type="text"
+                        id="dislikes"
+                        class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 block w-full p-2.5 dark:bg-gray-600 dark:border-gray-500 dark:placeholder-gray-400 dark:text-white"
+                        placeholder="olives, coriander"
+                        prop:value=move || profile.with(|p| p.dislikes.join(", "))
+                        on:change=on_dislikes_change
+                    />
+                    {move || save.value()().and_then(Result::err).map(|e| view! {
+                        <p class="mt-2 text-sm text-red-400">{error_message(&e)}</p>
+                    })}
+                    {(!logged_in).then(|| view! {
+                        <p class="mt-2 text-sm text-gray-500 dark:text-gray-400">
+                            "This profile is only kept in this browser. "
+                            <A href="/login" class="text-blue-600 dark:text-blue-500 hover:underline">"Log in"</A>
+                            " to keep it with your account."
+                        </p>
+                    })}
+                    <p class="mt-4 text-sm text-gray-500 dark:text-gray-400">
+                        {move || profile.with(|p| match p.is_empty() {
+                            true => "Recipes can use anything.".to_owned(),
+                            false => p.to_string(),
+                        })}
+                    </p>
+                </ClientOnly>
+            </div>
+        </div>
+    }
+}
+
+#[component]
+fn LoginPage() -> impl IntoView {
+    let UserCtx { login, .. } = expect_context::<UserCtx>();
+    go_to_lab_after(login);
+
+    view! {
+        <Title text="Log in"/>
+        <AccountPage title="Log in" error=Signal::derive(move || login.value()().and_then(Result::err))>
+            <ActionForm action=login class="flex flex-col gap-3">
+                <AccountFields new_password=false />
+                <SubmitButton pending=login.pending()>"Log in"</SubmitButton>
+            </ActionForm>
+            <p class="text-sm text-gray-500 dark:text-gray-400">
+                "No account yet? "
+                <A href="/register" class="text-blue-600 dark:text-blue-500 hover:underline">"Register"</A>
+            </p>
+        </AccountPage>
+    }
+}
+
+#[component]
+fn RegisterPage() -> impl IntoView {
+    let UserCtx { register, .. } = expect_context::<UserCtx>();
+    go_to_lab_after(register);
+
+    view! {
+        <Title text="Register"/>
+        <AccountPage title="Register" error=Signal::derive(move || register.value()().and_then(Result::err))>
+            <ActionForm action=register class="flex flex-col gap-3">
+                <AccountFields new_password=true />
+                <SubmitButton pending=register.pending()>"Register"</SubmitButton>
+            </ActionForm>
+            <p class="text-sm text-gray-500 dark:text-gray-400">
+                "Already have an account? "
+                <A href="/login" class="text-blue-600 dark:text-blue-500 hover:underline">"Log in"</A>
+            </p>
+        </AccountPage>
+    }
+}
+
+fn go_to_lab_after<I: 'static>(action: Action<I, Result<User, ServerFnError>>) {
+    create_effect(move |_| {
+        if let Some(Ok(_)) = action.value()() {
+            use_navigate()("/lab", Default::default());
+        }
+    });
+}
+
+#[component]
+fn AccountPage(title: &'static str, error: Signal<Option<ServerFnError>>, children: Children) -> impl IntoView {
+    view! {
+        <div class="mt-20 flex flex-col gap-2 px-2 md:px-5 lg:px-12 md:w-2/5 mx-auto" >
+            <div class="w-full p-2 bg-white border border-gray-200 rounded-lg shadow md:p-4 dark:bg-gray-800 dark:border-gray-700 flex flex-col gap-3">
+                <h5 class="text-xl font-medium text-gray-900 dark:text-white">{title}</h5>
+                {children()}
+                {move || error().map(|e| view! { <p class="text-sm text-red-400">{error_message(&e)}</p> })}
+            </div>
+        </div>
+    }
+}
+
+/// The `email` and `password` inputs the account server functions take.
+#[component]
+fn AccountFields(new_password: bool) -> impl IntoView {
+    let input_class = "bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg focus:ring-blue-500 focus:border-blue-500 block w-full p-2.5 dark:bg-gray-600 dark:border-gray-500 dark:placeholder-gray-400 dark:text-white";
+
+    view! {
+        <label class="block text-sm font-medium text-gray-900 dark:text-white">
+            "Email"
+            <input type="email" name="email" class=input_class autocomplete="email" required />
+        </label>
+        <label class="block text-sm font-medium text-gray-900 dark:text-white">
+            "Password"
+            <input
+                type="password"
+                name="password"
+                class=input_class
+                autocomplete=if new_password { "new-password" } else { "current-password" }
+                minlength=if new_password { crate::user::MIN_PASSWORD_LENGTH } else { 1 }
+                required
+            />
+        </label>
+    }
+}
+
+#[component]
+fn SubmitButton(pending: ReadSignal<bool>, children: Children) -> impl IntoView {
+    view! {
+        <button
+            type="submit"
+            class="text-white bg-blue-700 hover:bg-blue-800 focus:ring-4 focus:outline-none focus:ring-blue-300 font-medium rounded-lg text-sm px-5 py-2.5 text-center dark:bg-blue-600 dark:hover:bg-blue-700 dark:focus:ring-blue-800 disabled:opacity-50"
+            disabled=pending
+        >
+            {children()}
+        </button>
+    }
+}
+
 #[component]
 fn AddButton(
     #[prop(default = 5)]
@@ -433,16 +1491,10 @@ fn AddButton(
 
 #[component]
 fn DeleteButton(
-    #[prop(default = 5)]
-    w: u16,
-    #[prop(default = 5)]
-    h: u16,
     #[prop(default = "button".to_owned())]
     btn_type: String
 ) -> impl IntoView {
 
-    let size_class = "w-".to_owned()+&w.to_string()+" h-"+&h.to_string();
-
     view! {
         <button type={btn_type} class="text-white bg-red-700 hover:bg-red-800 focus:ring-4 focus:outline-none focus:ring-red-300 font-medium rounded-lg text-sm p-1.5 text-center inline-flex items-center dark:bg-red-600 dark:hover:bg-red-700 dark:focus:ring-red-900" >
             <svg class="w-3.5 h-3.5" xmlns="http://www.w3.org/2000/svg" fill="currentColor" viewBox="0 0 16 16">
@@ -477,70 +1529,189 @@ fn CookieLogo() -> impl IntoView {
 
 
 #[component]
-fn RecipeList(
-    // recipes: ReadSignal<Vec<RecipeItem>>,
-) -> impl IntoView {
-    // TODO(filip): handle saving recipes
-    // TODO(filip): handle rating recipes
+fn RecipeList(user: Option<User>) -> impl IntoView {
+    let RecipesCtx { report: recipes, template, generation, .. } = expect_context::<RecipesCtx>();
+
+    let (pantry, _, _) = use_local_storage::<Vec<Ingredient>, JsonCodec>(storage_key(PANTRY_KEY, user.as_ref()));
+    let (unit_system, set_unit_system) = use_unit_system(user.as_ref());
+
+    let failed = create_memo(move |_| recipes.with(|r| match r {
+        Some(Err(e)) => Some(e.clone()),
+        _ => None,
+    }));
+    let warnings = create_memo(move |_| recipes.with(|r| match r {
+        Some(Ok(report)) => report.warnings.iter().map(|w| w.to_string()).collect(),
+        _ => vec![],
+    }));
+    let shown = create_memo(move |_| recipes.with(|r| match r {
+        Some(Ok(report)) => report.recipes.clone(),
+        _ => vec![],
+    }));
 
+    view! {
 
-    let (recipes, set_recipes) = create_signal(vec![RecipeItem { id: Uuid::new_v4(), text: "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Sed ultricies sed dui id mattis. Vivamus viverra consectetur mi, sit amet tincidunt diam facilisis et. Fusce id diam quis ex placerat maximus non ut nisi. Donec bibendum aliquet eros et hendrerit. Etiam scelerisque, ante ac hendrerit sollicitudin, sem orci ullamcorper erat, quis vestibulum leo ex et urna. Phasellus vulputate condimentum nisl ut elementum. Mauris vitae lacinia dolor. Aliquam risus nibh, iaculis id ultricies non, ultrices vitae arcu. Sed consequat maximus ultricies. Vivamus elementum sit amet est nec gravida. Vestibulum dignissim dolor velit, id imperdiet velit sodales et. Nulla sit amet maximus lorem. Integer aliquam, leo quis fermentum hendrerit, erat turpis venenatis lectus, non vestibulum tortor est ut erat. Fusce fermentum felis tincidunt, facilisis leo a, eleifend nisl. Mauris a felis at mi suscipit sagittis sed et tellus. Vestibulum ultricies orci quis odio blandit, at aliquet turpis finibus.
-
-Quisque eget tempus urna. Sed laoreet metus massa. Donec dapibus quam et aliquam lacinia. Etiam purus enim, ultrices in augue a, dignissim condimentum lacus. Morbi pulvinar tempor arcu, sed mollis nisl rhoncus et. Donec fermentum at enim ut efficitur. Proin id pharetra lorem. Quisque vel massa sapien. In metus diam, suscipit sed quam a, accumsan interdum lorem. Praesent efficitur justo eget lacinia varius. Mauris tellus mi, cursus ac vehicula et, sagittis vel tellus. Curabitur imperdiet enim suscipit ullamcorper tristique. Integer ullamcorper erat quis dolor consectetur, at mollis tellus gravida. Sed venenatis leo dui, et tempor massa semper et. Proin posuere mollis massa a porta. Nunc sem dolor, commodo at turpis a, pharetra imperdiet dolor.
-
-Maecenas pharetra diam et nulla accumsan fringilla. Vestibulum ut urna mauris. Vivamus eu sem dui. Duis placerat mi rhoncus ante rhoncus, id lacinia odio egestas. Mauris interdum posuere felis, et aliquet nisl tincidunt non. Curabitur at porttitor quam. Nulla at felis a dolor pharetra feugiat. Donec rhoncus risus neque, et rhoncus dolor imperdiet ac".to_owned() }]);
-
-
-    let get_recipes = expect_context::<GetRecipesCtx>().0;
-
-    let recipes = get_recipes.value();
+        <div class="w-full p-2 text-white bg-white border border-gray-200 rounded-lg shadow md:p-4 dark:bg-gray-800 dark:border-gray-700">
+            <div class="flex flex-row justify-end">
+                <UnitSystemSelect unit_system=unit_system set_unit_system=set_unit_system />
+            </div>
+            {move || failed().map(|e| view! { <p class="text-red-400">{e}</p> })}
+            {move || (!warnings().is_empty()).then(|| view! {
+                <p class="text-sm text-yellow-400" title=warnings().join("\n")>
+                    {format!("Parts of the answer could not be read ({} skipped)", warnings().len())}
+                </p>
+            })}
+            {move || view! { <RecipeJsonLd recipes=shown() /> }}
+            // a recipe keeps its row, and the servings picked in it, while more stream in after it
+            <For
+                each=move || shown().into_iter().enumerate()
+                key=|(i, r)| (*i, r.to_markdown())
+                children=move |(_, r)| view! {
+                    <GeneratedRecipe recipe=r pantry=pantry unit_system=unit_system generation=generation />
+                }
+            />
+            {move || template().map(|t| view! { <p class="mt-2 text-xs text-gray-500 dark:text-gray-400">"Prompt: "{t}</p> })}
+        </div>
+    }
+}
 
-    let recipe_view =  move || {
-        match recipes() {
-            Some(rrs) => rrs
-                .ok()
-                .map(|rs| rs
-                    .iter()
-                    .map(|r| view! {
-                        <div>{r.name.clone().into_view()}</div>
-                        <ul>{
-                            r.instructions
-                                .iter()
-                                .map(|i| view! {<li>{ i.clone().into_view() }</li>})
-                                .collect_view()
-                        }</ul>
-                    })
-                    .collect_view()),
-            None => None,
+/// One of the recipes in `RecipeList`, with the servings and units picked for it.
+#[component]
+fn GeneratedRecipe(
+    recipe: recipe::Recipe,
+    pantry: Signal<Vec<Ingredient>>,
+    unit_system: Signal<Option<UnitSystem>>,
+    /// Read when the recipe is saved, as it's only known once the answer is complete.
+    generation: RwSignal<Option<Uuid>>,
+) -> impl IntoView {
+    let (servings, set_servings) = create_signal(recipe.servings.unwrap_or(recipe::DEFAULT_SERVINGS));
+    let original = recipe.clone();
+    let scaled = move || {
+        let r = recipe.with_servings(servings());
+        match unit_system() {
+            Some(system) => r.to_system(system),
+            None => r,
         }
     };
 
     view! {
-
-        <div class="w-full p-2 text-white bg-white border border-gray-200 rounded-lg shadow md:p-4 dark:bg-gray-800 dark:border-gray-700">
-            {recipe_view}
-            // <For
-            //     each=recipes
-            //     key=|r| r.id
-            //     let:child
-            // >
-            //     <Recipe recipe=child />
-            // </For>
+        <div class="flex flex-row items-center justify-between gap-2">
+            <div>{let r = scaled.clone(); move || r().name.into_view()}</div>
+            <ServingsStepper servings=servings set_servings=set_servings />
         </div>
+        <ul>{
+            let r = scaled.clone();
+            move || r().ingredients
+                .iter()
+                .map(|i| {
+                    let missing = pantry.with(|p| !p.iter().any(|pi| pi.covers(i)));
+                    view! {
+                        <li class:text-red-400=missing>
+                            {i.quantity.map(|q| q.to_string() + " ")}{i.name.clone()}
+                            {missing.then_some(" (missing)")}
+                        </li>
+                    }
+                })
+                .collect_view()
+        }</ul>
+        <ul>{
+            move || scaled().instructions
+                .into_iter()
+                .map(|i| view! {<li>{ i.into_view() }</li>})
+                .collect_view()
+        }</ul>
+        <RecipeActions recipe=original generation=generation />
     }
 }
 
+/// schema.org markup for the recipes on the page, so search engines and recipe managers can read them.
 #[component]
-fn Recipe(
-    recipe: RecipeItem,
+fn RecipeJsonLd(recipes: Vec<recipe::Recipe>) -> impl IntoView {
+    (!recipes.is_empty()).then(|| view! {
+        <Script type_="application/ld+json">{recipe::SchemaRecipe::to_json_ld(&recipes)}</Script>
+    })
+}
+
+#[component]
+fn ServingsStepper(
+    servings: ReadSignal<u32>,
+    set_servings: WriteSignal<u32>,
 ) -> impl IntoView {
     view! {
-        <div class="text-white" >
-            {recipe.text}
+        <div class="inline-flex items-center gap-2 text-sm text-gray-500 dark:text-gray-400">
+            <button
+                type="button"
+                class="px-2 rounded-lg bg-gray-200 dark:bg-gray-700 disabled:opacity-50"
+                disabled=move || servings() <= 1
+                on:click=move |_| set_servings.update(|s| *s = s.saturating_sub(1).max(1))
+            >
+                "-"
+            </button>
+            <span>{servings}" servings"</span>
+            <button
+                type="button"
+                class="px-2 rounded-lg bg-gray-200 dark:bg-gray-700"
+                on:click=move |_| set_servings.update(|s| *s += 1)
+            >
+                "+"
+            </button>
         </div>
     }
 }
 
+/// The units recipes are shown in for `user`, `None` for as written. Everything showing a recipe
+/// reads it from here so they all agree. With an account the choice is kept on the server, like
+/// the dietary profile, and the browser only has a copy of it.
+fn use_unit_system(user: Option<&User>) -> (Signal<Option<UnitSystem>>, Callback<Option<UnitSystem>>) {
+    let (unit_system, set_unit_system, _) = use_local_storage::<Option<UnitSystem>, JsonCodec>(storage_key(UNIT_SYSTEM_KEY, user));
+
+    let logged_in = user.is_some();
+    let saved = create_resource(|| (), move |_| async move {
+        match logged_in {
+            true => get_unit_system().await.map(Some),
+            false => Ok(None),
+        }
+    });
+    create_effect(move |_| {
+        if let Some(Ok(Some(system))) = saved() {
+            set_unit_system(system);
+        }
+    });
+    let save = create_server_action::<SetUnitSystem>();
+    let update = Callback::new(move |system: Option<UnitSystem>| {
+        set_unit_system(system);
+        if logged_in {
+            save.dispatch(SetUnitSystem { unit_system: system });
+        }
+    });
+
+    (unit_system, update)
+}
+
+#[component]
+fn UnitSystemSelect(
+    unit_system: Signal<Option<UnitSystem>>,
+    set_unit_system: Callback<Option<UnitSystem>>,
+) -> impl IntoView {
+    let on_change = move |ev| {
+        set_unit_system(match event_target_value(&ev).as_str() {
+            "metric" => Some(UnitSystem::Metric),
+            "us" => Some(UnitSystem::UsCustomary),
+            _ => None,
+        });
+    };
+
+    view! {
+        <select
+            class="bg-gray-50 border border-gray-300 text-gray-900 text-sm rounded-lg p-1.5 dark:bg-gray-700 dark:border-gray-600 dark:text-white"
+            on:change=on_change
+        >
+            <option value="original" selected=move || unit_system().is_none()>"As written"</option>
+            <option value="metric" selected=move || unit_system() == Some(UnitSystem::Metric)>"Metric"</option>
+            <option value="us" selected=move || unit_system() == Some(UnitSystem::UsCustomary)>"US customary"</option>
+        </select>
+    }
+}
+
 #[component]
 fn ClientOnly(
     // TODO(filip): optional skeleton comp to display instead of spinner
@@ -557,100 +1728,613 @@ fn ClientOnly(
     }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-struct GptMessage {
-    role: String,
-    content: Option<String>,
+/// The prompt for `request` from the `recipes` template, and which version of the template that was.
+#[cfg(feature = "ssr")]
+fn recipe_prompt(prompts: &crate::llm::Prompts, request: &RecipeRequest, taste: &book::Taste, format: crate::llm::OutputFormat) -> Result<(String, String), String> {
+    use crate::llm::{PromptIngredient, RecipePromptVars, RECIPES_TEMPLATE};
+
+    let ingredients = request.ingredients
+        .iter()
+        .filter(|i| !i.deleted)
+        .map(|i| PromptIngredient { name: i.name.trim().to_owned(), quantity: i.quantity.map(|q| q.to_string()), certainty: i.certainty.clone() })
+        .collect();
+    let options = &request.options;
+    options.check()?;
+
+    let mut vars = RecipePromptVars::new(ingredients, &request.profile);
+    vars.cuisine = Some(options.cuisine.trim().to_owned()).filter(|c| !c.is_empty());
+    vars.time_budget = options.time_budget;
+    vars.servings = options.servings;
+    if let Some(count) = options.count {
+        vars.recipe_count = count;
+    }
+    vars.liked = taste.liked.clone();
+    vars.disliked = taste.disliked.clone();
+    vars.format = format;
+
+    prompts
+        .render(RECIPES_TEMPLATE, &vars)
+        .map(|(prompt, template)| (prompt, template.to_string()))
+        .map_err(|e| format!("Could not fill in the prompt template: {}", e))
 }
 
-impl GptMessage {
-    fn user(content: &str) -> GptMessage {
-        GptMessage { role: "user".to_owned(), content: Some(content.to_owned()) }
+/// The templates to use for this request, so edits in `PROMPT_DIR` apply without a restart.
+#[cfg(feature = "ssr")]
+fn current_prompts() -> Result<crate::llm::Prompts, String> {
+    crate::llm::Prompts::from_env().map_err(|e| format!("Could not load the prompt templates: {}", e))
+}
+
+/// What came out of parsing the answer `s`, unless nothing useful did.
+#[cfg(feature = "ssr")]
+fn checked_report(report: ParseReport, s: &str) -> Result<ParseReport, ServerFnError> {
+    for w in report.warnings.iter() {
+        log!("skipped part of the response: {}", w);
+    }
+
+    match report.warnings.first() {
+        Some(e) if report.recipes.is_empty() => {
+            log!("could not parse recipes:\n{}", s);
+            Err(ServerFnError::ServerError(format!("Could not parse recipes: {}", e)))
+        }
+        _ => Ok(report),
     }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-struct GptChatRequest {
-    model: String,
-    messages: Vec<GptMessage>,
-    temperature: f32
+/// Reads the recipes out of an answer. One asked for as JSON that isn't valid is read as
+/// Markdown instead, which is how most models answer when they ignore the request.
+#[cfg(feature = "ssr")]
+fn read_answer(s: &str, format: crate::llm::OutputFormat) -> ParseReport {
+    if format == crate::llm::OutputFormat::Json {
+        match recipe::parse_json(s) {
+            Ok(recipes) => return ParseReport { recipes, warnings: vec![] },
+            Err(e) => log!("answer is not the JSON asked for, reading it as Markdown: {}", e),
+        }
+    }
+    recipe::parse_lenient(s)
+}
+
+/// What the account behind a request knows better than the browser: the dietary profile saved
+/// with it and the ratings in its book. Recipes are better without either than not at all, so
+/// a book that can't be read only gets logged.
+#[cfg(feature = "ssr")]
+fn apply_account(db: &crate::db::Db, user: Option<&User>, request: &mut RecipeRequest) -> book::Taste {
+    let Some(user) = user else { return book::Taste::default() };
+
+    match db.preference(user.id, DIETARY_PROFILE_KEY) {
+        Ok(Some(profile)) => request.profile = profile,
+        Ok(None) => {}
+        Err(e) => log!("could not read the dietary profile: {}", e),
+    }
+    db.taste(user.id).unwrap_or_else(|e| {
+        log!("could not read the ratings: {}", e);
+        book::Taste::default()
+    })
+}
+
+/// Asks `generator` for the recipes in `request` and reads them out of its answer.
+#[cfg(feature = "ssr")]
+async fn generate_with(
+    generator: &dyn crate::llm::RecipeGenerator,
+    prompts: &crate::llm::Prompts,
+    request: &RecipeRequest,
+    taste: &book::Taste,
+    format: crate::llm::OutputFormat,
+) -> Result<Generation, ServerFnError> {
+    use crate::llm::{GptMessage, OutputFormat};
+
+    let (prompt, template) = recipe_prompt(prompts, request, taste, format).map_err(ServerFnError::ServerError)?;
+    log!("prompt from {}: {:?}", template, prompt);
+
+    let messages = [GptMessage::user(&prompt)];
+    let s = match format {
+        OutputFormat::Markdown => generator.generate(&messages).await,
+        OutputFormat::Json => generator.generate_json(&messages, &recipe::JsonAnswer::schema()).await,
+    }
+    .map_err(|e| ServerFnError::ServerError(e.to_string()))?;
+
+    let report = checked_report(read_answer(&s, format), &s)?;
+    Ok(Generation { report, template, id: None })
 }
 
-impl GptChatRequest {
-    fn new_recipe_request(ingredients: &Vec<Ingredient>) -> GptChatRequest {
-        let prompt = format!( "what should I eat for dinner? i have {}. I can't eat gluten and milk. can you give me some interesting and simple recipes I could do with the above ingredients? Please answer in the markdown format, don't include anyting else than recipe names and text.",
-            ingredients.iter().map(Ingredient::to_string).collect::<Vec<String>>().join(", "));
+/// Records a generation from `template` for `Generation::id`. Recipes are better without it than
+/// not at all, so a failure only gets logged.
+#[cfg(feature = "ssr")]
+fn record_generation(db: &crate::db::Db, template: &str) -> Option<Uuid> {
+    db.record_generation(template)
+        .map_err(|e| log!("could not record the generation: {}", e))
+        .ok()
+}
 
-        println!("{:?}", prompt);
+#[cfg(feature = "ssr")]
+fn sse_event(event: &str, data: &impl serde::Serialize) -> String {
+    format!("event: {}\ndata: {}\n\n", event, serde_json::to_string(data).expect("events serialize"))
+}
 
-        GptChatRequest { 
-            model: "gpt-3.5-turbo".to_owned(),
-            messages: vec![GptMessage::user(&prompt)],
-            temperature: 0.5
+/// The server-sent events for one answer: a `recipe` whenever one is complete, and then either `done` with the whole `Generation` or
+/// `failed` with what went wrong.
+#[cfg(feature = "ssr")]
+fn recipe_events(chunks: crate::llm::TextStream, template: String, id: Option<Uuid>) -> impl futures::Stream<Item = String> {
+    use futures::StreamExt;
+
+    futures::stream::unfold(Some((chunks, recipe::RecipeStream::new(), template)), move |state| async move {
+        let (mut chunks, mut parser, template) = state?;
+        match chunks.next().await {
+            Some(Ok(text)) => {
+                let events: String = parser.push(&text).iter().map(|r| sse_event("recipe", r)).collect();
+                Some((events, Some((chunks, parser, template))))
+            }
+            Some(Err(e)) => Some((sse_event("failed", &e.to_string()), None)),
+            None => Some((answer_event(parser.text(), template, id, crate::llm::OutputFormat::Markdown), None)),
         }
+    })
+}
+
+/// The last of the events: `done` with the recipes in the whole answer `s`, or `failed`.
+#[cfg(feature = "ssr")]
+fn answer_event(s: &str, template: String, id: Option<Uuid>, format: crate::llm::OutputFormat) -> String {
+    match checked_report(read_answer(s, format), s) {
+        Ok(report) => sse_event("done", &Generation { report, template, id }),
+        Err(ServerFnError::ServerError(e)) => sse_event("failed", &e),
+        Err(e) => sse_event("failed", &e.to_string()),
     }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-struct GptUsageStats {
-    completion_tokens: i32,
-    prompt_tokens: i32,
-    total_tokens: i32
+#[cfg(feature = "ssr")]
+#[derive(serde::Deserialize)]
+pub struct StreamRecipesQuery {
+    /// A `RecipeRequest` as JSON.
+    request: String,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-struct GptChatChoice {
-    finish_reason: String,
-    index: i32,
-    message: GptMessage,
-    logprobs: Option<String>,
+/// `GET STREAM_RECIPES_PATH`: like `generate_recipes`, but as server-sent events from
+/// `recipe_events`, so recipes can be shown before the whole answer is there. JSON answers can't
+/// be read until they're complete, so with `LLM_OUTPUT=json` there's only the final event.
+#[cfg(feature = "ssr")]
+pub async fn stream_recipes(
+    query: actix_web::web::Query<StreamRecipesQuery>,
+    db: actix_web::web::Data<crate::db::Db>,
+    user: Option<crate::auth::CurrentUser>,
+) -> actix_web::HttpResponse {
+    use crate::llm::{GeneratorConfig, GptMessage, OutputFormat};
+    use futures::StreamExt;
+
+    let events = async {
+        let mut request: RecipeRequest = serde_json::from_str(&query.request).map_err(|e| format!("Could not read the request: {}", e))?;
+        log!("{:?}", request.ingredients);
+        let taste = apply_account(&db, user.map(|u| u.0).as_ref(), &mut request);
+
+        let config = GeneratorConfig::from_env().map_err(|e| e.to_string())?;
+        let (prompt, template) = recipe_prompt(&current_prompts()?, &request, &taste, config.output)?;
+        log!("prompt from {}: {:?}", template, prompt);
+        let id = record_generation(&db, &template);
+
+        let generator = config.build().map_err(|e| e.to_string())?;
+        let messages = [GptMessage::user(&prompt)];
+        Ok::<_, String>(match config.output {
+            OutputFormat::Markdown => {
+                let chunks = generator.generate_stream(&messages).await.map_err(|e| e.to_string())?;
+                recipe_events(chunks, template, id).boxed_local()
+            }
+            OutputFormat::Json => {
+                let s = generator.generate_json(&messages, &recipe::JsonAnswer::schema()).await.map_err(|e| e.to_string())?;
+                futures::stream::once(async move { answer_event(&s, template, id, OutputFormat::Json) }).boxed_local()
+            }
+        })
+    }
+    .await;
+
+    let events = match events {
+        Ok(events) => events,
+        Err(e) => futures::stream::once(async move { sse_event("failed", &e) }).boxed_local(),
+    };
+
+    actix_web::HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events.map(|e| Ok::<_, actix_web::Error>(actix_web::web::Bytes::from(e))))
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-struct GptChatResponse {
-    id: String,
-    choices: Vec<GptChatChoice>,
-    created: i64,
-    model: String,
-    system_fingerprint: Option<String>,
-    object: String,
-    usage: GptUsageStats,
+#[server(GenerateRecipes, "/api")]
+pub async fn generate_recipes(request: RecipeRequest) -> Result<Generation, ServerFnError> {
+    use crate::llm::GeneratorConfig;
+
+    log!("{:?}", request.ingredients);
+
+    let prompts = current_prompts().map_err(ServerFnError::ServerError)?;
+    let config = GeneratorConfig::from_env().map_err(|e| ServerFnError::ServerError(e.to_string()))?;
+    let generator = config.build().map_err(|e| ServerFnError::ServerError(e.to_string()))?;
+
+    let mut request = request;
+    let db = db().await?;
+    let taste = apply_account(&db, maybe_user().await?.as_ref(), &mut request);
+
+    let mut generation = generate_with(generator.as_ref(), &prompts, &request, &taste, config.output).await?;
+    generation.id = record_generation(&db, &generation.template);
+    Ok(generation)
 }
 
+/// Reads the recipes out of a saved page and puts them in the book.
+#[server(ImportRecipes, "/api")]
+pub async fn import_recipes(html: String) -> Result<Vec<SavedRecipe>, ServerFnError> {
+    // pages can be large, only parse them for someone with a book to put the recipes in
+    let user = user().await?;
+    let recipes = recipe::recipes_from_html(&html);
+    if recipes.is_empty() {
+        return Err(ServerFnError::ServerError("No recipe found in the page".to_owned()));
+    }
 
-#[server(GenerateRecipes, "/api")]
-pub async fn generate_recipes(ingredients: Vec<Ingredient>) -> Result<Vec<Recipe>, ServerFnError> {
+    let db = db().await?;
+    recipes.iter().map(|r| db.save_recipe(user.id, r, None).map_err(db_error)).collect()
+}
 
-    log!("{:?}", ingredients);
+/// The database `main` hands to every request.
+#[cfg(feature = "ssr")]
+async fn db() -> Result<actix_web::web::Data<crate::db::Db>, ServerFnError> {
+    leptos_actix::extract(|db: actix_web::web::Data<crate::db::Db>| async move { db }).await
+}
 
-    let api_key = match var("OPENAI_API_KEY") {
-        Ok(k) => k,
-        Err(_) => return Err(ServerFnError::ServerError("No API key found".to_owned())),
+/// Whoever is logged in, if anybody.
+#[cfg(feature = "ssr")]
+async fn maybe_user() -> Result<Option<User>, ServerFnError> {
+    leptos_actix::extract(|user: Option<crate::auth::CurrentUser>| async move { user.map(|u| u.0) }).await
+}
+
+/// The logged-in user, for what only makes sense with an account.
+#[cfg(feature = "ssr")]
+async fn user() -> Result<User, ServerFnError> {
+    maybe_user().await?.ok_or_else(|| ServerFnError::ServerError(LOG_IN_FIRST.to_owned()))
+}
+
+#[cfg(feature = "ssr")]
+async fn session() -> Result<actix_session::Session, ServerFnError> {
+    leptos_actix::extract(|session: actix_session::Session| async move { session }).await
+}
+
+#[cfg(feature = "ssr")]
+fn db_error(e: rusqlite::Error) -> ServerFnError {
+    log!("database error: {}", e);
+    ServerFnError::ServerError("Could not reach the recipe book, try again later".to_owned())
+}
+
+/// `generation` is the `Generation::id` of the answer the recipe is from, if any. The template
+/// kept with the recipe is the one the server recorded for it.
+#[server(SaveRecipe, "/api")]
+pub async fn save_recipe(recipe: recipe::Recipe, generation: Option<Uuid>) -> Result<SavedRecipe, ServerFnError> {
+    let user = user().await?;
+    let db = db().await?;
+    let template = match generation {
+        Some(id) => db.generation_template(id).map_err(db_error)?,
+        None => None,
     };
+    db.save_recipe(user.id, &recipe, template.as_deref()).map_err(db_error)
+}
+
+#[server(ListRecipes, "/api")]
+pub async fn list_recipes() -> Result<Vec<SavedRecipe>, ServerFnError> {
+    let user = user().await?;
+    db().await?.list_recipes(user.id).map_err(db_error)
+}
+
+/// `None` when there is no recipe with that id.
+#[server(GetRecipe, "/api")]
+pub async fn get_recipe(id: Uuid) -> Result<Option<SavedRecipe>, ServerFnError> {
+    let user = user().await?;
+    db().await?.get_recipe(user.id, id).map_err(db_error)
+}
+
+/// Gives the recipe a public link, see `Db::share_recipe`, and answers with its token.
+#[server(ShareRecipe, "/api")]
+pub async fn share_recipe(id: Uuid) -> Result<String, ServerFnError> {
+    let user = user().await?;
+    db().await?.share_recipe(user.id, id).map_err(db_error)?.ok_or_else(not_found)
+}
+
+#[server(UnshareRecipe, "/api")]
+pub async fn unshare_recipe(id: Uuid) -> Result<(), ServerFnError> {
+    let user = user().await?;
+    db().await?.unshare_recipe(user.id, id).map_err(db_error)?.then_some(()).ok_or_else(not_found)
+}
+
+/// The recipe shared under `token`, for anyone, logged in or not.
+#[server(GetSharedRecipe, "/api")]
+pub async fn get_shared_recipe(token: String) -> Result<Option<recipe::Recipe>, ServerFnError> {
+    db().await?.shared_recipe(&token).map_err(db_error)
+}
+
+#[server(DeleteRecipe, "/api")]
+pub async fn delete_recipe(id: Uuid) -> Result<(), ServerFnError> {
+    let user = user().await?;
+    db().await?.delete_recipe(user.id, id).map_err(db_error)?.then_some(()).ok_or_else(not_found)
+}
+
+#[cfg(feature = "ssr")]
+fn not_found() -> ServerFnError {
+    ServerFnError::ServerError("There is no such recipe in the book".to_owned())
+}
+
+/// Sets the rating from 1 to 5 stars, or clears it with `None`.
+#[server(RateRecipe, "/api")]
+pub async fn rate_recipe(id: Uuid, rating: Option<u8>) -> Result<(), ServerFnError> {
+    if rating.is_some_and(|r| !(1..=5).contains(&r)) {
+        return Err(ServerFnError::ServerError("A rating is from 1 to 5 stars".to_owned()));
+    }
+    let user = user().await?;
+    db().await?.rate_recipe(user.id, id, rating).map_err(db_error)?.then_some(()).ok_or_else(not_found)
+}
+
+#[server(SetRecipeNotes, "/api")]
+pub async fn set_recipe_notes(id: Uuid, notes: String) -> Result<(), ServerFnError> {
+    let user = user().await?;
+    db().await?.set_recipe_notes(user.id, id, notes.trim()).map_err(db_error)?.then_some(()).ok_or_else(not_found)
+}
+
+/// Notes down that the recipe was cooked just now, and says when that was.
+#[server(LogCooked, "/api")]
+pub async fn log_cooked(id: Uuid) -> Result<i64, ServerFnError> {
+    let user = user().await?;
+    db().await?.log_cooked(user.id, id).map_err(db_error)?.ok_or_else(not_found)
+}
 
-    let client = reqwest::Client::new();
 
-    let req_body = GptChatRequest::new_recipe_request(&ingredients);
+#[server(Register, "/api")]
+pub async fn register(email: String, password: String) -> Result<User, ServerFnError> {
+    crate::user::check_new_account(&email, &password).map_err(ServerFnError::ServerError)?;
 
-    let resp = client.post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&req_body)
-        .send()
+    // argon2 takes a while on purpose, which is better spent off the server's threads
+    let hash = actix_web::web::block(move || crate::auth::hash_password(&password))
         .await?
-        // .text()
-        .json::<GptChatResponse>()
-    .await?;
-    println!("response: {:#?}", resp);
+        .map_err(|e| ServerFnError::ServerError(format!("Could not keep the password: {}", e)))?;
+    let user = db().await?
+        .create_user(&email, &hash)
+        .map_err(db_error)?
+        .ok_or_else(|| ServerFnError::ServerError("There already is an account for that email".to_owned()))?;
+
+    crate::auth::log_in(&session().await?, &user).map_err(|e| ServerFnError::ServerError(e.to_string()))?;
+    Ok(user)
+}
+
+#[server(Login, "/api")]
+pub async fn login(email: String, password: String) -> Result<User, ServerFnError> {
+    let (user, hash) = db().await?.user_by_email(&email).map_err(db_error)?.unzip();
+    let matches = actix_web::web::block(move || crate::auth::check_login(&password, hash.as_deref())).await?;
+    let user = match user {
+        Some(user) if matches => user,
+        _ => return Err(ServerFnError::ServerError("Wrong email or password".to_owned())),
+    };
+
+    crate::auth::log_in(&session().await?, &user).map_err(|e| ServerFnError::ServerError(e.to_string()))?;
+    Ok(user)
+}
+
+#[server(Logout, "/api")]
+pub async fn logout() -> Result<(), ServerFnError> {
+    crate::auth::log_out(&session().await?);
+    Ok(())
+}
+
+#[server(GetUser, "/api")]
+pub async fn get_user() -> Result<Option<User>, ServerFnError> {
+    maybe_user().await
+}
+
+/// The profile saved with the account, `None` until there is one.
+#[server(GetDietaryProfile, "/api")]
+pub async fn get_dietary_profile() -> Result<Option<DietaryProfile>, ServerFnError> {
+    let user = user().await?;
+    db().await?.preference(user.id, DIETARY_PROFILE_KEY).map_err(db_error)
+}
+
+#[server(SetDietaryProfile, "/api")]
+pub async fn set_dietary_profile(profile: DietaryProfile) -> Result<(), ServerFnError> {
+    let user = user().await?;
+    db().await?.set_preference(user.id, DIETARY_PROFILE_KEY, &profile).map_err(db_error)
+}
+
+/// The units chosen with the account, `None` for as written.
+#[server(GetUnitSystem, "/api")]
+pub async fn get_unit_system() -> Result<Option<UnitSystem>, ServerFnError> {
+    let user = user().await?;
+    Ok(db().await?.preference(user.id, UNIT_SYSTEM_KEY).map_err(db_error)?.flatten())
+}
+
+#[server(SetUnitSystem, "/api")]
+pub async fn set_unit_system(unit_system: Option<UnitSystem>) -> Result<(), ServerFnError> {
+    let user = user().await?;
+    db().await?.set_preference(user.id, UNIT_SYSTEM_KEY, &unit_system).map_err(db_error)
+}
+
+/// What's in the pantry saved with the account.
+#[server(GetPantry, "/api")]
+pub async fn get_pantry() -> Result<Vec<Ingredient>, ServerFnError> {
+    let user = user().await?;
+    Ok(pantry::in_stock(&db().await?.pantry(user.id).map_err(db_error)?))
+}
+
+/// Puts `ingredient` in the pantry, or changes the one with its id, as of now.
+#[server(SaveIngredient, "/api")]
+pub async fn save_ingredient(ingredient: Ingredient) -> Result<Ingredient, ServerFnError> {
+    let user = user().await?;
+    let ingredient = Ingredient { updated_at: crate::db::now_millis(), deleted: false, ..ingredient };
+    db().await?.merge_pantry(user.id, std::slice::from_ref(&ingredient)).map_err(db_error)?;
+    Ok(ingredient)
+}
 
-    let s = resp.choices[0].message.content
-        .borrow()
-        .as_ref()
-        .unwrap().to_owned();
+#[server(DeleteIngredient, "/api")]
+pub async fn delete_ingredient(id: Uuid) -> Result<(), ServerFnError> {
+    let user = user().await?;
+    let db = db().await?;
+    let Some(mut item) = db.pantry(user.id).map_err(db_error)?.into_iter().find(|i| i.id == id && !i.deleted) else {
+        return Err(ServerFnError::ServerError("There is no such ingredient in the pantry".to_owned()));
+    };
+    item.deleted = true;
+    item.updated_at = crate::db::now_millis();
+    db.merge_pantry(user.id, &[item]).map_err(db_error)
+}
+
+/// Merges `changes` into the pantry saved with the account, see `pantry::merge`, and answers
+/// with all of it, taken out items included. As CBOR, because form encoding drops empty lists.
+#[server(SyncPantry, "/api", "Cbor")]
+pub async fn sync_pantry(changes: Vec<Ingredient>) -> Result<Vec<Ingredient>, ServerFnError> {
+    let user = user().await?;
+    let db = db().await?;
+    db.merge_pantry(user.id, &changes).map_err(db_error)?;
+    db.pantry(user.id).map_err(db_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Taste;
+    use crate::llm::{serve_fake_openai, MockGenerator, OpenAiCompatible, OutputFormat, Prompts};
+
+    fn pantry(names: &[&str]) -> Vec<Ingredient> {
+        names.iter().map(|n| Ingredient::new(n.to_string(), None, 0)).collect()
+    }
 
+    fn request(names: &[&str]) -> RecipeRequest {
+        RecipeRequest { ingredients: pantry(names), ..RecipeRequest::default() }
+    }
+
+    #[test]
+    fn test_recipe_prompt() {
+        let profile = DietaryProfile { allergens: vec![Allergen::Gluten, Allergen::Milk], ..DietaryProfile::default() };
+        let mut ingredients = pantry(&["ham", "rice"]);
+        ingredients[0].quantity = recipe::parse_ingredient("200 g ham").quantity;
+        ingredients[1].certainty = Some("Running low".to_owned());
+
+        let (prompt, template) = recipe_prompt(&Prompts::built_in(), &RecipeRequest { ingredients, profile, ..RecipeRequest::default() }, &Taste::default(), OutputFormat::Markdown).unwrap();
+        assert_eq!(template, "recipes@3");
+        assert!(prompt.contains("i have 200 g ham, rice (Running low). I can't eat gluten or milk, not even traces. can you give me 3 interesting"), "{}", prompt);
+
+        let options = RecipeOptions { cuisine: " Italian ".to_owned(), time_budget: Some(20), servings: None, count: Some(5) };
+        let (prompt, _) = recipe_prompt(&Prompts::built_in(), &RecipeRequest { options, ..request(&["ham"]) }, &Taste::default(), OutputFormat::Markdown).unwrap();
+        assert!(prompt.contains("i have ham. I'm in the mood for Italian food. I have at most 20 minutes to cook. can you give me 5 interesting"), "{}", prompt);
+
+        let taste = Taste { liked: vec!["Ham Hash".to_owned()], disliked: vec!["Rice Pudding".to_owned()] };
+        let (prompt, _) = recipe_prompt(&Prompts::built_in(), &request(&["ham"]), &taste, OutputFormat::Markdown).unwrap();
+        assert!(prompt.contains("i have ham. I liked Ham Hash before. I didn't like Rice Pudding. can you"), "{}", prompt);
+
+        for options in [
+            RecipeOptions { count: Some(11), ..RecipeOptions::default() },
+            RecipeOptions { count: Some(0), ..RecipeOptions::default() },
+            RecipeOptions { time_budget: Some(u32::MAX), ..RecipeOptions::default() },
+            RecipeOptions { servings: Some(1000), ..RecipeOptions::default() },
+        ] {
+            assert!(recipe_prompt(&Prompts::built_in(), &RecipeRequest { options: options.clone(), ..request(&["ham"]) }, &Taste::default(), OutputFormat::Markdown).is_err(), "{:?}", options);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_generate_with_mock() {
+        let generation = generate_with(&MockGenerator::default(), &Prompts::built_in(), &request(&["ham", "potatoes"]), &Taste::default(), OutputFormat::Markdown).await.unwrap();
+        let report = generation.report;
+
+        assert_eq!(generation.template, "recipes@3");
+        assert_eq!(report.recipes.len(), 2);
+        assert!(report.warnings.is_empty());
+        assert!(pantry(&["ham", "potatoes"]).iter().all(|p| report.recipes[0].ingredients.iter().any(|i| p.covers(i))));
+    }
+
+    #[actix_web::test]
+    async fn test_generate_through_fake_openai() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = serve_fake_openai(MockGenerator::default(), listener).unwrap();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let client = OpenAiCompatible::new(&format!("http://{}/v1", addr), None, "gpt-3.5-turbo");
+        let generation = generate_with(&client, &Prompts::built_in(), &request(&["rice"]), &Taste::default(), OutputFormat::Markdown).await;
+
+        handle.stop(false).await;
+        assert_eq!(generation.unwrap().report.recipes[0].name, vec![recipe::MdElement::Text("Mock rice Hash".to_owned())]);
+    }
+
+    #[actix_web::test]
+    async fn test_generate_json() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = serve_fake_openai(MockGenerator::default(), listener).unwrap();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let client = OpenAiCompatible::new(&format!("http://{}/v1", addr), None, "gpt-4o");
+        let generation = generate_with(&client, &Prompts::built_in(), &request(&["ham", "rice"]), &Taste::default(), OutputFormat::Json).await;
+
+        handle.stop(false).await;
+        let generation = generation.unwrap();
+        assert_eq!(generation.report.recipes.len(), 2);
+        assert_eq!(generation.report.recipes[1].name, vec![recipe::MdElement::Text("Mock rice Soup".to_owned())]);
+        assert!(generation.report.warnings.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_generate_json_without_structured_output() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = serve_fake_openai(MockGenerator::default(), listener).unwrap();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        // the fake server answers 400 to a `response_format` for this model, like OpenAI does
+        let client = OpenAiCompatible::new(&format!("http://{}/v1", addr), None, "gpt-3.5-turbo");
+        let generation = generate_with(&client, &Prompts::built_in(), &request(&["ham", "rice"]), &Taste::default(), OutputFormat::Json).await;
+
+        handle.stop(false).await;
+        let generation = generation.unwrap();
+        assert_eq!(generation.report.recipes.len(), 2);
+        assert_eq!(generation.report.recipes[0].name, vec![recipe::MdElement::Text("Mock ham Hash".to_owned())]);
+    }
+
+    #[actix_web::test]
+    async fn test_generate_json_falls_back_to_markdown() {
+        let markdown = "Sure!\n\n1. Toast\nIngredients:\n- bread\nInstructions:\n- Toast the bread.";
+        let generation = generate_with(&MockGenerator::with_answer(markdown), &Prompts::built_in(), &request(&["bread"]), &Taste::default(), OutputFormat::Json).await.unwrap();
+
+        assert_eq!(generation.report, recipe::parse_lenient(markdown));
+        assert_eq!(read_answer("{\"recipes\": []}", OutputFormat::Json).recipes, vec![]);
+    }
+
+    async fn events(generator: MockGenerator) -> Vec<(String, String)> {
+        use crate::llm::RecipeGenerator;
+        use futures::StreamExt;
+
+        let chunks = generator.generate_stream(&[crate::llm::GptMessage::user("i have ham, rice. Go.")]).await.unwrap();
+        let body: String = recipe_events(chunks, "recipes@3".to_owned(), None).collect::<Vec<_>>().await.concat();
+
+        body.split_terminator("\n\n")
+            .map(|event| {
+                let (name, data) = event.split_once('\n').unwrap();
+                (name.trim_start_matches("event: ").to_owned(), data.trim_start_matches("data: ").to_owned())
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_recipe_events() {
+        let events = events(MockGenerator::default()).await;
+        let names: Vec<_> = events.iter().map(|(name, _)| name.as_str()).collect();
+
+        assert_eq!(names, vec!["recipe", "done"]);
+        let first: recipe::Recipe = serde_json::from_str(&events.iter().find(|(n, _)| n == "recipe").unwrap().1).unwrap();
+        let generation: Generation = serde_json::from_str(&events.last().unwrap().1).unwrap();
+        assert_eq!(generation.template, "recipes@3");
+        assert_eq!(generation.report.recipes.len(), 2);
+        assert_eq!(generation.report.recipes[0], first);
+    }
+
+    #[actix_web::test]
+    async fn test_recipe_events_unreadable_answer() {
+        let events = events(MockGenerator::with_answer("Sorry, I can't help with that.")).await;
+        let (name, data) = events.last().unwrap();
+
+        assert_eq!(name, "failed");
+        assert!(serde_json::from_str::<String>(data).unwrap().starts_with("Could not parse recipes"), "{}", data);
+    }
 
-    match recipe::parse(&s) {
-        Ok(r) => Ok(r),
-        Err(_) => Err(ServerFnError::ServerError("Could not parse recipes".to_owned())),
+    #[actix_web::test]
+    async fn test_generate_unreadable_answer() {
+        let err = generate_with(&MockGenerator::with_answer("Sorry, I can't help with that."), &Prompts::built_in(), &request(&["rice"]), &Taste::default(), OutputFormat::Markdown).await.unwrap_err();
+        assert!(err.to_string().contains("Could not parse recipes"), "{}", err);
     }
 }
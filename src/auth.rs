@@ -0,0 +1,180 @@
+use std::env::var;
+use std::future::{ready, Ready};
+use std::sync::OnceLock;
+
+use actix_session::config::PersistentSession;
+use actix_session::storage::CookieSessionStore;
+use actix_session::{Session, SessionExt, SessionInsertError, SessionMiddleware};
+use actix_web::cookie::{self, Key, SameSite};
+use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse};
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{self, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use uuid::Uuid;
+
+use crate::db::Db;
+use crate::user::User;
+
+
+const USER_ID: &str = "user_id";
+const SESSION_COOKIE: &str = "cookie-session";
+const SESSION_DAYS: i64 = 30;
+
+pub fn hash_password(password: &str) -> Result<String, password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default().hash_password(password.as_bytes(), &salt)?.to_string())
+}
+
+/// Whether `password` is the one `hash` was made from. A hash that can't be read matches nothing.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+/// Whether `password` is the one for an account with `hash`. Without an account it's checked
+/// against a made-up hash all the same, so how long logging in takes doesn't give away which
+/// emails have one.
+pub fn check_login(password: &str, hash: Option<&str>) -> bool {
+    static NOBODY: OnceLock<String> = OnceLock::new();
+    match hash {
+        Some(hash) => verify_password(password, hash),
+        None => {
+            let nobody = NOBODY.get_or_init(|| hash_password("nobody's password").unwrap_or_default());
+            verify_password(password, nobody);
+            false
+        }
+    }
+}
+
+/// Starts a session for `user`, under a new id so one handed out before logging in can't be
+/// used to ride along.
+pub fn log_in(session: &Session, user: &User) -> Result<(), SessionInsertError> {
+    session.renew();
+    session.insert(USER_ID, user.id)
+}
+
+pub fn log_out(session: &Session) {
+    session.purge();
+}
+
+/// The key session cookies are signed and encrypted with, from `SESSION_KEY` (at least 64
+/// bytes). Without one there's a new key on every start, which logs everybody out.
+pub fn session_key_from_env() -> Key {
+    match var("SESSION_KEY") {
+        Ok(key) => Key::try_from(key.as_bytes()).unwrap_or_else(|_| {
+            leptos::logging::log!("SESSION_KEY is shorter than 64 bytes, using a random key instead");
+            Key::generate()
+        }),
+        Err(_) => {
+            leptos::logging::log!("SESSION_KEY is not set, using a random key that logs everybody out on every start");
+            Key::generate()
+        }
+    }
+}
+
+/// Keeps sessions in a private cookie that lasts `SESSION_DAYS`.
+pub fn session_middleware(key: Key) -> SessionMiddleware<CookieSessionStore> {
+    SessionMiddleware::builder(CookieSessionStore::default(), key)
+        .cookie_name(SESSION_COOKIE.to_owned())
+        .cookie_same_site(SameSite::Lax)
+        .session_lifecycle(PersistentSession::default().session_ttl(cookie::time::Duration::days(SESSION_DAYS)))
+        .build()
+}
+
+/// Looks up the logged-in user before the request is handled, for `App::wrap_fn` inside
+/// `session_middleware`. Streamed pages render after the session middleware has taken the
+/// session back out of the request, so `CurrentUser` reads what is found here instead.
+pub fn load_user<S, B>(req: ServiceRequest, service: &S) -> S::Future
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    if let Some(user) = session_user(req.request()) {
+        req.extensions_mut().insert(user);
+    }
+    service.call(req)
+}
+
+/// The logged-in user, for handlers and server functions. Requests without one are answered
+/// with a 401, take an `Option<CurrentUser>` where that's fine.
+pub struct CurrentUser(pub User);
+
+impl FromRequest for CurrentUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<CurrentUser, actix_web::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user = req.extensions().get::<User>().cloned();
+        ready(user.map(CurrentUser).ok_or_else(|| actix_web::error::ErrorUnauthorized("Log in first")))
+    }
+}
+
+fn session_user(req: &HttpRequest) -> Option<User> {
+    let id: Uuid = req.get_session().get(USER_ID).ok()??;
+    let db = req.app_data::<web::Data<Db>>()?;
+    // an account deleted since logging in leaves a session for nobody
+    db.user(id).unwrap_or_else(|e| {
+        leptos::logging::log!("could not look up the logged-in user: {}", e);
+        None
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{App, HttpResponse};
+
+    #[test]
+    fn test_passwords() {
+        let hash = hash_password("correct horse").unwrap();
+
+        assert!(hash.starts_with("$argon2id$"), "{}", hash);
+        assert_ne!(hash, hash_password("correct horse").unwrap());
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("battery staple", &hash));
+        assert!(!verify_password("correct horse", "not a hash"));
+
+        assert!(check_login("correct horse", Some(&hash)));
+        assert!(!check_login("battery staple", Some(&hash)));
+        assert!(!check_login("nobody's password", None));
+    }
+
+    #[actix_web::test]
+    async fn test_current_user() {
+        let db = web::Data::new(Db::open_in_memory().unwrap());
+        let user = db.create_user("bonnie@example.com", "hash").unwrap().unwrap();
+
+        let app = init_service(
+            App::new()
+                .app_data(db.clone())
+                .wrap_fn(load_user)
+                .wrap(session_middleware(Key::generate()))
+                .route("/login", web::post().to(move |session: Session| {
+                    let user = user.clone();
+                    async move { log_in(&session, &user).map(|_| HttpResponse::Ok().finish()) }
+                }))
+                .route("/logout", web::post().to(|session: Session| async move {
+                    log_out(&session);
+                    HttpResponse::Ok().finish()
+                }))
+                .route("/me", web::get().to(|user: CurrentUser| async move { HttpResponse::Ok().body(user.0.email) })),
+        )
+        .await;
+
+        let resp = call_service(&app, TestRequest::get().uri("/me").to_request()).await;
+        assert_eq!(resp.status(), 401);
+
+        let resp = call_service(&app, TestRequest::post().uri("/login").to_request()).await;
+        let cookie = resp.response().cookies().find(|c| c.name() == SESSION_COOKIE).unwrap().into_owned();
+        let resp = call_service(&app, TestRequest::get().uri("/me").cookie(cookie.clone()).to_request()).await;
+        assert_eq!(read_body(resp).await, "bonnie@example.com");
+        let resp = call_service(&app, TestRequest::get().uri("/me").insert_header(("Cookie", cookie.encoded().stripped().to_string())).to_request()).await;
+        assert_eq!(read_body(resp).await, "bonnie@example.com");
+
+        let resp = call_service(&app, TestRequest::post().uri("/logout").cookie(cookie).to_request()).await;
+        let removed = resp.response().cookies().find(|c| c.name() == SESSION_COOKIE).unwrap().into_owned();
+        let resp = call_service(&app, TestRequest::get().uri("/me").cookie(removed).to_request()).await;
+        assert_eq!(resp.status(), 401);
+    }
+}
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::recipe::Recipe;
+
+
+/// A recipe in the book.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedRecipe {
+    pub id: Uuid,
+    pub recipe: Recipe,
+    /// Seconds since the Unix epoch.
+    pub saved_at: i64,
+    /// From 1 to 5 stars.
+    pub rating: Option<u8>,
+    pub notes: String,
+    /// When it was cooked, oldest first.
+    pub cooked: Vec<i64>,
+    /// The prompt template it was generated with, as `name@version`. `None` for imported
+    /// recipes and ones saved before this was kept.
+    pub template: Option<String>,
+    /// What its public link is made of while it's shared.
+    pub share_token: Option<String>,
+}
+
+/// The lowest rating that counts as liking a recipe, and the highest that counts as not.
+pub const LIKED_RATING: u8 = 4;
+pub const DISLIKED_RATING: u8 = 2;
+
+/// What the ratings in the book say, for the prompt.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Taste {
+    /// Recipe names.
+    pub liked: Vec<String>,
+    pub disliked: Vec<String>,
+}
+
+impl SavedRecipe {
+    /// The day it was saved as `YYYY-MM-DD`, in UTC.
+    pub fn saved_on(&self) -> String {
+        date(self.saved_at)
+    }
+}
+
+/// `secs` since the Unix epoch as `YYYY-MM-DD`, in UTC.
+pub fn date(secs: i64) -> String {
+    // days to a civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let days = secs.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::dummy_recipes;
+
+    #[test]
+    fn test_saved_on() {
+        let saved = |saved_at| SavedRecipe { id: Uuid::nil(), recipe: dummy_recipes()[0].clone(), saved_at, rating: None, notes: String::new(), cooked: vec![], template: None, share_token: None };
+        assert_eq!(saved(0).saved_on(), "1970-01-01");
+        assert_eq!(saved(951_782_400).saved_on(), "2000-02-29");
+        assert_eq!(saved(1_792_367_999).saved_on(), "2026-10-18");
+    }
+}
@@ -0,0 +1,523 @@
+use std::env::var;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use uuid::Uuid;
+
+use crate::book::{SavedRecipe, Taste, DISLIKED_RATING, LIKED_RATING};
+use crate::pantry::Ingredient;
+use crate::recipe::{fragment_text, Recipe};
+use crate::user::{normalize_email, User};
+
+
+const DEFAULT_PATH: &str = "cookie.db";
+
+/// Applied in order, each exactly once. Only ever add to the end.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_recipes.sql"),
+    include_str!("../migrations/0002_recipe_notes.sql"),
+    include_str!("../migrations/0003_users.sql"),
+    include_str!("../migrations/0004_pantry.sql"),
+    include_str!("../migrations/0005_recipe_template.sql"),
+    include_str!("../migrations/0006_recipe_sharing.sql"),
+    include_str!("../migrations/0007_generations.sql"),
+];
+
+/// How long recipes from a generation can be saved with its template, in seconds.
+const GENERATION_TTL: i64 = 7 * 24 * 60 * 60;
+
+// cooked dates are inserted as they happen, so rowid order is date order
+const SELECT_RECIPES: &str = "SELECT id, recipe, saved_at, rating, notes, \
+    (SELECT json_group_array(cooked_at) FROM (SELECT cooked_at FROM cooked WHERE recipe_id = recipes.id ORDER BY rowid)), \
+    template, share_token \
+    FROM recipes";
+
+// how many rated recipes go into a prompt
+const TASTE_LIMIT: usize = 20;
+
+/// The app's SQLite database. The queries are small enough to run while a request waits, so
+/// one connection behind a lock does.
+pub struct Db {
+    conn: Mutex<Connection>,
+}
+
+impl Db {
+    /// The database at `DATABASE_PATH`, `cookie.db` when it isn't set.
+    pub fn from_env() -> rusqlite::Result<Db> {
+        Db::open(&var("DATABASE_PATH").unwrap_or(DEFAULT_PATH.to_owned()))
+    }
+
+    /// Opens or creates the database at `path` and brings it up to date.
+    pub fn open(path: &str) -> rusqlite::Result<Db> {
+        Db::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> rusqlite::Result<Db> {
+        Db::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut conn: Connection) -> rusqlite::Result<Db> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut conn)?;
+        Ok(Db { conn: Mutex::new(conn) })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        // a panic halfway through a query leaves nothing behind that the next one can't handle
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A new account, or `None` when there already is one for `email`. The first account gets
+    /// the recipes saved before there were any.
+    pub fn create_user(&self, email: &str, password_hash: &str) -> rusqlite::Result<Option<User>> {
+        let user = User { id: Uuid::new_v4(), email: normalize_email(email) };
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+
+        let created = tx.execute(
+            "INSERT INTO users (id, email, password_hash, created_at) VALUES (?1, ?2, ?3, ?4) ON CONFLICT (email) DO NOTHING",
+            params![user.id.to_string(), user.email, password_hash, now()],
+        )?;
+        if created == 0 {
+            return Ok(None);
+        }
+        let users: i64 = tx.query_row("SELECT count(*) FROM users", [], |row| row.get(0))?;
+        if users == 1 {
+            tx.execute("UPDATE recipes SET user_id = ?1 WHERE user_id IS NULL", [user.id.to_string()])?;
+        }
+
+        tx.commit()?;
+        Ok(Some(user))
+    }
+
+    pub fn user(&self, id: Uuid) -> rusqlite::Result<Option<User>> {
+        self.conn()
+            .query_row("SELECT id, email FROM users WHERE id = ?1", [id.to_string()], |row| Ok(User { id: uuid_column(row, 0)?, email: row.get(1)? }))
+            .optional()
+    }
+
+    /// The account for `email` and its password hash.
+    pub fn user_by_email(&self, email: &str) -> rusqlite::Result<Option<(User, String)>> {
+        self.conn()
+            .query_row("SELECT id, email, password_hash FROM users WHERE email = ?1", [normalize_email(email)], |row| {
+                Ok((User { id: uuid_column(row, 0)?, email: row.get(1)? }, row.get(2)?))
+            })
+            .optional()
+    }
+
+    pub fn preference<T: serde::de::DeserializeOwned>(&self, user: Uuid, name: &str) -> rusqlite::Result<Option<T>> {
+        self.conn()
+            .query_row("SELECT value FROM preferences WHERE user_id = ?1 AND name = ?2", params![user.to_string(), name], |row| column(row, 0))
+            .optional()
+    }
+
+    pub fn set_preference(&self, user: Uuid, name: &str, value: &impl serde::Serialize) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO preferences (user_id, name, value) VALUES (?1, ?2, ?3) ON CONFLICT (user_id, name) DO UPDATE SET value = excluded.value",
+            params![user.to_string(), name, to_json(value)?],
+        )?;
+        Ok(())
+    }
+
+    /// Remembers that an answer was asked for with `template`, and forgets the ones older than
+    /// `GENERATION_TTL`. The id is what recipes from it are saved with.
+    pub fn record_generation(&self, template: &str) -> rusqlite::Result<Uuid> {
+        let id = Uuid::new_v4();
+        let conn = self.conn();
+        conn.execute("DELETE FROM generations WHERE created_at < ?1", [now() - GENERATION_TTL])?;
+        conn.execute("INSERT INTO generations (id, template, created_at) VALUES (?1, ?2, ?3)", params![id.to_string(), template, now()])?;
+        Ok(id)
+    }
+
+    /// The template of a recorded generation, `None` when there's no such one (anymore).
+    pub fn generation_template(&self, id: Uuid) -> rusqlite::Result<Option<String>> {
+        self.conn()
+            .query_row("SELECT template FROM generations WHERE id = ?1 AND created_at >= ?2", params![id.to_string(), now() - GENERATION_TTL], |row| row.get(0))
+            .optional()
+    }
+
+    /// `template` is the prompt template behind a generated recipe, as `name@version`.
+    pub fn save_recipe(&self, user: Uuid, recipe: &Recipe, template: Option<&str>) -> rusqlite::Result<SavedRecipe> {
+        let saved = SavedRecipe {
+            id: Uuid::new_v4(),
+            recipe: recipe.clone(),
+            saved_at: now(),
+            rating: None,
+            notes: String::new(),
+            cooked: vec![],
+            template: template.map(str::to_owned),
+            share_token: None,
+        };
+        self.conn().execute(
+            "INSERT INTO recipes (id, name, recipe, saved_at, user_id, template) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![saved.id.to_string(), fragment_text(&recipe.name), to_json(recipe)?, saved.saved_at, user.to_string(), saved.template],
+        )?;
+        Ok(saved)
+    }
+
+    /// Newest first.
+    pub fn list_recipes(&self, user: Uuid) -> rusqlite::Result<Vec<SavedRecipe>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!("{} WHERE user_id = ?1 ORDER BY saved_at DESC, rowid DESC", SELECT_RECIPES))?;
+        let recipes = stmt.query_map([user.to_string()], saved_recipe)?.collect();
+        recipes
+    }
+
+    pub fn get_recipe(&self, user: Uuid, id: Uuid) -> rusqlite::Result<Option<SavedRecipe>> {
+        self.conn()
+            .query_row(&format!("{} WHERE id = ?1 AND user_id = ?2", SELECT_RECIPES), [id.to_string(), user.to_string()], saved_recipe)
+            .optional()
+    }
+
+    /// Whether there was a recipe to delete.
+    pub fn delete_recipe(&self, user: Uuid, id: Uuid) -> rusqlite::Result<bool> {
+        Ok(self.conn().execute("DELETE FROM recipes WHERE id = ?1 AND user_id = ?2", [id.to_string(), user.to_string()])? > 0)
+    }
+
+    /// Gives the recipe a public link, or keeps the one it has, and answers with its token.
+    /// `None` when there is no such recipe.
+    pub fn share_recipe(&self, user: Uuid, id: Uuid) -> rusqlite::Result<Option<String>> {
+        let conn = self.conn();
+        conn.execute(
+            "UPDATE recipes SET share_token = COALESCE(share_token, ?3) WHERE id = ?1 AND user_id = ?2",
+            params![id.to_string(), user.to_string(), Uuid::new_v4().simple().to_string()],
+        )?;
+        conn.query_row("SELECT share_token FROM recipes WHERE id = ?1 AND user_id = ?2", [id.to_string(), user.to_string()], |row| row.get(0))
+            .optional()
+    }
+
+    /// Takes the public link away, the token won't work again. Whether there was such a recipe.
+    pub fn unshare_recipe(&self, user: Uuid, id: Uuid) -> rusqlite::Result<bool> {
+        Ok(self.conn().execute("UPDATE recipes SET share_token = NULL WHERE id = ?1 AND user_id = ?2", [id.to_string(), user.to_string()])? > 0)
+    }
+
+    /// The recipe shared under `token`, for anyone. Only the recipe, what its owner noted down
+    /// about it stays in the book.
+    pub fn shared_recipe(&self, token: &str) -> rusqlite::Result<Option<Recipe>> {
+        self.conn()
+            .query_row("SELECT recipe FROM recipes WHERE share_token = ?1", [token], |row| column(row, 0))
+            .optional()
+    }
+
+    /// Sets or, with `None`, clears the rating. Whether there was such a recipe.
+    pub fn rate_recipe(&self, user: Uuid, id: Uuid, rating: Option<u8>) -> rusqlite::Result<bool> {
+        Ok(self.conn().execute("UPDATE recipes SET rating = ?3 WHERE id = ?1 AND user_id = ?2", params![id.to_string(), user.to_string(), rating])? > 0)
+    }
+
+    pub fn set_recipe_notes(&self, user: Uuid, id: Uuid, notes: &str) -> rusqlite::Result<bool> {
+        Ok(self.conn().execute("UPDATE recipes SET notes = ?3 WHERE id = ?1 AND user_id = ?2", params![id.to_string(), user.to_string(), notes])? > 0)
+    }
+
+    /// Notes down that the recipe was cooked just now, and when that was.
+    pub fn log_cooked(&self, user: Uuid, id: Uuid) -> rusqlite::Result<Option<i64>> {
+        let at = now();
+        let logged = self.conn().execute(
+            "INSERT INTO cooked (recipe_id, cooked_at) SELECT id, ?3 FROM recipes WHERE id = ?1 AND user_id = ?2",
+            params![id.to_string(), user.to_string(), at],
+        )?;
+        Ok((logged > 0).then_some(at))
+    }
+
+    /// The names of the most recently saved recipes that were rated well or badly.
+    pub fn taste(&self, user: Uuid) -> rusqlite::Result<Taste> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT name, rating FROM recipes WHERE user_id = ?1 AND (rating >= ?2 OR rating <= ?3) ORDER BY saved_at DESC, rowid DESC LIMIT ?4",
+        )?;
+        let mut taste = Taste::default();
+        for row in stmt.query_map(params![user.to_string(), LIKED_RATING, DISLIKED_RATING, TASTE_LIMIT], |row| Ok((row.get::<_, String>(0)?, row.get::<_, u8>(1)?)))? {
+            let (name, rating) = row?;
+            match rating >= LIKED_RATING {
+                true => taste.liked.push(name),
+                false => taste.disliked.push(name),
+            }
+        }
+        Ok(taste)
+    }
+
+    /// Everything that was ever in the pantry, taken out or not, in the order it was put in.
+    pub fn pantry(&self, user: Uuid) -> rusqlite::Result<Vec<Ingredient>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT ingredient FROM pantry WHERE user_id = ?1 ORDER BY rowid")?;
+        let pantry = stmt.query_map([user.to_string()], |row| column(row, 0))?.collect();
+        pantry
+    }
+
+    /// Keeps each of `items` that is newer than what there is for it, like `pantry::merge`.
+    /// Browsers stamp their own changes, so none can be later than now by this clock, or one
+    /// with its clock ahead would win over every change made elsewhere until then.
+    pub fn merge_pantry(&self, user: Uuid, items: &[Ingredient]) -> rusqlite::Result<()> {
+        let now = now_millis();
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO pantry (id, user_id, ingredient, updated_at) VALUES (?1, ?2, ?3, ?4) \
+                ON CONFLICT (user_id, id) DO UPDATE SET ingredient = excluded.ingredient, updated_at = excluded.updated_at \
+                WHERE excluded.updated_at > pantry.updated_at",
+            )?;
+            for item in items {
+                let item = &Ingredient { updated_at: item.updated_at.min(now), ..item.clone() };
+                stmt.execute(params![item.id.to_string(), user.to_string(), to_json(item)?, item.updated_at])?;
+            }
+        }
+        tx.commit()
+    }
+}
+
+/// Runs the migrations the database hasn't had yet, each in its own transaction. SQLite's
+/// `user_version` counts the ones that are done.
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let done: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(done as usize) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", i as i64 + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+pub(crate) fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
+
+pub(crate) fn now_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or_default()
+}
+
+pub(crate) fn to_json(value: &impl serde::Serialize) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+// JSON and UUIDs are stored as text
+pub(crate) fn column<T: serde::de::DeserializeOwned>(row: &Row, i: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(i)?;
+    serde_json::from_str(&text).map_err(|e| rusqlite::Error::FromSqlConversionFailure(i, Type::Text, Box::new(e)))
+}
+
+pub(crate) fn uuid_column(row: &Row, i: usize) -> rusqlite::Result<Uuid> {
+    let text: String = row.get(i)?;
+    Uuid::parse_str(&text).map_err(|e| rusqlite::Error::FromSqlConversionFailure(i, Type::Text, Box::new(e)))
+}
+
+fn saved_recipe(row: &Row) -> rusqlite::Result<SavedRecipe> {
+    Ok(SavedRecipe {
+        id: uuid_column(row, 0)?,
+        recipe: column(row, 1)?,
+        saved_at: row.get(2)?,
+        rating: row.get(3)?,
+        notes: row.get(4)?,
+        cooked: column(row, 5)?,
+        template: row.get(6)?,
+        share_token: row.get(7)?,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::dummy_recipes;
+
+    #[test]
+    fn test_migrate() {
+        let db = Db::open_in_memory().unwrap();
+        let mut conn = db.conn();
+
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len() as i64);
+        // running them again doesn't try to create anything twice
+        migrate(&mut conn).unwrap();
+    }
+
+    fn user(db: &Db, email: &str) -> Uuid {
+        db.create_user(email, "hash").unwrap().unwrap().id
+    }
+
+    #[test]
+    fn test_users() {
+        let db = Db::open_in_memory().unwrap();
+
+        let bonnie = db.create_user(" Bonnie@Example.com", "hash").unwrap().unwrap();
+        assert_eq!(bonnie.email, "bonnie@example.com");
+        assert_eq!(db.create_user("bonnie@example.com ", "other").unwrap(), None);
+
+        assert_eq!(db.user(bonnie.id).unwrap(), Some(bonnie.clone()));
+        assert_eq!(db.user_by_email("BONNIE@example.com").unwrap(), Some((bonnie.clone(), "hash".to_owned())));
+        assert_eq!(db.user_by_email("clyde@example.com").unwrap(), None);
+
+        assert_eq!(db.preference::<Vec<String>>(bonnie.id, "dislikes").unwrap(), None);
+        db.set_preference(bonnie.id, "dislikes", &vec!["olives"]).unwrap();
+        db.set_preference(bonnie.id, "dislikes", &vec!["olives", "capers"]).unwrap();
+        assert_eq!(db.preference::<Vec<String>>(bonnie.id, "dislikes").unwrap(), Some(vec!["olives".to_owned(), "capers".to_owned()]));
+    }
+
+    #[test]
+    fn test_first_user_gets_old_recipes() {
+        let db = Db::open_in_memory().unwrap();
+        db.conn()
+            .execute("INSERT INTO recipes (id, name, recipe, saved_at) VALUES (?1, 'old', ?2, 0)", params![Uuid::new_v4().to_string(), to_json(&dummy_recipes()[0]).unwrap()])
+            .unwrap();
+
+        let first = user(&db, "bonnie@example.com");
+        let second = user(&db, "clyde@example.com");
+        assert_eq!(db.list_recipes(first).unwrap().len(), 1);
+        assert!(db.list_recipes(second).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recipes() {
+        let db = Db::open_in_memory().unwrap();
+        let (bonnie, clyde) = (user(&db, "bonnie@example.com"), user(&db, "clyde@example.com"));
+        let recipes = dummy_recipes();
+
+        let first = db.save_recipe(bonnie, &recipes[0], Some("recipes@3")).unwrap();
+        let second = db.save_recipe(bonnie, &recipes[1], None).unwrap();
+        assert_eq!(first.template.as_deref(), Some("recipes@3"));
+
+        assert_eq!(db.list_recipes(bonnie).unwrap(), vec![second.clone(), first.clone()]);
+        assert_eq!(db.get_recipe(bonnie, first.id).unwrap(), Some(first.clone()));
+
+        // one person's book is nobody else's business
+        assert!(db.list_recipes(clyde).unwrap().is_empty());
+        assert_eq!(db.get_recipe(clyde, first.id).unwrap(), None);
+        assert!(!db.delete_recipe(clyde, first.id).unwrap());
+
+        assert!(db.delete_recipe(bonnie, first.id).unwrap());
+        assert!(!db.delete_recipe(bonnie, first.id).unwrap());
+        assert_eq!(db.get_recipe(bonnie, first.id).unwrap(), None);
+        assert_eq!(db.list_recipes(bonnie).unwrap(), vec![second]);
+    }
+
+    #[test]
+    fn test_sharing() {
+        let db = Db::open_in_memory().unwrap();
+        let (bonnie, clyde) = (user(&db, "bonnie@example.com"), user(&db, "clyde@example.com"));
+        let recipe = &dummy_recipes()[0];
+        let saved = db.save_recipe(bonnie, recipe, None).unwrap();
+
+        assert_eq!(db.share_recipe(clyde, saved.id).unwrap(), None);
+        let token = db.share_recipe(bonnie, saved.id).unwrap().unwrap();
+        assert_eq!(db.share_recipe(bonnie, saved.id).unwrap().as_ref(), Some(&token));
+        assert_eq!(db.get_recipe(bonnie, saved.id).unwrap().unwrap().share_token.as_ref(), Some(&token));
+        assert_eq!(db.shared_recipe(&token).unwrap().as_ref(), Some(recipe));
+        assert_eq!(db.shared_recipe(&saved.id.to_string()).unwrap(), None);
+
+        // taking the link back for good
+        assert!(!db.unshare_recipe(clyde, saved.id).unwrap());
+        assert!(db.unshare_recipe(bonnie, saved.id).unwrap());
+        assert_eq!(db.shared_recipe(&token).unwrap(), None);
+        assert_ne!(db.share_recipe(bonnie, saved.id).unwrap().unwrap(), token);
+    }
+
+    #[test]
+    fn test_generations() {
+        let db = Db::open_in_memory().unwrap();
+        let id = db.record_generation("recipes@3").unwrap();
+        assert_eq!(db.generation_template(id).unwrap().as_deref(), Some("recipes@3"));
+        assert_eq!(db.generation_template(Uuid::new_v4()).unwrap(), None);
+
+        db.conn().execute("UPDATE generations SET created_at = created_at - ?1", [GENERATION_TTL + 1]).unwrap();
+        assert_eq!(db.generation_template(id).unwrap(), None);
+        db.record_generation("recipes@3").unwrap();
+        assert_eq!(db.conn().query_row("SELECT COUNT(*) FROM generations", [], |row| row.get::<_, i64>(0)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_ratings_and_cooking() {
+        let db = Db::open_in_memory().unwrap();
+        let (bonnie, clyde) = (user(&db, "bonnie@example.com"), user(&db, "clyde@example.com"));
+        let recipes = dummy_recipes();
+        let ham = db.save_recipe(bonnie, &recipes[0], None).unwrap();
+        let chips = db.save_recipe(bonnie, &recipes[1], None).unwrap();
+
+        assert!(db.rate_recipe(bonnie, ham.id, Some(5)).unwrap());
+        assert!(db.rate_recipe(bonnie, chips.id, Some(1)).unwrap());
+        assert!(db.rate_recipe(bonnie, chips.id, Some(6)).is_err());
+        assert!(!db.rate_recipe(clyde, chips.id, Some(5)).unwrap());
+        assert!(db.set_recipe_notes(bonnie, ham.id, "more pepper").unwrap());
+        let first = db.log_cooked(bonnie, ham.id).unwrap().unwrap();
+        let second = db.log_cooked(bonnie, ham.id).unwrap().unwrap();
+        assert_eq!(db.log_cooked(bonnie, Uuid::new_v4()).unwrap(), None);
+        assert_eq!(db.log_cooked(clyde, ham.id).unwrap(), None);
+
+        let saved = db.get_recipe(bonnie, ham.id).unwrap().unwrap();
+        assert_eq!((saved.rating, saved.notes.as_str(), saved.cooked), (Some(5), "more pepper", vec![first, second]));
+
+        let taste = db.taste(bonnie).unwrap();
+        assert_eq!(taste.liked, vec![fragment_text(&recipes[0].name)]);
+        assert_eq!(taste.disliked, vec![fragment_text(&recipes[1].name)]);
+        assert_eq!(db.taste(clyde).unwrap(), Taste::default());
+
+        assert!(db.rate_recipe(bonnie, chips.id, None).unwrap());
+        assert!(db.taste(bonnie).unwrap().disliked.is_empty());
+
+        // the cooked dates go with the recipe
+        assert!(db.delete_recipe(bonnie, ham.id).unwrap());
+        let left: i64 = db.conn().query_row("SELECT count(*) FROM cooked", [], |row| row.get(0)).unwrap();
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn test_pantry() {
+        let db = Db::open_in_memory().unwrap();
+        let (bonnie, clyde) = (user(&db, "bonnie@example.com"), user(&db, "clyde@example.com"));
+        let ham = Ingredient::new("ham".to_owned(), None, 10);
+        let rice = Ingredient::new("rice".to_owned(), None, 10);
+
+        db.merge_pantry(bonnie, &[ham.clone(), rice.clone()]).unwrap();
+        let mut changed = db.pantry(bonnie).unwrap();
+        assert_eq!(changed, vec![ham.clone(), rice.clone()]);
+        assert!(db.pantry(clyde).unwrap().is_empty());
+
+        // whatever changed last stays, wherever it comes from
+        changed[0].name = "smoked ham".to_owned();
+        changed[0].updated_at = 20;
+        crate::pantry::remove(&mut changed, rice.id, 20);
+        db.merge_pantry(bonnie, &changed).unwrap();
+        db.merge_pantry(bonnie, &[ham.clone(), rice.clone()]).unwrap();
+        assert_eq!(db.pantry(bonnie).unwrap(), changed);
+        assert_eq!(db.pantry(bonnie).unwrap(), crate::pantry::merge(&[ham, rice], &changed));
+
+        // the same id in someone else's pantry is another item
+        db.merge_pantry(clyde, &changed[..1]).unwrap();
+        assert_eq!(db.pantry(clyde).unwrap(), changed[..1]);
+    }
+
+    #[test]
+    fn test_pantry_from_the_future() {
+        let db = Db::open_in_memory().unwrap();
+        let bonnie = user(&db, "bonnie@example.com");
+        let year = 365 * 24 * 60 * 60 * 1000;
+        let ham = Ingredient::new("ham".to_owned(), None, now_millis() + year);
+
+        db.merge_pantry(bonnie, std::slice::from_ref(&ham)).unwrap();
+        let saved = db.pantry(bonnie).unwrap();
+        assert!(saved[0].updated_at <= now_millis());
+
+        // a change made afterwards on a device with the right time still counts
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let later = Ingredient { name: "smoked ham".to_owned(), updated_at: now_millis(), ..ham.clone() };
+        db.merge_pantry(bonnie, std::slice::from_ref(&later)).unwrap();
+        assert_eq!(db.pantry(bonnie).unwrap(), vec![later]);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let path = std::env::temp_dir().join(format!("cookie-{}.db", Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+
+        let db = Db::open(path).unwrap();
+        let bonnie = user(&db, "bonnie@example.com");
+        let saved = db.save_recipe(bonnie, &dummy_recipes()[0], None).unwrap();
+        drop(db);
+        let recipes = Db::open(path).unwrap().list_recipes(bonnie);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(recipes.unwrap(), vec![saved]);
+    }
+}
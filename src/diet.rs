@@ -0,0 +1,211 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+
+/// The major food allergens, as labelled in the EU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Allergen {
+    Gluten,
+    Milk,
+    Eggs,
+    Peanuts,
+    TreeNuts,
+    Soy,
+    Fish,
+    Shellfish,
+    Molluscs,
+    Sesame,
+    Mustard,
+    Celery,
+    Lupin,
+    Sulphites,
+}
+
+impl Allergen {
+    pub const ALL: [Allergen; 14] = [
+        Allergen::Gluten,
+        Allergen::Milk,
+        Allergen::Eggs,
+        Allergen::Peanuts,
+        Allergen::TreeNuts,
+        Allergen::Soy,
+        Allergen::Fish,
+        Allergen::Shellfish,
+        Allergen::Molluscs,
+        Allergen::Sesame,
+        Allergen::Mustard,
+        Allergen::Celery,
+        Allergen::Lupin,
+        Allergen::Sulphites,
+    ];
+}
+
+impl Display for Allergen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Allergen::Gluten => "gluten",
+            Allergen::Milk => "milk",
+            Allergen::Eggs => "eggs",
+            Allergen::Peanuts => "peanuts",
+            Allergen::TreeNuts => "tree nuts",
+            Allergen::Soy => "soy",
+            Allergen::Fish => "fish",
+            Allergen::Shellfish => "shellfish",
+            Allergen::Molluscs => "molluscs",
+            Allergen::Sesame => "sesame",
+            Allergen::Mustard => "mustard",
+            Allergen::Celery => "celery",
+            Allergen::Lupin => "lupin",
+            Allergen::Sulphites => "sulphites",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Diet {
+    Vegan,
+    Vegetarian,
+    Keto,
+    Halal,
+    Kosher,
+    LowFodmap,
+}
+
+impl Diet {
+    pub const ALL: [Diet; 6] = [Diet::Vegan, Diet::Vegetarian, Diet::Keto, Diet::Halal, Diet::Kosher, Diet::LowFodmap];
+}
+
+impl Display for Diet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Diet::Vegan => "vegan",
+            Diet::Vegetarian => "vegetarian",
+            Diet::Keto => "keto",
+            Diet::Halal => "halal",
+            Diet::Kosher => "kosher",
+            Diet::LowFodmap => "low-FODMAP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What someone can't or won't eat.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DietaryProfile {
+    pub allergens: Vec<Allergen>,
+    pub diets: Vec<Diet>,
+    /// Free text, one ingredient each.
+    pub dislikes: Vec<String>,
+}
+
+impl DietaryProfile {
+    pub fn is_empty(&self) -> bool {
+        self.allergens.is_empty() && self.diets.is_empty() && self.dislikes.iter().all(|d| d.trim().is_empty())
+    }
+
+    /// Adds whatever `other` has that this one doesn't.
+    pub fn merge(&mut self, other: &DietaryProfile) {
+        fn add<T: PartialEq + Clone>(items: &mut Vec<T>, more: &[T]) {
+            for item in more {
+                if !items.contains(item) {
+                    items.push(item.clone());
+                }
+            }
+        }
+        add(&mut self.allergens, &other.allergens);
+        add(&mut self.diets, &other.diets);
+        add(&mut self.dislikes, &other.dislikes);
+    }
+
+    /// Adds `item` if it's missing and removes it if it's there, for checkboxes.
+    pub fn toggle<T: PartialEq>(items: &mut Vec<T>, item: T) {
+        match items.iter().position(|i| *i == item) {
+            Some(i) => { items.remove(i); }
+            None => items.push(item),
+        }
+    }
+}
+
+// "a, b and c"
+fn join_list(items: &[String], conjunction: &str) -> String {
+    match items {
+        [] => String::new(),
+        [one] => one.clone(),
+        [rest @ .., last] => format!("{} {} {}", rest.join(", "), conjunction, last),
+    }
+}
+
+/// The profile as sentences for the prompt, like "I can't eat gluten or milk, not even traces.".
+/// Empty when there's nothing to say.
+impl Display for DietaryProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sentences = vec![];
+
+        if !self.allergens.is_empty() {
+            let allergens: Vec<String> = self.allergens.iter().map(Allergen::to_string).collect();
+            // "gluten and milk" could read as the combination, "or" makes clear each one is out
+            sentences.push(format!("I can't eat {}, not even traces.", join_list(&allergens, "or")));
+        }
+        if !self.diets.is_empty() {
+            let diets: Vec<String> = self.diets.iter().map(Diet::to_string).collect();
+            sentences.push(format!("Every recipe has to be {}.", join_list(&diets, "and")));
+        }
+
+        let dislikes: Vec<String> = self.dislikes.iter().map(|d| d.trim().to_owned()).filter(|d| !d.is_empty()).collect();
+        if !dislikes.is_empty() {
+            sentences.push(format!("I don't like {}, so leave them out.", join_list(&dislikes, "and")));
+        }
+
+        write!(f, "{}", sentences.join(" "))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt() {
+        let profile = DietaryProfile {
+            allergens: vec![Allergen::Gluten, Allergen::Milk, Allergen::TreeNuts],
+            diets: vec![Diet::Vegetarian, Diet::LowFodmap],
+            dislikes: vec!["olives".to_owned(), " ".to_owned(), " coriander ".to_owned()],
+        };
+
+        assert_eq!(
+            profile.to_string(),
+            "I can't eat gluten, milk or tree nuts, not even traces. Every recipe has to be vegetarian and low-FODMAP. I don't like olives and coriander, so leave them out.",
+        );
+    }
+
+    #[test]
+    fn test_prompt_single_items() {
+        let profile = DietaryProfile { allergens: vec![Allergen::Sesame], diets: vec![Diet::Halal], dislikes: vec![] };
+        assert_eq!(profile.to_string(), "I can't eat sesame, not even traces. Every recipe has to be halal.");
+    }
+
+    #[test]
+    fn test_empty() {
+        let profile = DietaryProfile { dislikes: vec!["  ".to_owned()], ..DietaryProfile::default() };
+        assert!(profile.is_empty());
+        assert_eq!(profile.to_string(), "");
+    }
+
+    #[test]
+    fn test_toggle() {
+        let mut diets = vec![Diet::Vegan];
+        DietaryProfile::toggle(&mut diets, Diet::Keto);
+        DietaryProfile::toggle(&mut diets, Diet::Vegan);
+        assert_eq!(diets, vec![Diet::Keto]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut profile = DietaryProfile { allergens: vec![Allergen::Milk], diets: vec![], dislikes: vec!["olives".to_owned()] };
+        profile.merge(&DietaryProfile { allergens: vec![Allergen::Sesame, Allergen::Milk], diets: vec![Diet::Vegan], dislikes: vec!["olives".to_owned()] });
+        assert_eq!(profile, DietaryProfile { allergens: vec![Allergen::Milk, Allergen::Sesame], diets: vec![Diet::Vegan], dislikes: vec!["olives".to_owned()] });
+    }
+}
@@ -1,5 +1,15 @@
 pub mod app;
+pub mod book;
+pub mod diet;
+pub mod pantry;
 pub mod recipe;
+pub mod user;
+#[cfg(feature = "ssr")]
+pub mod auth;
+#[cfg(feature = "ssr")]
+pub mod db;
+#[cfg(feature = "ssr")]
+pub mod llm;
 use cfg_if::cfg_if;
 
 cfg_if! {
@@ -0,0 +1,294 @@
+use std::time::Duration;
+
+use actix_web::rt::time::{sleep, timeout};
+use serde::Deserialize;
+
+use super::GenerateError;
+
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RETRIES: u32 = 2;
+const BACKOFF: Duration = Duration::from_millis(500);
+// a server asking for more than this is better answered with an error than a hanging page
+const MAX_WAIT: Duration = Duration::from_secs(20);
+
+/// Timeouts and retries for the requests to a model. `GeneratorConfig` reads them from
+/// `LLM_TIMEOUT_SECS`, `LLM_CONNECT_TIMEOUT_SECS` and `LLM_MAX_RETRIES`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HttpOptions {
+    /// For the whole answer, or for each piece of a streamed one.
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    /// How often a request is repeated after a 429, a 5xx or a timeout.
+    pub max_retries: u32,
+    /// The wait before the first retry, doubled for every one after it.
+    pub backoff: Duration,
+}
+
+impl Default for HttpOptions {
+    fn default() -> HttpOptions {
+        HttpOptions { timeout: TIMEOUT, connect_timeout: CONNECT_TIMEOUT, max_retries: MAX_RETRIES, backoff: BACKOFF }
+    }
+}
+
+impl HttpOptions {
+    pub(super) fn from_vars(get: impl Fn(&str) -> Option<String>) -> Result<HttpOptions, GenerateError> {
+        let number = |name: &str| -> Result<Option<u64>, GenerateError> {
+            match get(name).map(|v| v.trim().to_owned()).filter(|v| !v.is_empty()) {
+                Some(v) => v.parse().map(Some).map_err(|_| GenerateError::Config(format!("{} should be a whole number, not {:?}", name, v))),
+                None => Ok(None),
+            }
+        };
+
+        let defaults = HttpOptions::default();
+        Ok(HttpOptions {
+            timeout: number("LLM_TIMEOUT_SECS")?.map_or(defaults.timeout, Duration::from_secs),
+            connect_timeout: number("LLM_CONNECT_TIMEOUT_SECS")?.map_or(defaults.connect_timeout, Duration::from_secs),
+            max_retries: number("LLM_MAX_RETRIES")?.map_or(defaults.max_retries, |n| n as u32),
+            backoff: defaults.backoff,
+        })
+    }
+
+    fn wait_before_retry(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self.backoff.saturating_mul(2u32.saturating_pow(attempt));
+        retry_after.unwrap_or(backoff).max(backoff).min(MAX_WAIT)
+    }
+}
+
+// OpenAI sends `{"error": {"message": ..., "type": ..., "code": ...}}`, Ollama and llama.cpp
+// sometimes just `{"error": "..."}`
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ErrorDetail {
+    Detailed {
+        message: String,
+        #[serde(rename = "type")]
+        kind: Option<String>,
+        code: Option<serde_json::Value>,
+    },
+    Message(String),
+}
+
+/// What an error response says went wrong.
+pub(super) fn api_error(status: u16, body: &str) -> GenerateError {
+    let (message, code) = match serde_json::from_str::<ErrorBody>(body).map(|b| b.error) {
+        Ok(ErrorDetail::Detailed { message, kind, code }) => {
+            // codes are strings for OpenAI but numbers for some compatible servers
+            let code = code.and_then(|c| c.as_str().map(str::to_owned)).or(kind);
+            (message, code)
+        }
+        Ok(ErrorDetail::Message(message)) => (message, None),
+        Err(_) => (body.trim().chars().take(200).collect(), None),
+    };
+    GenerateError::Api { status, code, message }
+}
+
+fn is_retryable(e: &GenerateError) -> bool {
+    match e {
+        // running out of credit won't get better by waiting
+        GenerateError::Api { status: 429, code: Some(code), .. } if code == "insufficient_quota" => false,
+        GenerateError::Api { status, .. } => *status == 429 || *status >= 500,
+        GenerateError::Timeout => true,
+        GenerateError::Request(e) => e.is_connect(),
+        _ => false,
+    }
+}
+
+/// A `reqwest::Client` that gives up and retries the way `HttpOptions` say.
+pub(crate) struct HttpClient {
+    client: reqwest::Client,
+    options: HttpOptions,
+}
+
+impl HttpClient {
+    pub(crate) fn new(options: HttpOptions) -> HttpClient {
+        let client = reqwest::Client::builder()
+            .connect_timeout(options.connect_timeout)
+            .build()
+            .unwrap_or_default();
+        HttpClient { client, options }
+    }
+
+    /// Sends the request `build` makes until it gets a successful response or runs out of
+    /// retries. A `stream`ed response only has to start within the timeout, after that each
+    /// piece of it has its own.
+    pub(crate) async fn send(&self, build: impl Fn(&reqwest::Client) -> reqwest::RequestBuilder, stream: bool) -> Result<reqwest::Response, GenerateError> {
+        let mut attempt = 0;
+        loop {
+            let (result, retry_after) = self.send_once(build(&self.client), stream).await;
+            match result {
+                Err(e) if attempt < self.options.max_retries && is_retryable(&e) => {
+                    let wait = self.options.wait_before_retry(attempt, retry_after);
+                    leptos::logging::log!("{}, retrying in {:?}", e, wait);
+                    sleep(wait).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn send_once(&self, req: reqwest::RequestBuilder, stream: bool) -> (Result<reqwest::Response, GenerateError>, Option<Duration>) {
+        let resp = match stream {
+            true => timeout(self.options.timeout, req.send()).await.map_err(|_| GenerateError::Timeout).and_then(|r| r.map_err(GenerateError::from)),
+            false => req.timeout(self.options.timeout).send().await.map_err(GenerateError::from),
+        };
+        let resp = match resp {
+            Ok(resp) => resp,
+            Err(e) => return (Err(e), None),
+        };
+
+        let status = resp.status();
+        if status.is_success() {
+            return (Ok(resp), None);
+        }
+
+        let retry_after = resp
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+            .map(Duration::from_secs);
+        let body = match timeout(self.options.timeout, resp.text()).await {
+            Ok(body) => body.unwrap_or_default(),
+            Err(_) => return (Err(GenerateError::Timeout), retry_after),
+        };
+        (Err(api_error(status.as_u16(), &body)), retry_after)
+    }
+
+    /// How long to wait for the response, or for each piece of a streamed one.
+    pub(crate) fn timeout(&self) -> Duration {
+        self.options.timeout
+    }
+
+    /// Reads a whole non-streamed answer as `T`, with an error that says what came instead.
+    pub(crate) async fn json<T: serde::de::DeserializeOwned>(resp: reqwest::Response) -> Result<T, GenerateError> {
+        let body = resp.text().await.map_err(GenerateError::from)?;
+        serde_json::from_str(&body).map_err(|e| {
+            // some servers answer errors with a 200
+            match serde_json::from_str::<ErrorBody>(&body) {
+                Ok(_) => api_error(200, &body),
+                Err(_) => GenerateError::BadResponse(format!("{} in {:?}", e, body.chars().take(200).collect::<String>())),
+            }
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+
+    fn options() -> HttpOptions {
+        HttpOptions { timeout: Duration::from_millis(500), backoff: Duration::from_millis(1), ..HttpOptions::default() }
+    }
+
+    /// Has a server answer with `responses` in order, the last one over and over, and says what
+    /// came back and how many requests it took.
+    async fn get(options: HttpOptions, responses: Vec<(u16, &'static str)>, delay: Duration) -> (Result<String, GenerateError>, usize) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let served = count.clone();
+        let server = HttpServer::new(move || {
+            let (responses, served) = (responses.clone(), served.clone());
+            App::new().default_service(web::to(move || {
+                let n = served.fetch_add(1, Ordering::SeqCst);
+                let (status, body) = responses[n.min(responses.len() - 1)];
+                async move {
+                    sleep(delay).await;
+                    HttpResponse::build(actix_web::http::StatusCode::from_u16(status).unwrap()).body(body)
+                }
+            }))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let client = HttpClient::new(options);
+        let result = match client.send(|c| c.get(format!("http://{}/", addr)), false).await {
+            Ok(resp) => resp.text().await.map_err(GenerateError::from),
+            Err(e) => Err(e),
+        };
+
+        handle.stop(false).await;
+        (result, count.load(Ordering::SeqCst))
+    }
+
+    #[actix_web::test]
+    async fn test_retries_server_errors() {
+        let (result, count) = get(options(), vec![(503, "busy"), (429, "{}"), (200, "ok")], Duration::ZERO).await;
+        assert_eq!((result.unwrap().as_str(), count), ("ok", 3));
+
+        let (result, count) = get(options(), vec![(500, r#"{"error": {"message": "boom", "type": "server_error"}}"#)], Duration::ZERO).await;
+        assert_eq!(count, 3);
+        assert!(matches!(result, Err(GenerateError::Api { status: 500, ref message, .. }) if message == "boom"), "{:?}", result);
+    }
+
+    #[actix_web::test]
+    async fn test_no_retry_for_client_errors() {
+        let body = r#"{"error": {"message": "Incorrect API key provided", "type": "invalid_request_error", "code": "invalid_api_key"}}"#;
+        let (result, count) = get(options(), vec![(401, body)], Duration::ZERO).await;
+
+        assert_eq!(count, 1);
+        let err = result.unwrap_err();
+        assert!(matches!(err, GenerateError::Api { status: 401, code: Some(ref c), .. } if c == "invalid_api_key"), "{:?}", err);
+
+        let (_, count) = get(options(), vec![(429, r#"{"error": {"message": "out of credit", "code": "insufficient_quota"}}"#)], Duration::ZERO).await;
+        assert_eq!(count, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_timeout() {
+        let options = HttpOptions { timeout: Duration::from_millis(50), max_retries: 1, ..options() };
+        let (result, count) = get(options, vec![(200, "late")], Duration::from_millis(300)).await;
+
+        assert_eq!(count, 2);
+        assert!(matches!(result, Err(GenerateError::Timeout)), "{:?}", result);
+    }
+
+    #[test]
+    fn test_error_bodies() {
+        let err = api_error(404, r#"{"error": "model 'llama9' not found"}"#);
+        assert!(matches!(err, GenerateError::Api { status: 404, code: None, ref message } if message == "model 'llama9' not found"));
+
+        let err = api_error(502, "<html>Bad Gateway</html>");
+        assert!(matches!(err, GenerateError::Api { status: 502, ref message, .. } if message == "<html>Bad Gateway</html>"));
+    }
+
+    #[test]
+    fn test_options() {
+        let vars = |name: &str| match name {
+            "LLM_TIMEOUT_SECS" => Some("5".to_owned()),
+            "LLM_MAX_RETRIES" => Some(" 0 ".to_owned()),
+            _ => None,
+        };
+        let options = HttpOptions::from_vars(vars).unwrap();
+        assert_eq!((options.timeout, options.max_retries, options.connect_timeout), (Duration::from_secs(5), 0, CONNECT_TIMEOUT));
+
+        assert!(HttpOptions::from_vars(|_| Some("soon".to_owned())).is_err());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let options = HttpOptions { backoff: Duration::from_secs(1), ..HttpOptions::default() };
+        assert_eq!(options.wait_before_retry(0, None), Duration::from_secs(1));
+        assert_eq!(options.wait_before_retry(2, None), Duration::from_secs(4));
+        assert_eq!(options.wait_before_retry(0, Some(Duration::from_secs(3))), Duration::from_secs(3));
+        assert_eq!(options.wait_before_retry(0, Some(Duration::from_secs(600))), MAX_WAIT);
+    }
+}
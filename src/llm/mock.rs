@@ -0,0 +1,248 @@
+use std::net::TcpListener;
+
+use actix_web::{dev::Server, web, App, HttpResponse, HttpServer};
+use async_trait::async_trait;
+use futures::stream;
+
+use crate::recipe::{self, parse_ingredient, JsonAnswer, JsonRecipe, MdElement, Recipe, Step};
+use super::{GenerateError, GptChatChoice, GptChatChunk, GptChatRequest, GptChatResponse, GptChunkChoice, GptDelta, GptMessage, GptUsageStats, RecipeGenerator, TextStream};
+
+
+const FALLBACK_INGREDIENTS: &[&str] = &["potatoes", "onion", "eggs"];
+const WORDS_PER_CHUNK: usize = 3;
+
+/// Answers without a model, for tests and for working on the app offline. Unless it's given a
+/// fixed answer, it writes two recipes around the ingredients the prompt lists after "i have",
+/// in Markdown or, when asked for JSON, as a `JsonAnswer`. The same prompt always gets the same
+/// answer.
+#[derive(Debug, Clone, Default)]
+pub struct MockGenerator {
+    answer: Option<String>,
+}
+
+impl MockGenerator {
+    pub fn with_answer(answer: &str) -> MockGenerator {
+        MockGenerator { answer: Some(answer.to_owned()) }
+    }
+
+    fn templated_recipes(prompt: &str) -> Vec<Recipe> {
+        let lower = prompt.to_lowercase();
+        let listed: Vec<String> = lower
+            .find("i have ")
+            .map(|i| &prompt[i + "i have ".len()..])
+            .and_then(|rest| rest.split(". ").next())
+            .map(|list| list.trim_end_matches('.').split(',').map(|i| i.trim().to_owned()).filter(|i| !i.is_empty()).collect())
+            .unwrap_or_default();
+        let listed = match listed.is_empty() {
+            true => FALLBACK_INGREDIENTS.iter().map(|i| i.to_string()).collect(),
+            false => listed,
+        };
+        let ingredients: Vec<_> = listed.iter().map(|i| parse_ingredient(i)).collect();
+        let names: Vec<_> = ingredients.iter().map(|i| i.name.as_str()).collect();
+
+        let text = |s: String| vec![MdElement::Text(s)];
+        vec![
+            Recipe {
+                name: text(format!("Mock {} Hash", names[0])),
+                servings: Some(2),
+                ingredients: ingredients.clone(),
+                instructions: vec![
+                    Step::new(text(format!("Chop the {}.", names.join(" and ")))),
+                    Step::new(text("Fry everything in a hot pan until golden.".to_owned())),
+                ],
+            },
+            Recipe {
+                name: text(format!("Mock {} Soup", names[names.len() - 1])),
+                servings: Some(4),
+                ingredients: ingredients.iter().cloned().chain([parse_ingredient("1 l water")]).collect(),
+                instructions: vec![
+                    Step::new(text("Bring the water to a boil.".to_owned())),
+                    Step::new(text("Add everything else and simmer for 20 minutes.".to_owned())),
+                ],
+            },
+        ]
+    }
+
+    fn templated_answer(prompt: &str, json: bool) -> String {
+        let recipes = MockGenerator::templated_recipes(prompt);
+        match json {
+            true => serde_json::to_string_pretty(&JsonAnswer { recipes: recipes.iter().map(JsonRecipe::from).collect() }).expect("recipes serialize"),
+            false => recipe::recipes_to_markdown(&recipes),
+        }
+    }
+
+    /// The whole completion, shaped like OpenAI's, as JSON if `json` is set.
+    pub fn completion(&self, messages: &[GptMessage], json: bool) -> GptChatResponse {
+        let prompt = messages.iter().rev().find(|m| m.role == "user").and_then(|m| m.content.clone()).unwrap_or_default();
+        let answer = self.answer.clone().unwrap_or_else(|| MockGenerator::templated_answer(&prompt, json));
+
+        // close enough for anything that only looks at the totals
+        let prompt_tokens = messages.iter().filter_map(|m| m.content.as_ref()).map(|c| c.split_whitespace().count() as i32).sum();
+        let completion_tokens = answer.split_whitespace().count() as i32;
+
+        GptChatResponse {
+            id: "chatcmpl-mock".to_owned(),
+            choices: vec![GptChatChoice {
+                finish_reason: Some("stop".to_owned()),
+                index: 0,
+                message: GptMessage { role: "assistant".to_owned(), content: Some(answer) },
+                logprobs: None,
+            }],
+            created: 0,
+            model: "mock".to_owned(),
+            system_fingerprint: None,
+            object: "chat.completion".to_owned(),
+            usage: Some(GptUsageStats { completion_tokens, prompt_tokens, total_tokens: prompt_tokens + completion_tokens }),
+        }
+    }
+
+    /// The answer cut into the pieces a streamed completion would send, a few words each.
+    pub fn completion_chunks(&self, messages: &[GptMessage], json: bool) -> Vec<String> {
+        let answer = self.completion(messages, json).choices.remove(0).message.content.unwrap_or_default();
+        let words: Vec<&str> = answer.split_inclusive(char::is_whitespace).collect();
+        words.chunks(WORDS_PER_CHUNK).map(|c| c.concat()).collect()
+    }
+}
+
+#[async_trait]
+impl RecipeGenerator for MockGenerator {
+    async fn generate(&self, messages: &[GptMessage]) -> Result<String, GenerateError> {
+        let resp = self.completion(messages, false);
+        super::answer(resp.choices.into_iter().next().and_then(|c| c.message.content))
+    }
+
+    async fn generate_stream(&self, messages: &[GptMessage]) -> Result<TextStream, GenerateError> {
+        Ok(Box::pin(stream::iter(self.completion_chunks(messages, false).into_iter().map(Ok))))
+    }
+
+    async fn generate_json(&self, messages: &[GptMessage], _schema: &serde_json::Value) -> Result<String, GenerateError> {
+        let resp = self.completion(messages, true);
+        super::answer(resp.choices.into_iter().next().and_then(|c| c.message.content))
+    }
+}
+
+// what OpenAI answers when a model can't do structured output
+const UNSUPPORTED_RESPONSE_FORMAT: &str = r#"{"error": {"message": "Invalid parameter: 'response_format' of type 'json_schema' is not supported with this model.", "type": "invalid_request_error", "param": "response_format", "code": null}}"#;
+
+async fn chat_completions(mock: web::Data<MockGenerator>, req: web::Json<GptChatRequest>) -> HttpResponse {
+    let json = req.response_format.is_some();
+    if json && req.model == "gpt-3.5-turbo" {
+        return HttpResponse::BadRequest().content_type("application/json").body(UNSUPPORTED_RESPONSE_FORMAT);
+    }
+    if !req.stream {
+        return HttpResponse::Ok().json(mock.completion(&req.messages, json));
+    }
+
+    let events = mock
+        .completion_chunks(&req.messages, json)
+        .into_iter()
+        .map(|content| GptChatChunk {
+            id: "chatcmpl-mock".to_owned(),
+            choices: vec![GptChunkChoice { index: 0, delta: GptDelta { content: Some(content) }, finish_reason: None }],
+        })
+        .map(|chunk| format!("data: {}\n\n", serde_json::to_string(&chunk).expect("chunks serialize")))
+        .chain(["data: [DONE]\n\n".to_owned()])
+        .map(|event| Ok::<_, actix_web::Error>(web::Bytes::from(event)));
+
+    HttpResponse::Ok().content_type("text/event-stream").streaming(stream::iter(events))
+}
+
+/// A stand-in for the OpenAI API that answers `POST /v1/chat/completions` from `mock`. Like
+/// OpenAI, it refuses a `response_format` for `gpt-3.5-turbo`. Point `OpenAiCompatible` (or
+/// `LLM_BASE_URL`) at `http://<listener address>/v1` to go through the real client without a
+/// network. The returned server has to be awaited or spawned to run.
+pub fn serve_fake_openai(mock: MockGenerator, listener: TcpListener) -> std::io::Result<Server> {
+    let mock = web::Data::new(mock);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(mock.clone())
+            .route("/v1/chat/completions", web::post().to(chat_completions))
+    })
+    .workers(1)
+    .listen(listener)?
+    .run();
+    Ok(server)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::OpenAiCompatible;
+    use crate::recipe::fragment_text;
+
+    #[test]
+    fn test_templated_answer() {
+        let answer = MockGenerator::templated_answer("what should I eat? i have Ham, 2 cups rice. I can't eat gluten.", false);
+        let recipes = recipe::parse(&answer).unwrap();
+
+        assert_eq!(recipes.iter().map(|r| fragment_text(&r.name)).collect::<Vec<_>>(), vec!["Mock Ham Hash", "Mock rice Soup"]);
+        assert_eq!(recipes[0].ingredients.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), vec!["Ham", "rice"]);
+        assert_eq!(answer, MockGenerator::templated_answer("what should I eat? i have Ham, 2 cups rice. I can't eat gluten.", false));
+
+        let json = MockGenerator::templated_answer("what should I eat? i have Ham, 2 cups rice. I can't eat gluten.", true);
+        assert_eq!(recipe::parse_json(&json).unwrap(), recipes);
+    }
+
+    #[test]
+    fn test_templated_answer_without_ingredients() {
+        let recipes = recipe::parse(&MockGenerator::templated_answer("surprise me", false)).unwrap();
+        assert_eq!(recipes[0].ingredients.len(), FALLBACK_INGREDIENTS.len());
+    }
+
+    #[actix_web::test]
+    async fn test_fake_openai_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = serve_fake_openai(MockGenerator::with_answer("1. Toast"), listener).unwrap();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let client = OpenAiCompatible::new(&format!("http://{}/v1", addr), Some("sk-test".to_owned()), "gpt-3.5-turbo");
+        let answer = client.generate(&[GptMessage::user("what should I eat?")]).await;
+
+        handle.stop(false).await;
+        assert_eq!(answer.unwrap(), "1. Toast");
+    }
+
+    #[actix_web::test]
+    async fn test_fake_openai_server_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = serve_fake_openai(MockGenerator::default(), listener).unwrap();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let client = OpenAiCompatible::new(&format!("http://{}/v1", addr), None, "gpt-4o");
+        let answer = client.generate_json(&[GptMessage::user("i have ham. Thanks.")], &JsonAnswer::schema()).await;
+
+        handle.stop(false).await;
+        let recipes = recipe::parse_json(&answer.unwrap()).unwrap();
+        assert_eq!(fragment_text(&recipes[0].name), "Mock ham Hash");
+    }
+
+    #[actix_web::test]
+    async fn test_fake_openai_server_streams() {
+        use futures::TryStreamExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mock = MockGenerator::default();
+        let messages = [GptMessage::user("i have ham. Thanks.")];
+        let server = serve_fake_openai(mock.clone(), listener).unwrap();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let client = OpenAiCompatible::new(&format!("http://{}/v1", addr), None, "gpt-3.5-turbo");
+        let chunks: Result<Vec<String>, _> = match client.generate_stream(&messages).await {
+            Ok(s) => s.try_collect().await,
+            Err(e) => Err(e),
+        };
+
+        handle.stop(false).await;
+        let chunks = chunks.unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks, mock.completion_chunks(&messages, false));
+        assert_eq!(chunks.concat(), mock.generate(&messages).await.unwrap());
+    }
+}
@@ -0,0 +1,687 @@
+use std::env::var;
+use std::fmt::{self, Display};
+use std::pin::Pin;
+use std::time::Duration;
+
+use actix_web::rt::time::timeout;
+use async_trait::async_trait;
+use futures::{stream, Stream};
+use serde::{Deserialize, Serialize};
+
+pub use crate::llm::http::HttpOptions;
+pub use crate::llm::mock::*;
+pub use crate::llm::prompt::*;
+
+use crate::llm::http::HttpClient;
+
+mod http;
+mod mock;
+mod prompt;
+
+const OPENAI_URL: &str = "https://api.openai.com/v1";
+const OPENAI_MODEL: &str = "gpt-3.5-turbo";
+const OLLAMA_URL: &str = "http://localhost:11434";
+const OLLAMA_MODEL: &str = "llama3";
+const LLAMA_CPP_URL: &str = "http://localhost:8080";
+const TEMPERATURE: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GptMessage {
+    pub role: String,
+    pub content: Option<String>,
+}
+
+impl GptMessage {
+    pub fn system(content: &str) -> GptMessage {
+        GptMessage { role: "system".to_owned(), content: Some(content.to_owned()) }
+    }
+
+    pub fn user(content: &str) -> GptMessage {
+        GptMessage { role: "user".to_owned(), content: Some(content.to_owned()) }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GptChatRequest {
+    pub model: String,
+    pub messages: Vec<GptMessage>,
+    pub temperature: f32,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    /// Structured output, like `{"type": "json_schema", ...}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GptUsageStats {
+    pub completion_tokens: i32,
+    pub prompt_tokens: i32,
+    pub total_tokens: i32
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GptChatChoice {
+    pub finish_reason: Option<String>,
+    pub index: i32,
+    pub message: GptMessage,
+    pub logprobs: Option<serde_json::Value>,
+}
+
+/// An OpenAI chat completion. Compatible servers leave out the bookkeeping fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GptChatResponse {
+    #[serde(default)]
+    pub id: String,
+    pub choices: Vec<GptChatChoice>,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub model: String,
+    pub system_fingerprint: Option<String>,
+    #[serde(default)]
+    pub object: String,
+    pub usage: Option<GptUsageStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GptDelta {
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GptChunkChoice {
+    pub index: i32,
+    pub delta: GptDelta,
+    pub finish_reason: Option<String>,
+}
+
+/// One of the server-sent events of a `stream: true` completion.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GptChatChunk {
+    #[serde(default)]
+    pub id: String,
+    pub choices: Vec<GptChunkChoice>,
+}
+
+/// What went wrong asking a model. The messages are meant for the people using the app.
+#[derive(Debug)]
+pub enum GenerateError {
+    /// The provider isn't set up, like a missing API key.
+    Config(String),
+    /// The request didn't get an answer at all, like when the server is down.
+    Request(reqwest::Error),
+    Timeout,
+    /// The provider answered with an error, `code` is its own name for it when it has one.
+    Api { status: u16, code: Option<String>, message: String },
+    /// The provider answered, but without any text.
+    EmptyAnswer,
+    /// The model stopped because of its content filter.
+    ContentFilter,
+    /// The model ran out of tokens before the answer was finished.
+    Truncated,
+    /// A whole answer that isn't what the provider should send.
+    BadResponse(String),
+    /// A streamed answer had something in it that isn't what the provider should send.
+    BadStream(String),
+}
+
+impl Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerateError::Config(e) => write!(f, "{}", e),
+            GenerateError::Request(e) => write!(f, "Could not reach the model: {}", e),
+            GenerateError::Timeout => write!(f, "The model took too long to answer, try again in a moment"),
+            GenerateError::Api { status: 401, .. } | GenerateError::Api { status: 403, .. } => write!(f, "The model provider did not accept the API key"),
+            GenerateError::Api { status: 429, code: Some(code), .. } if code == "insufficient_quota" => write!(f, "The model provider account has run out of credit"),
+            GenerateError::Api { status: 429, .. } => write!(f, "The model provider is getting too many requests, try again in a minute"),
+            GenerateError::Api { status, message, .. } if *status >= 500 => write!(f, "The model provider is having problems ({}): {}", status, message),
+            GenerateError::Api { status, message, .. } => write!(f, "The model provider refused the request ({}): {}", status, message),
+            GenerateError::EmptyAnswer => write!(f, "The model gave an empty answer"),
+            GenerateError::ContentFilter => write!(f, "The model's content filter stopped the answer"),
+            GenerateError::Truncated => write!(f, "The answer was cut off before it was finished, try asking for fewer recipes"),
+            GenerateError::BadResponse(e) => write!(f, "Could not read the model's answer: {}", e),
+            GenerateError::BadStream(e) => write!(f, "Could not read the model's answer: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {}
+
+impl From<reqwest::Error> for GenerateError {
+    fn from(e: reqwest::Error) -> Self {
+        match e.is_timeout() {
+            true => GenerateError::Timeout,
+            false => GenerateError::Request(e),
+        }
+    }
+}
+
+/// Pieces of an answer in the order the model wrote them.
+pub type TextStream = Pin<Box<dyn Stream<Item = Result<String, GenerateError>> + Send>>;
+
+/// Something that answers a chat with text, usually a language model behind an HTTP API.
+#[async_trait]
+pub trait RecipeGenerator: Send + Sync {
+    async fn generate(&self, messages: &[GptMessage]) -> Result<String, GenerateError>;
+
+    /// Like `generate`, but hands out the answer as it is written. Generators that can't stream
+    /// send it all in one piece.
+    async fn generate_stream(&self, messages: &[GptMessage]) -> Result<TextStream, GenerateError> {
+        let answer = self.generate(messages).await?;
+        Ok(Box::pin(stream::once(async { Ok(answer) })))
+    }
+
+    /// Like `generate`, but has the model answer with JSON matching `schema` where the provider
+    /// can enforce it. Elsewhere the prompt is all there is to go on, so check what comes back.
+    async fn generate_json(&self, messages: &[GptMessage], _schema: &serde_json::Value) -> Result<String, GenerateError> {
+        self.generate(messages).await
+    }
+}
+
+fn answer(content: Option<String>) -> Result<String, GenerateError> {
+    content.filter(|c| !c.trim().is_empty()).ok_or(GenerateError::EmptyAnswer)
+}
+
+/// Why the model stopped, if that means the answer can't be used.
+fn check_finish_reason(reason: Option<&str>) -> Result<(), GenerateError> {
+    match reason {
+        Some("content_filter") => Err(GenerateError::ContentFilter),
+        Some("length") => Err(GenerateError::Truncated),
+        _ => Ok(()),
+    }
+}
+
+fn choice_answer(choices: Vec<GptChatChoice>) -> Result<String, GenerateError> {
+    let choice = choices.into_iter().next().ok_or(GenerateError::EmptyAnswer)?;
+    check_finish_reason(choice.finish_reason.as_deref())?;
+    answer(choice.message.content)
+}
+
+enum StreamLine {
+    Text(String),
+    Skip,
+    Done,
+}
+
+/// Splits a streamed response body into lines and hands out the text `parse_line` finds in them.
+/// A model that goes quiet for longer than `wait` ends it with a `Timeout`.
+fn line_stream(resp: reqwest::Response, wait: Duration, parse_line: fn(&str) -> Result<StreamLine, GenerateError>) -> TextStream {
+    // the response goes away once the body is fully read, the buffer can still hold a last line
+    let state = (Some(resp), Vec::new());
+
+    Box::pin(stream::unfold(state, move |(mut resp, mut buf)| async move {
+        loop {
+            let line = match buf.iter().position(|b| *b == b'\n') {
+                Some(i) => buf.drain(..=i).collect(),
+                None if resp.is_none() && !buf.is_empty() => std::mem::take(&mut buf),
+                None => {
+                    match timeout(wait, resp.as_mut()?.chunk()).await {
+                        Ok(Ok(Some(bytes))) => buf.extend_from_slice(&bytes),
+                        Ok(Ok(None)) => resp = None,
+                        Ok(Err(e)) => return Some((Err(e.into()), (None, vec![]))),
+                        Err(_) => return Some((Err(GenerateError::Timeout), (None, vec![]))),
+                    }
+                    continue;
+                }
+            };
+
+            match parse_line(String::from_utf8_lossy(&line).trim()) {
+                Ok(StreamLine::Text(text)) => return Some((Ok(text), (resp, buf))),
+                Ok(StreamLine::Skip) => {}
+                Ok(StreamLine::Done) => return None,
+                Err(e) => return Some((Err(e), (None, vec![]))),
+            }
+        }
+    }))
+}
+
+// "data: {...}" lines with a chunk each, then "data: [DONE]"
+fn openai_stream_line(line: &str) -> Result<StreamLine, GenerateError> {
+    let data = match line.strip_prefix("data:") {
+        Some(data) => data.trim(),
+        None => return Ok(StreamLine::Skip),
+    };
+    if data == "[DONE]" {
+        return Ok(StreamLine::Done);
+    }
+
+    let chunk: GptChatChunk = match serde_json::from_str(data) {
+        Ok(chunk) => chunk,
+        // errors halfway through come as an event of their own
+        Err(_) if data.contains("\"error\"") => return Err(http::api_error(200, data)),
+        Err(e) => return Err(GenerateError::BadStream(e.to_string())),
+    };
+    let choice = match chunk.choices.into_iter().next() {
+        Some(choice) => choice,
+        None => return Ok(StreamLine::Skip),
+    };
+    check_finish_reason(choice.finish_reason.as_deref())?;
+
+    Ok(choice.delta.content.filter(|c| !c.is_empty()).map_or(StreamLine::Skip, StreamLine::Text))
+}
+
+/// Anything that speaks the OpenAI chat completions API: OpenAI itself, vLLM, LM Studio, ...
+pub struct OpenAiCompatible {
+    http: HttpClient,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl OpenAiCompatible {
+    pub fn new(base_url: &str, api_key: Option<String>, model: &str) -> OpenAiCompatible {
+        OpenAiCompatible {
+            http: HttpClient::new(HttpOptions::default()),
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            api_key,
+            model: model.to_owned(),
+        }
+    }
+
+    pub fn with_options(self, options: HttpOptions) -> OpenAiCompatible {
+        OpenAiCompatible { http: HttpClient::new(options), ..self }
+    }
+}
+
+#[async_trait]
+impl RecipeGenerator for OpenAiCompatible {
+    async fn generate(&self, messages: &[GptMessage]) -> Result<String, GenerateError> {
+        self.complete(messages, None).await
+    }
+
+    async fn generate_stream(&self, messages: &[GptMessage]) -> Result<TextStream, GenerateError> {
+        let resp = self.http.send(|client| self.request(client, messages, true, None), true).await?;
+        Ok(line_stream(resp, self.http.timeout(), openai_stream_line))
+    }
+
+    async fn generate_json(&self, messages: &[GptMessage], schema: &serde_json::Value) -> Result<String, GenerateError> {
+        let format = serde_json::json!({
+            "type": "json_schema",
+            "json_schema": { "name": "recipes", "strict": true, "schema": schema },
+        });
+        match self.complete(messages, Some(format)).await {
+            // older models and some compatible servers refuse structured output, asked without
+            // it most still answer in JSON, and the rest in Markdown that `read_answer` takes too
+            Err(GenerateError::Api { status: 400, message, .. }) => {
+                leptos::logging::log!("{} refused the JSON schema, asking without it: {}", self.model, message);
+                self.complete(messages, None).await
+            }
+            answer => answer,
+        }
+    }
+}
+
+impl OpenAiCompatible {
+    async fn complete(&self, messages: &[GptMessage], response_format: Option<serde_json::Value>) -> Result<String, GenerateError> {
+        let resp = self.http.send(|client| self.request(client, messages, false, response_format.clone()), false).await?;
+        choice_answer(HttpClient::json::<GptChatResponse>(resp).await?.choices)
+    }
+
+    fn request(&self, client: &reqwest::Client, messages: &[GptMessage], stream: bool, response_format: Option<serde_json::Value>) -> reqwest::RequestBuilder {
+        let req_body = GptChatRequest {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            temperature: TEMPERATURE,
+            stream,
+            response_format,
+        };
+
+        let req = client.post(format!("{}/chat/completions", self.base_url)).json(&req_body);
+        match &self.api_key {
+            Some(key) => req.bearer_auth(key),
+            None => req,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest<'a> {
+    model: &'a str,
+    messages: &'a [GptMessage],
+    stream: bool,
+    options: OllamaOptions,
+    /// A JSON schema the answer has to follow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    temperature: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    message: Option<GptMessage>,
+    #[serde(default)]
+    done: bool,
+    /// Like OpenAI's `finish_reason`.
+    done_reason: Option<String>,
+    /// Set instead of everything else when the model failed halfway through.
+    error: Option<String>,
+}
+
+// a whole response per line, the last with "done": true
+fn ollama_stream_line(line: &str) -> Result<StreamLine, GenerateError> {
+    if line.is_empty() {
+        return Ok(StreamLine::Skip);
+    }
+
+    let resp: OllamaChatResponse = serde_json::from_str(line).map_err(|e| GenerateError::BadStream(e.to_string()))?;
+    if let Some(message) = resp.error {
+        return Err(GenerateError::Api { status: 200, code: None, message });
+    }
+    check_finish_reason(resp.done_reason.as_deref())?;
+    match resp.message.and_then(|m| m.content).filter(|c| !c.is_empty()) {
+        Some(text) => Ok(StreamLine::Text(text)),
+        None if resp.done => Ok(StreamLine::Done),
+        None => Ok(StreamLine::Skip),
+    }
+}
+
+/// A model served by Ollama, through its own `/api/chat`.
+pub struct Ollama {
+    http: HttpClient,
+    base_url: String,
+    model: String,
+}
+
+impl Ollama {
+    pub fn new(base_url: &str, model: &str) -> Ollama {
+        Ollama { http: HttpClient::new(HttpOptions::default()), base_url: base_url.trim_end_matches('/').to_owned(), model: model.to_owned() }
+    }
+
+    pub fn with_options(self, options: HttpOptions) -> Ollama {
+        Ollama { http: HttpClient::new(options), ..self }
+    }
+}
+
+#[async_trait]
+impl RecipeGenerator for Ollama {
+    async fn generate(&self, messages: &[GptMessage]) -> Result<String, GenerateError> {
+        self.complete(messages, None).await
+    }
+
+    async fn generate_stream(&self, messages: &[GptMessage]) -> Result<TextStream, GenerateError> {
+        let resp = self.http.send(|client| self.request(client, messages, true, None), true).await?;
+        Ok(line_stream(resp, self.http.timeout(), ollama_stream_line))
+    }
+
+    async fn generate_json(&self, messages: &[GptMessage], schema: &serde_json::Value) -> Result<String, GenerateError> {
+        self.complete(messages, Some(schema)).await
+    }
+}
+
+impl Ollama {
+    async fn complete(&self, messages: &[GptMessage], format: Option<&serde_json::Value>) -> Result<String, GenerateError> {
+        let resp = self.http.send(|client| self.request(client, messages, false, format), false).await?;
+        let resp = HttpClient::json::<OllamaChatResponse>(resp).await?;
+        check_finish_reason(resp.done_reason.as_deref())?;
+        answer(resp.message.and_then(|m| m.content))
+    }
+
+    fn request(&self, client: &reqwest::Client, messages: &[GptMessage], stream: bool, format: Option<&serde_json::Value>) -> reqwest::RequestBuilder {
+        let req_body = OllamaChatRequest {
+            model: &self.model,
+            messages,
+            stream,
+            options: OllamaOptions { temperature: TEMPERATURE },
+            format,
+        };
+        client.post(format!("{}/api/chat", self.base_url)).json(&req_body)
+    }
+}
+
+/// A llama.cpp server. Its chat endpoint applies the loaded model's chat template and serves
+/// whatever model it was started with, so there's no key or model to pass.
+pub struct LlamaCpp(OpenAiCompatible);
+
+impl LlamaCpp {
+    pub fn new(base_url: &str) -> LlamaCpp {
+        LlamaCpp(OpenAiCompatible::new(&format!("{}/v1", base_url.trim_end_matches('/')), None, "default"))
+    }
+
+    pub fn with_options(self, options: HttpOptions) -> LlamaCpp {
+        LlamaCpp(self.0.with_options(options))
+    }
+}
+
+#[async_trait]
+impl RecipeGenerator for LlamaCpp {
+    async fn generate(&self, messages: &[GptMessage]) -> Result<String, GenerateError> {
+        self.0.generate(messages).await
+    }
+
+    async fn generate_stream(&self, messages: &[GptMessage]) -> Result<TextStream, GenerateError> {
+        self.0.generate_stream(messages).await
+    }
+
+    // the server turns the schema into a grammar the model can't stray from
+    async fn generate_json(&self, messages: &[GptMessage], schema: &serde_json::Value) -> Result<String, GenerateError> {
+        self.0.generate_json(messages, schema).await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    OpenAi,
+    Ollama,
+    LlamaCpp,
+    Mock,
+}
+
+impl Provider {
+    // `--features mock-llm` answers from `MockGenerator` unless told otherwise
+    const DEFAULT: Provider = if cfg!(feature = "mock-llm") { Provider::Mock } else { Provider::OpenAi };
+}
+
+/// How the model is asked to write the recipes down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Markdown,
+    /// `recipe::JsonAnswer`, with structured output where the provider has it.
+    Json,
+}
+
+/// Which model to ask, read from `LLM_PROVIDER` (openai, ollama, llamacpp or mock),
+/// `LLM_BASE_URL`, `LLM_MODEL`, `OPENAI_API_KEY`, `LLM_OUTPUT` (markdown or json) and the
+/// variables of `HttpOptions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorConfig {
+    pub provider: Provider,
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+    pub output: OutputFormat,
+    pub http: HttpOptions,
+}
+
+impl GeneratorConfig {
+    pub fn from_env() -> Result<GeneratorConfig, GenerateError> {
+        GeneratorConfig::from_vars(|name| var(name).ok())
+    }
+
+    fn from_vars(get: impl Fn(&str) -> Option<String>) -> Result<GeneratorConfig, GenerateError> {
+        let get = |name: &str| get(name).map(|v| v.trim().to_owned()).filter(|v| !v.is_empty());
+
+        let provider = match get("LLM_PROVIDER").map(|p| p.to_lowercase()).as_deref() {
+            None => Provider::DEFAULT,
+            Some("openai") => Provider::OpenAi,
+            Some("ollama") => Provider::Ollama,
+            Some("llamacpp") | Some("llama.cpp") | Some("llama-cpp") => Provider::LlamaCpp,
+            Some("mock") => Provider::Mock,
+            Some(other) => return Err(GenerateError::Config(format!("Unknown LLM_PROVIDER {:?}, expected openai, ollama, llamacpp or mock", other))),
+        };
+        let output = match get("LLM_OUTPUT").map(|o| o.to_lowercase()).as_deref() {
+            None | Some("markdown") => OutputFormat::Markdown,
+            Some("json") => OutputFormat::Json,
+            Some(other) => return Err(GenerateError::Config(format!("Unknown LLM_OUTPUT {:?}, expected markdown or json", other))),
+        };
+
+        Ok(GeneratorConfig {
+            provider,
+            base_url: get("LLM_BASE_URL"),
+            model: get("LLM_MODEL"),
+            api_key: get("OPENAI_API_KEY"),
+            output,
+            http: HttpOptions::from_vars(get)?,
+        })
+    }
+
+    pub fn build(&self) -> Result<Box<dyn RecipeGenerator>, GenerateError> {
+        let model = |default: &str| self.model.clone().unwrap_or(default.to_owned());
+        let base_url = |default: &str| self.base_url.clone().unwrap_or(default.to_owned());
+
+        Ok(match self.provider {
+            Provider::OpenAi => {
+                // a key is only required by OpenAI itself, not by compatible servers
+                if self.api_key.is_none() && self.base_url.is_none() {
+                    return Err(GenerateError::Config("No API key found".to_owned()));
+                }
+                Box::new(OpenAiCompatible::new(&base_url(OPENAI_URL), self.api_key.clone(), &model(OPENAI_MODEL)).with_options(self.http))
+            }
+            Provider::Ollama => Box::new(Ollama::new(&base_url(OLLAMA_URL), &model(OLLAMA_MODEL)).with_options(self.http)),
+            Provider::LlamaCpp => Box::new(LlamaCpp::new(&base_url(LLAMA_CPP_URL)).with_options(self.http)),
+            Provider::Mock => Box::new(MockGenerator::default()),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<GeneratorConfig, GenerateError> {
+        let vars: HashMap<_, _> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        GeneratorConfig::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_config() {
+        let c = config(&[("OPENAI_API_KEY", "sk-1")]).unwrap();
+        assert_eq!((c.provider, c.api_key.as_deref(), c.base_url), (Provider::DEFAULT, Some("sk-1"), None));
+
+        let c = config(&[("LLM_PROVIDER", "Ollama"), ("LLM_MODEL", "mistral"), ("LLM_BASE_URL", " ")]).unwrap();
+        assert_eq!((c.provider, c.model.as_deref(), c.base_url), (Provider::Ollama, Some("mistral"), None));
+
+        assert_eq!(config(&[("LLM_PROVIDER", "llama.cpp")]).unwrap().provider, Provider::LlamaCpp);
+        assert_eq!(config(&[("LLM_PROVIDER", "mock")]).unwrap().provider, Provider::Mock);
+        assert!(matches!(config(&[("LLM_PROVIDER", "gemini")]), Err(GenerateError::Config(_))));
+    }
+
+    #[test]
+    fn test_config_output() {
+        assert_eq!(config(&[]).unwrap().output, OutputFormat::Markdown);
+        assert_eq!(config(&[("LLM_OUTPUT", "JSON")]).unwrap().output, OutputFormat::Json);
+        assert!(matches!(config(&[("LLM_OUTPUT", "yaml")]), Err(GenerateError::Config(_))));
+    }
+
+    #[test]
+    #[cfg(not(feature = "mock-llm"))]
+    fn test_build_needs_key_for_openai() {
+        assert!(matches!(config(&[]).unwrap().build(), Err(GenerateError::Config(_))));
+        assert!(config(&[("LLM_BASE_URL", "http://localhost:1234/v1")]).unwrap().build().is_ok());
+        assert!(config(&[("LLM_PROVIDER", "ollama")]).unwrap().build().is_ok());
+    }
+
+    #[test]
+    fn test_stream_lines() {
+        let text = |line: &str, parse: fn(&str) -> Result<StreamLine, GenerateError>| match parse(line) {
+            Ok(StreamLine::Text(t)) => Some(t),
+            Ok(StreamLine::Skip) => None,
+            Ok(StreamLine::Done) => Some("<done>".to_owned()),
+            Err(e) => Some(format!("<error: {}>", e)),
+        };
+
+        assert_eq!(text(r#"data: {"id": "1", "choices": [{"index": 0, "delta": {"content": "1. So"}}]}"#, openai_stream_line).as_deref(), Some("1. So"));
+        assert_eq!(text(r#"data: {"choices": [{"index": 0, "delta": {"role": "assistant"}}]}"#, openai_stream_line), None);
+        assert_eq!(text(": keep-alive", openai_stream_line), None);
+        assert_eq!(text("data: [DONE]", openai_stream_line).as_deref(), Some("<done>"));
+        assert!(text("data: {", openai_stream_line).unwrap().starts_with("<error"));
+
+        assert_eq!(text(r#"{"message": {"role": "assistant", "content": "up"}, "done": false}"#, ollama_stream_line).as_deref(), Some("up"));
+        assert_eq!(text(r#"{"message": {"role": "assistant", "content": ""}, "done": true}"#, ollama_stream_line).as_deref(), Some("<done>"));
+    }
+
+    #[test]
+    fn test_stream_stops() {
+        let err = |line: &str, parse: fn(&str) -> Result<StreamLine, GenerateError>| parse(line).err().map(|e| e.to_string()).unwrap_or_default();
+
+        assert_eq!(err(r#"data: {"choices": [{"index": 0, "delta": {}, "finish_reason": "length"}]}"#, openai_stream_line), GenerateError::Truncated.to_string());
+        assert_eq!(err(r#"data: {"choices": [{"index": 0, "delta": {}, "finish_reason": "content_filter"}]}"#, openai_stream_line), GenerateError::ContentFilter.to_string());
+        assert!(openai_stream_line(r#"data: {"choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}]}"#).is_ok());
+        assert!(err(r#"data: {"error": {"message": "overloaded", "type": "server_error"}}"#, openai_stream_line).contains("overloaded"));
+
+        assert_eq!(err(r#"{"message": {"role": "assistant", "content": ""}, "done": true, "done_reason": "length"}"#, ollama_stream_line), GenerateError::Truncated.to_string());
+        assert!(err(r#"{"error": "model not found"}"#, ollama_stream_line).contains("model not found"));
+    }
+
+    #[actix_web::test]
+    async fn test_stream_goes_quiet() {
+        use actix_web::{web, App, HttpResponse, HttpServer};
+        use futures::StreamExt;
+
+        // one chunk, and then nothing, without ever closing the connection
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = HttpServer::new(|| App::new().default_service(web::to(|| async {
+            let first = r#"data: {"id": "1", "choices": [{"index": 0, "delta": {"content": "1. So"}}]}"#.to_owned() + "\n\n";
+            let events = stream::once(async move { Ok::<_, actix_web::Error>(web::Bytes::from(first)) }).chain(stream::pending());
+            HttpResponse::Ok().content_type("text/event-stream").streaming(events)
+        })))
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let options = HttpOptions { timeout: Duration::from_millis(200), ..HttpOptions::default() };
+        let client = OpenAiCompatible::new(&format!("http://{}/v1", addr), None, "gpt-4o").with_options(options);
+        let chunks: Vec<_> = client.generate_stream(&[GptMessage::user("hi")]).await.unwrap().collect().await;
+
+        handle.stop(false).await;
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].as_deref().ok(), Some("1. So"));
+        assert!(matches!(chunks[1], Err(GenerateError::Timeout)), "{:?}", chunks[1]);
+    }
+
+    #[test]
+    fn test_choice_answer() {
+        let choice = |content: Option<&str>, reason: &str| GptChatChoice {
+            finish_reason: Some(reason.to_owned()),
+            index: 0,
+            message: GptMessage { role: "assistant".to_owned(), content: content.map(str::to_owned) },
+            logprobs: None,
+        };
+
+        assert_eq!(choice_answer(vec![choice(Some("1. Soup"), "stop")]).unwrap(), "1. Soup");
+        assert!(matches!(choice_answer(vec![]), Err(GenerateError::EmptyAnswer)));
+        assert!(matches!(choice_answer(vec![choice(None, "stop")]), Err(GenerateError::EmptyAnswer)));
+        assert!(matches!(choice_answer(vec![choice(Some("1. So"), "length")]), Err(GenerateError::Truncated)));
+        assert!(matches!(choice_answer(vec![choice(None, "content_filter")]), Err(GenerateError::ContentFilter)));
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let api = |status, code: Option<&str>| GenerateError::Api { status, code: code.map(str::to_owned), message: "details".to_owned() }.to_string();
+
+        assert_eq!(api(401, Some("invalid_api_key")), "The model provider did not accept the API key");
+        assert!(api(429, None).contains("too many requests"));
+        assert!(api(429, Some("insufficient_quota")).contains("run out of credit"));
+        assert_eq!(api(503, None), "The model provider is having problems (503): details");
+        assert_eq!(api(400, None), "The model provider refused the request (400): details");
+    }
+
+    #[test]
+    fn test_compatible_response() {
+        // llama.cpp and friends leave out most of what OpenAI sends
+        let resp: GptChatResponse = serde_json::from_str(r#"{"choices": [{"index": 0, "message": {"role": "assistant", "content": "1. Soup"}}]}"#).unwrap();
+        assert_eq!(resp.choices[0].message.content.as_deref(), Some("1. Soup"));
+    }
+}
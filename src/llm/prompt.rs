@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::env::var;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+use minijinja::{AutoEscape, Environment};
+use serde::Serialize;
+
+use crate::diet::DietaryProfile;
+use super::OutputFormat;
+
+
+pub const RECIPES_TEMPLATE: &str = "recipes";
+const TEMPLATE_EXTENSION: &str = "j2";
+const DEFAULT_RECIPE_COUNT: u32 = 3;
+
+const BUILT_IN: &[(&str, &str)] = &[(RECIPES_TEMPLATE, include_str!("prompts/recipes.j2"))];
+
+/// A prompt template and the version it declares on its first line, like `{# version: 2 #}`.
+/// Templates that don't declare one are versioned by a hash of their source, so edits still
+/// show up as a new version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub version: String,
+    source: String,
+}
+
+impl PromptTemplate {
+    pub fn new(name: &str, source: &str) -> PromptTemplate {
+        let declared = source
+            .trim_start()
+            .strip_prefix("{#")
+            .and_then(|comment| comment.split("#}").next())
+            .and_then(|comment| comment.trim().trim_end_matches('-').trim().strip_prefix("version:"))
+            .map(|version| version.trim().to_owned())
+            .filter(|version| !version.is_empty());
+
+        PromptTemplate {
+            name: name.to_owned(),
+            version: declared.unwrap_or_else(|| format!("{:016x}", fnv1a(source.as_bytes()))),
+            source: source.to_owned(),
+        }
+    }
+}
+
+impl Display for PromptTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
+// stable across builds, unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// The prompt templates in use: the built-in ones, with any `<name>.j2` from the directory in
+/// `PROMPT_DIR` in their place.
+#[derive(Debug, Clone)]
+pub struct Prompts {
+    templates: HashMap<String, PromptTemplate>,
+}
+
+impl Prompts {
+    pub fn built_in() -> Prompts {
+        Prompts {
+            templates: BUILT_IN.iter().map(|(name, source)| (name.to_string(), PromptTemplate::new(name, source))).collect(),
+        }
+    }
+
+    /// Read on every call, so templates can be edited while the server runs.
+    pub fn from_env() -> std::io::Result<Prompts> {
+        match var("PROMPT_DIR") {
+            Ok(dir) if !dir.trim().is_empty() => Prompts::load(Path::new(dir.trim())),
+            _ => Ok(Prompts::built_in()),
+        }
+    }
+
+    pub fn load(dir: &Path) -> std::io::Result<Prompts> {
+        let mut prompts = Prompts::built_in();
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(TEMPLATE_EXTENSION) {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
+                let template = PromptTemplate::new(name, &std::fs::read_to_string(&path)?);
+                prompts.templates.insert(name.to_owned(), template);
+            }
+        }
+
+        Ok(prompts)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PromptTemplate> {
+        self.templates.get(name)
+    }
+
+    /// Fills in the template `name`, and says which version of it that was.
+    pub fn render(&self, name: &str, vars: &impl Serialize) -> Result<(String, &PromptTemplate), minijinja::Error> {
+        let template = self.get(name).ok_or_else(|| {
+            minijinja::Error::new(minijinja::ErrorKind::TemplateNotFound, format!("no prompt template named {:?}", name))
+        })?;
+
+        let mut env = Environment::new();
+        env.set_auto_escape_callback(|_| AutoEscape::None);
+        env.set_trim_blocks(true);
+        env.add_template(&template.name, &template.source)?;
+
+        let text = env.get_template(&template.name)?.render(vars)?;
+        Ok((text, template))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PromptIngredient {
+    pub name: String,
+    pub quantity: Option<String>,
+    pub certainty: Option<String>,
+}
+
+/// Everything the `recipes` template can use.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecipePromptVars {
+    pub ingredients: Vec<PromptIngredient>,
+    /// The whole profile in a few sentences, empty if there are no restrictions.
+    pub dietary_profile: String,
+    pub allergens: Vec<String>,
+    pub diets: Vec<String>,
+    pub dislikes: Vec<String>,
+    pub cuisine: Option<String>,
+    /// In minutes.
+    pub time_budget: Option<u32>,
+    pub servings: Option<u32>,
+    pub recipe_count: u32,
+    /// Names of recipes from the book rated well and badly.
+    pub liked: Vec<String>,
+    pub disliked: Vec<String>,
+    /// How to ask for the answer to be written, `markdown` or `json`.
+    pub format: OutputFormat,
+}
+
+impl RecipePromptVars {
+    pub fn new(ingredients: Vec<PromptIngredient>, profile: &DietaryProfile) -> RecipePromptVars {
+        RecipePromptVars {
+            ingredients,
+            dietary_profile: profile.to_string(),
+            allergens: profile.allergens.iter().map(|a| a.to_string()).collect(),
+            diets: profile.diets.iter().map(|d| d.to_string()).collect(),
+            dislikes: profile.dislikes.clone(),
+            cuisine: None,
+            time_budget: None,
+            servings: None,
+            recipe_count: DEFAULT_RECIPE_COUNT,
+            liked: vec![],
+            disliked: vec![],
+            format: OutputFormat::Markdown,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diet::Allergen;
+
+    fn vars() -> RecipePromptVars {
+        let ingredients = vec![
+            PromptIngredient { name: "ham".to_owned(), quantity: Some("200 g".to_owned()), certainty: None },
+            PromptIngredient { name: "rice".to_owned(), quantity: None, certainty: Some("Running low".to_owned()) },
+        ];
+        RecipePromptVars::new(ingredients, &DietaryProfile { allergens: vec![Allergen::Milk], ..DietaryProfile::default() })
+    }
+
+    #[test]
+    fn test_built_in_recipes() {
+        let prompts = Prompts::built_in();
+        let (text, template) = prompts.render(RECIPES_TEMPLATE, &vars()).unwrap();
+
+        assert_eq!(template.to_string(), "recipes@3");
+        assert!(text.starts_with("what should I eat for dinner? i have 200 g ham, rice (Running low). I can't eat milk, not even traces. can you give me 3 interesting"), "{}", text);
+        assert!(!text.contains('\n'), "{}", text);
+    }
+
+    #[test]
+    fn test_optional_vars() {
+        let vars = RecipePromptVars { cuisine: Some("Thai".to_owned()), time_budget: Some(30), servings: Some(2), recipe_count: 1, ..vars() };
+        let (text, _) = Prompts::built_in().render(RECIPES_TEMPLATE, &vars).unwrap();
+
+        assert!(text.contains("not even traces. I'm in the mood for Thai food. I have at most 30 minutes to cook. Each recipe should serve 2. can you give me 1 interesting"), "{}", text);
+    }
+
+    #[test]
+    fn test_taste() {
+        let vars = RecipePromptVars { liked: vec!["Ham Hash".to_owned(), "Pad Thai".to_owned()], disliked: vec!["Chips".to_owned()], ..vars() };
+        let (text, _) = Prompts::built_in().render(RECIPES_TEMPLATE, &vars).unwrap();
+
+        assert!(text.contains("not even traces. I liked Ham Hash, Pad Thai before. I didn't like Chips. can you"), "{}", text);
+    }
+
+    #[test]
+    fn test_formats() {
+        let (markdown, _) = Prompts::built_in().render(RECIPES_TEMPLATE, &vars()).unwrap();
+        let (json, _) = Prompts::built_in().render(RECIPES_TEMPLATE, &RecipePromptVars { format: OutputFormat::Json, ..vars() }).unwrap();
+
+        assert!(markdown.contains("in the markdown format") && !markdown.contains("JSON"), "{}", markdown);
+        assert!(json.contains("with a JSON object") && !json.contains("markdown") && !json.contains('\n'), "{}", json);
+    }
+
+    #[test]
+    fn test_versions() {
+        assert_eq!(PromptTemplate::new("a", "{# version: 2024-06 -#}\nhi").version, "2024-06");
+        assert_eq!(PromptTemplate::new("a", "  {#version:3#}hi").version, "3");
+
+        let unversioned = PromptTemplate::new("a", "hi {{ name }}");
+        assert_eq!(unversioned.version.len(), 16);
+        assert_eq!(unversioned, PromptTemplate::new("a", "hi {{ name }}"));
+        assert_ne!(unversioned.version, PromptTemplate::new("a", "hello {{ name }}").version);
+    }
+
+    #[test]
+    fn test_load_overrides() {
+        let dir = std::env::temp_dir().join(format!("cookie-prompts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("recipes.j2"), "{# version: 7 #}{{ recipe_count }} recipes with {{ ingredients | map(attribute='name') | join(' & ') }}").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a template").unwrap();
+
+        let prompts = Prompts::load(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let prompts = prompts.unwrap();
+        let (text, template) = prompts.render(RECIPES_TEMPLATE, &vars()).unwrap();
+        assert_eq!((text.as_str(), template.to_string().as_str()), ("3 recipes with ham & rice", "recipes@7"));
+        assert!(prompts.get("notes").is_none());
+    }
+
+    #[test]
+    fn test_render_errors() {
+        assert!(Prompts::built_in().render("nope", &vars()).is_err());
+
+        let mut prompts = Prompts::built_in();
+        prompts.templates.insert("broken".to_owned(), PromptTemplate::new("broken", "{% if %}"));
+        assert!(prompts.render("broken", &vars()).is_err());
+    }
+}
@@ -6,18 +6,30 @@ async fn main() -> std::io::Result<()> {
     use leptos::*;
     use leptos_actix::{generate_route_list, LeptosRoutes};
     use cookie_web::app::*;
+    use cookie_web::db::Db;
 
     let conf = get_configuration(None).await.unwrap();
     let addr = conf.leptos_options.site_addr;
     // Generate the list of routes in your Leptos App
     let routes = generate_route_list(App);
-    println!("listening on http://{}", &addr);
+    println!("listening on http://{}", addr);
+
+    let db = web::Data::new(Db::from_env().map_err(|e| std::io::Error::other(format!("could not open the database: {}", e)))?);
+    let session_key = cookie_web::auth::session_key_from_env();
+
+    // a local stand-in for the OpenAI API, for LLM_BASE_URL=http://<FAKE_OPENAI_ADDR>/v1
+    if let Ok(fake_addr) = std::env::var("FAKE_OPENAI_ADDR") {
+        let listener = std::net::TcpListener::bind(&fake_addr)?;
+        println!("fake OpenAI API on http://{}/v1", fake_addr);
+        rt::spawn(cookie_web::llm::serve_fake_openai(Default::default(), listener)?);
+    }
 
     HttpServer::new(move || {
         let leptos_options = &conf.leptos_options;
         let site_root = &leptos_options.site_root;
 
         App::new()
+            .route(STREAM_RECIPES_PATH, web::get().to(stream_recipes))
             .route("/api/{tail:.*}", leptos_actix::handle_server_fns())
             // serve JS/WASM/CSS from `pkg`
             .service(Files::new("/pkg", format!("{site_root}/pkg")))
@@ -27,6 +39,11 @@ async fn main() -> std::io::Result<()> {
             .service(favicon)
             .leptos_routes(leptos_options.to_owned(), routes.to_owned(), App)
             .app_data(web::Data::new(leptos_options.to_owned()))
+            .app_data(db.clone())
+            // imported pages are sent whole to a server function
+            .app_data(web::PayloadConfig::new(8 * 1024 * 1024))
+            .wrap_fn(cookie_web::auth::load_user)
+            .wrap(cookie_web::auth::session_middleware(session_key.clone()))
         //.wrap(middleware::Compress::default())
     })
     .bind(&addr)?
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::recipe::{mentions, Quantity, RecipeIngredient};
+
+
+/// Something in the pantry. Every copy of a pantry, in the database and in each browser, is
+/// merged item by item on `id`, and the one changed last wins.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Ingredient {
+    pub id: Uuid,
+    pub name: String,
+    pub quantity: Option<Quantity>,
+    pub certainty: Option<String>,
+    /// Milliseconds since the Unix epoch, by the clock of whoever changed it. Pantries kept
+    /// from before there was syncing have 0.
+    #[serde(default)]
+    pub updated_at: i64,
+    /// Taken out of the pantry. It stays around so the other copies hear about it.
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+impl Ingredient {
+    pub fn new(name: String, quantity: Option<Quantity>, updated_at: i64) -> Ingredient {
+        Ingredient { id: Uuid::new_v4(), name, quantity, certainty: None, updated_at, deleted: false }
+    }
+
+    /// Whether this pantry item can stand in for an ingredient a recipe asks for, when one
+    /// name is the other with some more words ("ham" and "smoked ham").
+    pub fn covers(&self, ingredient: &RecipeIngredient) -> bool {
+        !self.deleted && (mentions(&ingredient.name, &self.name) || mentions(&self.name, &ingredient.name))
+    }
+
+    /// Whether this is the newer of two versions of the same item. A tie goes to `other`, so
+    /// merging twice changes nothing.
+    pub fn newer_than(&self, other: &Ingredient) -> bool {
+        self.updated_at > other.updated_at
+    }
+}
+
+impl Display for Ingredient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// `ours` with whatever in `theirs` is newer. Items keep their place in `ours`, the ones only
+/// `theirs` has come after in their own order.
+pub fn merge(ours: &[Ingredient], theirs: &[Ingredient]) -> Vec<Ingredient> {
+    let mut merged = ours.to_vec();
+    let mut index: HashMap<Uuid, usize> = merged.iter().enumerate().map(|(i, item)| (item.id, i)).collect();
+
+    for item in theirs {
+        match index.get(&item.id) {
+            Some(&i) if item.newer_than(&merged[i]) => merged[i] = item.clone(),
+            Some(_) => {}
+            None => {
+                index.insert(item.id, merged.len());
+                merged.push(item.clone());
+            }
+        }
+    }
+    merged
+}
+
+/// The pantry once `sent` came back from the server as `saved`: the server's copy of everything,
+/// with what changed in `local` since it was sent on top. The server's copy wins even where it
+/// looks older, since it can have moved a timestamp back from the future.
+pub fn synced(local: &[Ingredient], sent: &[Ingredient], saved: &[Ingredient]) -> Vec<Ingredient> {
+    let mut synced = saved.to_vec();
+    for item in local.iter().filter(|i| !sent.contains(i)) {
+        match synced.iter_mut().find(|s| s.id == item.id) {
+            Some(s) => *s = item.clone(),
+            None => synced.push(item.clone()),
+        }
+    }
+    synced
+}
+
+/// Takes the item with `id` out of `pantry`, leaving the tombstone behind.
+pub fn remove(pantry: &mut [Ingredient], id: Uuid, at: i64) {
+    if let Some(item) = pantry.iter_mut().find(|i| i.id == id) {
+        item.deleted = true;
+        item.updated_at = at;
+    }
+}
+
+/// What's in `pantry`, without the tombstones.
+pub fn in_stock(pantry: &[Ingredient]) -> Vec<Ingredient> {
+    pantry.iter().filter(|i| !i.deleted).cloned().collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, updated_at: i64) -> Ingredient {
+        Ingredient::new(name.to_owned(), None, updated_at)
+    }
+
+    #[test]
+    fn test_merge() {
+        let ham = item("ham", 10);
+        let rice = item("rice", 10);
+        let mut newer_ham = Ingredient { name: "smoked ham".to_owned(), updated_at: 20, ..ham.clone() };
+        let eggs = item("eggs", 5);
+
+        let merged = merge(&[ham.clone(), rice.clone()], &[eggs.clone(), newer_ham.clone()]);
+        assert_eq!(merged, vec![newer_ham.clone(), rice.clone(), eggs.clone()]);
+
+        // older changes and ties don't undo anything
+        assert_eq!(merge(&merged, &[ham.clone(), Ingredient { name: "basmati".to_owned(), ..rice.clone() }]), merged);
+        assert_eq!(merge(&merged, &merged), merged);
+
+        // removing is a change like any other
+        let mut removed = merged.clone();
+        remove(&mut removed, ham.id, 30);
+        assert!(removed[0].deleted && removed[0].updated_at == 30);
+        assert_eq!(merge(&merged, &removed), removed);
+        assert_eq!(merge(&removed, &merged), removed);
+        newer_ham.updated_at = 40;
+        assert_eq!(merge(&removed, &[newer_ham.clone()])[0], newer_ham);
+        assert_eq!(in_stock(&removed), vec![rice, eggs]);
+    }
+
+    #[test]
+    fn test_synced() {
+        let ham = item("ham", i64::MAX);
+        let rice = item("rice", 10);
+        let sent = vec![ham.clone(), rice.clone()];
+        let saved = vec![Ingredient { updated_at: 20, ..ham.clone() }, rice.clone(), item("eggs", 15)];
+
+        // what was sent is what the server says it is now
+        assert_eq!(synced(&sent, &sent, &saved), saved);
+
+        // but what changed while it was on its way stays
+        let mut local = sent.clone();
+        remove(&mut local, rice.id, 5);
+        let oil = item("oil", 5);
+        local.push(oil.clone());
+        assert_eq!(synced(&local, &sent, &saved), vec![saved[0].clone(), local[1].clone(), saved[2].clone(), oil]);
+    }
+
+    #[test]
+    fn test_synced_out_of_order() {
+        let (ham, rice) = (item("ham", 10), item("rice", 10));
+        let first = vec![ham.clone(), rice.clone()];
+        let mut second = first.clone();
+        remove(&mut second, rice.id, 20);
+
+        // the answer to the second sync comes first, the one to the first doesn't undo it
+        let local = synced(&second, &second, &second);
+        assert_eq!(synced(&local, &first, &first), second);
+    }
+
+    #[test]
+    fn test_covers() {
+        let covers = |have: &str, need: &str| item(have, 0).covers(&crate::recipe::parse_ingredient(need));
+        assert!(covers("ham", "200g smoked ham, diced"));
+        assert!(covers("smoked ham", "ham"));
+        assert!(covers("Eggs", "1 egg"));
+        assert!(!covers("oil", "2 boiled eggs"));
+        assert!(!covers("salt", "50g unsalted butter"));
+        assert!(!covers("egg", "1 eggplant"));
+        assert!(!covers(" ", "salt"));
+        assert!(!Ingredient { deleted: true, ..item("salt", 0) }.covers(&crate::recipe::parse_ingredient("salt")));
+    }
+
+    #[test]
+    fn test_old_pantry() {
+        let old: Vec<Ingredient> = serde_json::from_str(r#"[{"id":"67e55044-10b1-426f-9247-bb680e5fe0c8","name":"ham","quantity":null,"certainty":null}]"#).unwrap();
+        assert_eq!((old[0].updated_at, old[0].deleted), (0, false));
+    }
+}
@@ -0,0 +1,238 @@
+use super::quantity::{map_quantities, Quantity, Unit};
+use super::recipe_parser::Recipe;
+use super::scale::kitchen_round;
+use super::words::mentions;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum UnitSystem {
+    Metric,
+    UsCustomary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Volume,
+    Mass,
+    Count,
+}
+
+impl Unit {
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Unit::Cup | Unit::Tablespoon | Unit::Teaspoon | Unit::Milliliter | Unit::Liter => Dimension::Volume,
+            Unit::Gram | Unit::Kilogram | Unit::Ounce | Unit::Pound => Dimension::Mass,
+            Unit::Pinch | Unit::Clove | Unit::Can | Unit::Slice => Dimension::Count,
+        }
+    }
+
+    // millilitres for volumes, grams for masses
+    fn base_factor(&self) -> Option<f64> {
+        let factor = match self {
+            Unit::Cup => 236.588,
+            Unit::Tablespoon => 14.787,
+            Unit::Teaspoon => 4.929,
+            Unit::Milliliter => 1.0,
+            Unit::Liter => 1000.0,
+            Unit::Gram => 1.0,
+            Unit::Kilogram => 1000.0,
+            Unit::Ounce => 28.3495,
+            Unit::Pound => 453.592,
+            Unit::Pinch | Unit::Clove | Unit::Can | Unit::Slice => return None,
+        };
+        Some(factor)
+    }
+}
+
+// grams per millilitre, the first entry whose key the ingredient name mentions wins, so more
+// specific names have to come before the generic ones
+const DENSITIES: &[(&str, f64)] = &[
+    ("brown sugar", 0.93),
+    ("powdered sugar", 0.51),
+    ("icing sugar", 0.51),
+    ("almond flour", 0.41),
+    ("flour", 0.53),
+    ("sugar", 0.85),
+    ("butter", 0.96),
+    ("rice", 0.78),
+    ("oats", 0.38),
+    ("cocoa", 0.42),
+    ("honey", 1.42),
+    ("maple syrup", 1.32),
+    ("salt", 1.22),
+    ("oil", 0.92),
+    ("milk", 1.03),
+    ("cream", 1.01),
+    ("yogurt", 1.03),
+    ("cheese", 0.45),
+    ("water", 1.0),
+    ("stock", 1.0),
+    ("broth", 1.0),
+];
+
+/// How many grams one millilitre of `ingredient` weighs, if we know.
+pub fn density(ingredient: &str) -> Option<f64> {
+    DENSITIES
+        .iter()
+        .find(|(name, _)| mentions(ingredient, name))
+        .map(|(_, d)| *d)
+}
+
+impl Quantity {
+    /// Converts to `unit`, going through the density table when switching between volume and
+    /// mass. Returns `None` if the units can't be converted.
+    pub fn convert_to(&self, unit: Unit, ingredient: Option<&str>) -> Option<Quantity> {
+        let from = self.unit?;
+        let to_base = from.base_factor()?;
+        let from_base = unit.base_factor()?;
+
+        let factor = match (from.dimension(), unit.dimension()) {
+            (a, b) if a == b => to_base / from_base,
+            (Dimension::Volume, Dimension::Mass) => to_base * density(ingredient?)? / from_base,
+            (Dimension::Mass, Dimension::Volume) => to_base / density(ingredient?)? / from_base,
+            _ => return None,
+        };
+
+        Some(Quantity {
+            amount: self.amount.map(|a| kitchen_round(a * factor, Some(unit))),
+            unit: Some(unit),
+        })
+    }
+
+    /// Re-expresses the quantity in the units usually used in `system`. Spoons are the same
+    /// everywhere and quantities that can't be converted are returned unchanged.
+    pub fn to_system(&self, system: UnitSystem, ingredient: Option<&str>) -> Quantity {
+        let Some(unit) = self.unit else {
+            return *self;
+        };
+        if matches!(unit, Unit::Tablespoon | Unit::Teaspoon) || unit.dimension() == Dimension::Count {
+            return *self;
+        }
+
+        let target = match (system, unit.dimension()) {
+            // metric kitchens weigh dry goods, so prefer grams when we know the density
+            (UnitSystem::Metric, Dimension::Volume) => match ingredient.and_then(density) {
+                Some(_) if !is_liquid(ingredient) => Unit::Gram,
+                _ => Unit::Milliliter,
+            },
+            (UnitSystem::Metric, Dimension::Mass) => Unit::Gram,
+            (UnitSystem::UsCustomary, Dimension::Volume) => Unit::Cup,
+            (UnitSystem::UsCustomary, Dimension::Mass) => Unit::Ounce,
+            (_, Dimension::Count) => return *self,
+        };
+
+        let converted = match self.convert_to(target, ingredient) {
+            Some(q) => q,
+            None => return *self,
+        };
+
+        converted.in_sensible_unit(system).unwrap_or(*self)
+    }
+
+    // 1500 g -> 1.5 kg, 0.1 cup -> 1 1/2 tbsp
+    fn in_sensible_unit(&self, system: UnitSystem) -> Option<Quantity> {
+        let unit = self.unit?;
+        let base = self.amount.max() * unit.base_factor()?;
+        let target = match (system, unit.dimension()) {
+            (UnitSystem::Metric, Dimension::Mass) if base >= 1000.0 => Unit::Kilogram,
+            (UnitSystem::Metric, Dimension::Volume) if base >= 1000.0 => Unit::Liter,
+            (UnitSystem::UsCustomary, Dimension::Mass) if base >= 453.592 => Unit::Pound,
+            (UnitSystem::UsCustomary, Dimension::Volume) if base < 15.0 => Unit::Teaspoon,
+            (UnitSystem::UsCustomary, Dimension::Volume) if base < 59.0 => Unit::Tablespoon,
+            _ => return Some(*self),
+        };
+
+        let factor = unit.base_factor()? / target.base_factor()?;
+        Some(Quantity {
+            amount: self.amount.map(|a| kitchen_round(a * factor, Some(target))),
+            unit: Some(target),
+        })
+    }
+}
+
+fn is_liquid(ingredient: Option<&str>) -> bool {
+    const LIQUIDS: &[&str] = &["water", "milk", "buttermilk", "oil", "stock", "broth", "cream", "juice", "vinegar", "sauce", "syrup", "wine"];
+
+    ingredient.is_some_and(|i| LIQUIDS.iter().any(|l| mentions(i, l)))
+}
+
+impl Recipe {
+    /// Rewrites ingredient quantities and the quantities mentioned in the instructions in the
+    /// units of `system`.
+    pub fn to_system(&self, system: UnitSystem) -> Recipe {
+        let mut recipe = self.clone();
+
+        for i in recipe.ingredients.iter_mut() {
+            i.quantity = i.quantity.map(|q| q.to_system(system, Some(&i.name)));
+        }
+
+        for step in recipe.instructions.iter_mut() {
+            *step = step.map_text(&|s| map_quantities(s, |q| q.to_system(system, None)));
+        }
+
+        recipe
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::{parse_quantity, MdElement, Step};
+
+    fn q(s: &str) -> Quantity {
+        parse_quantity(s).unwrap()
+    }
+
+    #[test]
+    fn test_convert_same_dimension() {
+        assert_eq!(q("1 cup").convert_to(Unit::Tablespoon, None), Some(q("16 tbsp")));
+        assert_eq!(q("2 lb").convert_to(Unit::Gram, None), Some(q("905 g")));
+        assert_eq!(q("500 ml").convert_to(Unit::Liter, None), Some(q("0.5 l")));
+    }
+
+    #[test]
+    fn test_convert_with_density() {
+        assert_eq!(q("1 cup").convert_to(Unit::Gram, Some("all-purpose flour")), Some(q("125 g")));
+        assert_eq!(q("227 g").convert_to(Unit::Cup, Some("butter")), Some(q("1 cup")));
+        assert_eq!(q("1 cup").convert_to(Unit::Gram, Some("mystery powder")), None);
+        assert_eq!(q("1 cup").convert_to(Unit::Gram, None), None);
+        // names are matched on whole words
+        assert_eq!(density("boiled eggs"), None);
+        assert_eq!(density("Unsalted Butter"), Some(0.96));
+        assert_eq!(density("saltine crackers"), None);
+        assert!(!is_liquid(Some("boiled potatoes")));
+        assert!(is_liquid(Some("buttermilk")));
+    }
+
+    #[test]
+    fn test_convert_incompatible() {
+        assert_eq!(q("2 cloves").convert_to(Unit::Gram, Some("garlic")), None);
+        assert_eq!(q("2").convert_to(Unit::Gram, Some("eggs")), None);
+    }
+
+    #[test]
+    fn test_to_system() {
+        assert_eq!(q("2 cups").to_system(UnitSystem::Metric, Some("sugar")), q("400 g"));
+        assert_eq!(q("2 cups").to_system(UnitSystem::Metric, Some("milk")), q("475 ml"));
+        assert_eq!(q("5 cups").to_system(UnitSystem::Metric, Some("water")), q("1.2 l"));
+        assert_eq!(q("1 tbsp").to_system(UnitSystem::Metric, Some("oil")), q("1 tbsp"));
+        assert_eq!(q("250 ml").to_system(UnitSystem::UsCustomary, Some("milk")), q("1 cup"));
+        assert_eq!(q("30 ml").to_system(UnitSystem::UsCustomary, Some("oil")), q("2 tbsp"));
+        assert_eq!(q("1 kg").to_system(UnitSystem::UsCustomary, Some("potatoes")), q("2.25 lb"));
+    }
+
+    #[test]
+    fn test_recipe_to_system() {
+        let recipe = Recipe {
+            name: vec![MdElement::Text("Soup".to_owned())],
+            servings: None,
+            ingredients: vec![crate::recipe::parse_ingredient("1 lb potatoes")],
+            instructions: vec![Step::new(vec![MdElement::Text("Pour in 2 cups of stock and simmer 10 minutes.".to_owned())])],
+        };
+
+        let metric = recipe.to_system(UnitSystem::Metric);
+        assert_eq!(metric.ingredients[0].quantity, Some(q("455 g")));
+        assert_eq!(metric.instructions[0].text, vec![MdElement::Text("Pour in 475 ml of stock and simmer 10 minutes.".to_owned())]);
+    }
+}
@@ -0,0 +1,487 @@
+use nom::branch::alt;
+use nom::bytes::complete::{is_not, take_till, take_till1, take_while1};
+use nom::character::complete::{anychar, char, one_of};
+use nom::combinator::{map, opt, recognize};
+use nom::multi::many0;
+use nom::sequence::{delimited, pair, preceded, terminated};
+use nom::IResult;
+
+use super::error::ParseError;
+use super::markdown::{fragment_text, MdElement};
+use super::quantity::{parse_quantity, Amount, Quantity};
+use super::recipe_parser::{Recipe, RecipeIngredient, Step};
+
+
+// a piece of a Cooklang step, https://cooklang.org/docs/spec/
+#[derive(Debug, Clone, PartialEq)]
+enum Token<'a> {
+    Text(&'a str),
+    // @name{qty%unit}(note)
+    Ingredient { name: &'a str, quantity: Option<&'a str>, note: Option<&'a str> },
+    // #name{}
+    Cookware(&'a str),
+    // ~name{qty%unit}
+    Timer(&'a str),
+}
+
+// the contents of {...}, a brace that's opened has to be closed on the same line
+fn braces(input: &str) -> IResult<&str, &str> {
+    let (rest, _) = char('{')(input)?;
+    terminated(take_till(|c| c == '}' || c == '\n'), char('}'))(rest)
+        // point at the brace that was left open rather than the end of the line
+        .map_err(|_: nom::Err<nom::error::Error<&str>>| nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Char)))
+}
+
+// "@olive oil{}" can span several words, "@salt" without braces is a single word
+fn component(input: &str) -> IResult<&str, (&str, Option<&str>)> {
+    alt((
+        pair(take_till1(|c| matches!(c, '{' | '@' | '#' | '~')), map(braces, Some)),
+        map(take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-'), |name| (name, None)),
+    ))(input)
+}
+
+fn ingredient(input: &str) -> IResult<&str, Token<'_>> {
+    let (rest, (name, quantity)) = preceded(char('@'), component)(input)?;
+    let (rest, note) = match quantity {
+        Some(_) => opt(delimited(char('('), is_not(")"), char(')')))(rest)?,
+        None => (rest, None),
+    };
+    Ok((rest, Token::Ingredient { name: name.trim(), quantity: quantity.filter(|q| !q.trim().is_empty()), note }))
+}
+
+fn cookware(input: &str) -> IResult<&str, Token<'_>> {
+    map(preceded(char('#'), component), |(name, _)| Token::Cookware(name.trim()))(input)
+}
+
+fn timer(input: &str) -> IResult<&str, Token<'_>> {
+    map(
+        preceded(char('~'), pair(take_till(|c| matches!(c, '{' | '@' | '#' | '~' | ' ')), braces)),
+        |(_, duration)| Token::Timer(duration),
+    )(input)
+}
+
+fn tokens(input: &str) -> IResult<&str, Vec<Token<'_>>> {
+    many0(alt((
+        // "\@home" is just text
+        map(preceded(char('\\'), recognize(one_of("@#~"))), Token::Text),
+        ingredient,
+        cookware,
+        timer,
+        map(take_till1(|c| matches!(c, '@' | '#' | '~' | '\\')), Token::Text),
+        // a marker that doesn't start anything, like "#1"
+        map(recognize(anychar), Token::Text),
+    )))(input)
+}
+
+// "2%cups" -> "2 cups", a '*' only pins the amount when scaling so it's dropped
+fn quantity_text(quantity: &str) -> String {
+    match quantity.split_once('%') {
+        Some((amount, unit)) => format!("{} {}", amount.trim().trim_end_matches('*'), unit.trim()),
+        None => quantity.trim().trim_end_matches('*').to_owned(),
+    }
+}
+
+fn to_ingredient(name: &str, quantity: Option<&str>, note: Option<&str>) -> RecipeIngredient {
+    let mut ingredient = RecipeIngredient {
+        quantity: None,
+        name: name.to_owned(),
+        note: note.map(|n| n.trim().to_owned()),
+    };
+
+    let Some(quantity) = quantity else {
+        return ingredient;
+    };
+    if let Some(q) = parse_quantity(&quantity_text(quantity)) {
+        ingredient.quantity = Some(q);
+        return ingredient;
+    }
+
+    // a unit we don't know, like "2%bunches", is kept in the note so nothing gets lost
+    let (quantity, unknown) = match quantity.split_once('%') {
+        Some((amount, unit)) if parse_quantity(amount).is_some() => (parse_quantity(amount), unit.trim().to_owned()),
+        _ => (None, quantity_text(quantity)),
+    };
+    ingredient.quantity = quantity;
+    ingredient.note = Some(match ingredient.note {
+        Some(note) => format!("{}, {}", unknown, note),
+        None => unknown,
+    });
+    ingredient
+}
+
+// [- block comments -] can span lines, blank them out so offsets still point into the input
+fn strip_block_comments(input: &str) -> Result<String, ParseError> {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find("[-") {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find("-]") else {
+            return Err(ParseError::at(input, input.len() - rest.len() + start, "a closing \"-]\""));
+        };
+        let comment = &rest[start..start + len + 2];
+        for c in comment.chars() {
+            match c {
+                '\n' => out.push('\n'),
+                c => out.push_str(&" ".repeat(c.len_utf8())),
+            }
+        }
+        rest = &rest[start + len + 2..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+// "= Dough", "== Filling =="
+fn section_line(line: &str) -> Option<&str> {
+    line.starts_with('=').then(|| line.trim_matches(|c: char| c == '=' || c.is_whitespace()))
+}
+
+/// Reads a Cooklang file. The recipe is called `name` unless the file has a title in its
+/// metadata, and sections become steps with the section's steps nested under them.
+pub fn parse_cooklang(name: &str, input: &str) -> Result<Recipe, ParseError> {
+    let text = strip_block_comments(input)?;
+
+    let mut recipe = Recipe {
+        name: vec![MdElement::Text(name.to_owned())],
+        servings: None,
+        ingredients: vec![],
+        instructions: vec![],
+    };
+    let mut set_metadata = |key: &str, value: &str| match key.trim().to_lowercase().as_str() {
+        "title" => recipe.name = vec![MdElement::Text(value.trim().to_owned())],
+        "servings" | "serves" => {
+            recipe.servings = value.trim().split(|c: char| !c.is_ascii_digit()).next().and_then(|n| n.parse().ok())
+        }
+        _ => {}
+    };
+
+    let mut lines: Vec<(usize, &str)> = text.split('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len() + 1;
+        Some((start, line))
+    }).collect();
+
+    // YAML front matter, only the flat "key: value" part of it
+    if lines.first().is_some_and(|(_, l)| l.trim_end() == "---") {
+        if let Some(end) = lines.iter().skip(1).position(|(_, l)| l.trim_end() == "---") {
+            for (_, line) in &lines[1..=end] {
+                if let Some((key, value)) = line.split_once(':') {
+                    set_metadata(key, value);
+                }
+            }
+            lines.drain(..end + 2);
+        }
+    }
+
+    let mut steps: Vec<Step> = vec![];
+    let mut section: Option<Step> = None;
+    let mut paragraph: Vec<String> = vec![];
+    let mut ingredients = vec![];
+
+    let flush = |paragraph: &mut Vec<String>, section: &mut Option<Step>, steps: &mut Vec<Step>| {
+        if paragraph.is_empty() {
+            return;
+        }
+        let step = Step::new(vec![MdElement::Text(paragraph.join(" "))]);
+        paragraph.clear();
+        match section {
+            Some(s) => s.substeps.push(step),
+            None => steps.push(step),
+        }
+    };
+
+    for (offset, line) in lines {
+        let line = match line.find("--") {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let trimmed = line.trim();
+
+        if let Some(meta) = trimmed.strip_prefix(">>") {
+            if let Some((key, value)) = meta.split_once(':') {
+                set_metadata(key, value);
+            }
+            continue;
+        }
+        if trimmed.is_empty() {
+            flush(&mut paragraph, &mut section, &mut steps);
+            continue;
+        }
+        if let Some(name) = section_line(trimmed) {
+            flush(&mut paragraph, &mut section, &mut steps);
+            steps.extend(section.take());
+            section = (!name.is_empty()).then(|| Step::new(vec![MdElement::Text(name.to_owned())]));
+            continue;
+        }
+
+        let (_, tokens) = tokens(line).map_err(|e| match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => {
+                ParseError::at(input, offset + line.len() - e.input.len(), "a closing \"}\"")
+            }
+            nom::Err::Incomplete(_) => ParseError::at(input, offset + line.len(), "a closing \"}\""),
+        })?;
+
+        let mut step_text = String::new();
+        for token in tokens {
+            match token {
+                Token::Text(t) => step_text.push_str(t),
+                Token::Ingredient { name, quantity, note } => {
+                    step_text.push_str(name);
+                    ingredients.push(to_ingredient(name, quantity, note));
+                }
+                Token::Cookware(name) => step_text.push_str(name),
+                Token::Timer(duration) => step_text.push_str(&quantity_text(duration)),
+            }
+        }
+        paragraph.push(step_text.trim().to_owned());
+    }
+
+    flush(&mut paragraph, &mut section, &mut steps);
+    steps.extend(section.take());
+
+    if steps.is_empty() {
+        return Err(ParseError::at(input, input.len(), "a recipe step"));
+    }
+
+    recipe.ingredients = ingredients;
+    recipe.instructions = steps;
+    Ok(recipe)
+}
+
+enum Block {
+    Section(String),
+    Step(String),
+}
+
+// "#1 tip" would be read back as cookware
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '@' | '#' | '~') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// steps with sub-steps become sections, an unnamed section goes back to the top level
+fn blocks(instructions: &[Step]) -> Vec<Block> {
+    fn flatten(step: &Step, blocks: &mut Vec<Block>) {
+        blocks.push(Block::Step(escape(&fragment_text(&step.text))));
+        step.substeps.iter().for_each(|s| flatten(s, blocks));
+    }
+
+    let mut blocks = vec![];
+    let mut in_section = false;
+    for step in instructions {
+        if !step.substeps.is_empty() {
+            blocks.push(Block::Section(fragment_text(&step.text)));
+            step.substeps.iter().for_each(|s| flatten(s, &mut blocks));
+            in_section = true;
+        } else {
+            if in_section {
+                blocks.push(Block::Section(String::new()));
+                in_section = false;
+            }
+            blocks.push(Block::Step(escape(&fragment_text(&step.text))));
+        }
+    }
+    blocks
+}
+
+// where `name` appears as a whole word, ignoring case
+fn find_word(text: &str, name: &str, taken: &[(usize, usize)]) -> Option<(usize, usize)> {
+    let haystack = text.to_ascii_lowercase();
+    let needle = name.to_ascii_lowercase();
+    if needle.is_empty() {
+        return None;
+    }
+
+    haystack.match_indices(&needle).map(|(start, m)| (start, start + m.len())).find(|&(start, end)| {
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        !before.is_some_and(char::is_alphanumeric)
+            && !after.is_some_and(char::is_alphanumeric)
+            && !taken.iter().any(|&(s, e)| start < e && s < end)
+    })
+}
+
+fn amount_markup(amount: Amount) -> String {
+    match amount {
+        Amount::Single(a) => a.to_string(),
+        Amount::Range(a, b) => format!("{}-{}", a, b),
+    }
+}
+
+fn ingredient_markup(ingredient: &RecipeIngredient) -> String {
+    let quantity = match ingredient.quantity {
+        Some(Quantity { amount, unit: Some(unit) }) => format!("{}%{}", amount_markup(amount), unit.name(amount.max() > 1.0)),
+        Some(Quantity { amount, unit: None }) => amount_markup(amount),
+        None => String::new(),
+    };
+    let note = ingredient.note.as_ref().map(|n| format!("({})", n)).unwrap_or_default();
+    format!("@{}{{{}}}{}", ingredient.name, quantity, note)
+}
+
+impl Recipe {
+    /// Writes the recipe as Cooklang. Each ingredient is marked where the steps first mention it,
+    /// the ones the steps never mention get a step of their own up front.
+    pub fn to_cooklang(&self) -> String {
+        let mut blocks = blocks(&self.instructions);
+
+        // (block, start, end) of every mention that gets marked up, per ingredient
+        let mut taken: Vec<Vec<(usize, usize)>> = blocks.iter().map(|_| vec![]).collect();
+        let mut mentions: Vec<(usize, usize, usize, &RecipeIngredient)> = vec![];
+        let mut unmentioned = vec![];
+        for ingredient in &self.ingredients {
+            let found = blocks.iter().enumerate().find_map(|(b, block)| match block {
+                Block::Step(text) => find_word(text, &ingredient.name, &taken[b]).map(|(s, e)| (b, s, e)),
+                Block::Section(_) => None,
+            });
+            match found {
+                Some((b, s, e)) => {
+                    taken[b].push((s, e));
+                    mentions.push((b, s, e, ingredient));
+                }
+                None => unmentioned.push(ingredient_markup(ingredient)),
+            }
+        }
+
+        // back to front so the offsets stay valid
+        mentions.sort_by_key(|m| std::cmp::Reverse((m.0, m.1)));
+        for (b, start, end, ingredient) in mentions {
+            if let Block::Step(text) = &mut blocks[b] {
+                text.replace_range(start..end, &ingredient_markup(ingredient));
+            }
+        }
+
+        let mut out = format!(">> title: {}\n", fragment_text(&self.name));
+        if let Some(servings) = self.servings {
+            out.push_str(&format!(">> servings: {}\n", servings));
+        }
+        if !unmentioned.is_empty() {
+            out.push_str(&format!("\nYou will need {}.\n", unmentioned.join(", ")));
+        }
+        for block in blocks.drain(..) {
+            match block {
+                Block::Section(name) if name.is_empty() => out.push_str("\n=\n"),
+                Block::Section(name) => out.push_str(&format!("\n== {} ==\n", name)),
+                Block::Step(text) => out.push_str(&format!("\n{}\n", text)),
+            }
+        }
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::Unit;
+
+    const PANCAKES: &str = "---
+title: Pancakes
+servings: 4
+---
+-- a family favourite
+>> source: grandma
+
+Crack @eggs{3} into a #large bowl{} and whisk in @milk{250%ml}.
+Add @plain flour{125%g}(sifted) and a pinch of @salt.
+
+== Cooking ==
+Heat the #frying pan{} and fry each pancake for ~{2%minutes}. [- flip
+once! -]
+
+Serve with @lemon{1%wedge}.
+";
+
+    fn text(s: &str) -> Vec<MdElement> {
+        vec![MdElement::Text(s.to_owned())]
+    }
+
+    #[test]
+    fn test_parse_cooklang() {
+        let r = parse_cooklang("pancakes", PANCAKES).unwrap();
+
+        assert_eq!(r.name, text("Pancakes"));
+        assert_eq!(r.servings, Some(4));
+        assert_eq!(r.ingredients.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), vec!["eggs", "milk", "plain flour", "salt", "lemon"]);
+        assert_eq!(r.ingredients[1].quantity, Some(Quantity { amount: Amount::Single(250.0), unit: Some(Unit::Milliliter) }));
+        assert_eq!(r.ingredients[2].note.as_deref(), Some("sifted"));
+        assert_eq!(r.ingredients[3].quantity, None);
+        assert_eq!(r.ingredients[4].note.as_deref(), Some("wedge"));
+        assert_eq!(r.instructions, vec![
+            Step::new(text("Crack eggs into a large bowl and whisk in milk. Add plain flour and a pinch of salt.")),
+            Step {
+                text: text("Cooking"),
+                substeps: vec![
+                    Step::new(text("Heat the frying pan and fry each pancake for 2 minutes.")),
+                    Step::new(text("Serve with lemon.")),
+                ],
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_cooklang_stray_markers() {
+        let r = parse_cooklang("x", "Bake in oven #2 at 200 ~ ish, @ home.").unwrap();
+        assert_eq!(r.instructions, vec![Step::new(text("Bake in oven 2 at 200 ~ ish, @ home."))]);
+        assert_eq!(r.ingredients, vec![]);
+    }
+
+    #[test]
+    fn test_parse_cooklang_errors() {
+        let e = parse_cooklang("x", ">> title: x\n\nAdd @salt{1%tsp and stir.").unwrap_err();
+        assert_eq!((e.line, e.column), (3, 10));
+        assert_eq!(e.expected, "a closing \"}\"");
+
+        let e = parse_cooklang("x", "Stir. [- never closed").unwrap_err();
+        assert_eq!((e.line, e.column), (1, 7));
+
+        assert!(parse_cooklang("x", "-- only a comment\n>> servings: 2").is_err());
+    }
+
+    #[test]
+    fn test_to_cooklang() {
+        let recipe = Recipe {
+            name: text("Ham Hash"),
+            servings: Some(2),
+            ingredients: vec![
+                crate::recipe::parse_ingredient("200 g ham, diced"),
+                crate::recipe::parse_ingredient("1 1/2 cups potatoes"),
+                crate::recipe::parse_ingredient("oil"),
+            ],
+            instructions: vec![
+                Step::new(text("Fry the Potatoes until golden.")),
+                Step { text: text("Finish"), substeps: vec![Step::new(text("Add the ham, more potatoes."))] },
+                Step::new(text("Serve.")),
+            ],
+        };
+
+        assert_eq!(recipe.to_cooklang(), ">> title: Ham Hash\n>> servings: 2\n\nYou will need @oil{}.\n\nFry the @potatoes{1.5%cups} until golden.\n\n== Finish ==\n\nAdd the @ham{200%g}(diced), more potatoes.\n\n=\n\nServe.\n");
+
+        let back = parse_cooklang("", &recipe.to_cooklang()).unwrap();
+        assert_eq!(back.ingredients.len(), 3);
+        assert_eq!(back.instructions[2], Step { text: text("Finish"), substeps: vec![Step::new(text("Add the ham, more potatoes."))] });
+    }
+
+    #[test]
+    fn test_cooklang_round_trip() {
+        let r = parse_cooklang("pancakes", PANCAKES).unwrap();
+        assert_eq!(parse_cooklang("", &r.to_cooklang()).unwrap(), r);
+
+        // markers in the text are escaped rather than read back as markup
+        let r = Recipe {
+            name: text("Tips"),
+            servings: None,
+            ingredients: vec![crate::recipe::parse_ingredient("salt")],
+            instructions: vec![Step::new(text("Email @home the #1 tip: fry ~5 minutes with salt."))],
+        };
+        assert!(r.to_cooklang().contains("Email \\@home the \\#1 tip: fry \\~5 minutes with @salt{}."));
+        assert_eq!(parse_cooklang("", &r.to_cooklang()).unwrap(), r);
+    }
+}
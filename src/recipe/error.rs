@@ -0,0 +1,87 @@
+use std::fmt::{self, Display};
+
+
+const SNIPPET_LEN: usize = 40;
+
+/// Where and why `parse` gave up on its input.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ParseError {
+    /// Byte offset into the input.
+    pub offset: usize,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+    pub expected: String,
+    /// The input starting at `offset`, cut short after a few dozen characters.
+    pub snippet: String,
+}
+
+impl ParseError {
+    pub(crate) fn at(input: &str, offset: usize, expected: &str) -> ParseError {
+        // parsers fail at the newline in front of the thing they wanted, point at the thing itself
+        let skipped = input[offset..].len() - input[offset..].trim_start().len();
+        let offset = match offset + skipped {
+            o if o == input.len() => offset,
+            o => o,
+        };
+
+        let before = &input[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+
+        ParseError {
+            offset,
+            line,
+            column,
+            expected: expected.to_owned(),
+            snippet: input[offset..].chars().take(SNIPPET_LEN).collect(),
+        }
+    }
+
+    pub(crate) fn from_nom(input: &str, err: nom::Err<nom::error::Error<&str>>, expected: &str) -> ParseError {
+        let offset = match err {
+            nom::Err::Error(e) | nom::Err::Failure(e) => input.len() - e.input.len(),
+            nom::Err::Incomplete(_) => input.len(),
+        };
+        ParseError::at(input, offset, expected)
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {} at line {}, column {}", self.expected, self.line, self.column)?;
+        match self.snippet.is_empty() {
+            true => write!(f, ", found end of input"),
+            false => write!(f, ", found {:?}", self.snippet),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let e = ParseError::at("one\ntwo\nthree", 5, "something");
+        assert_eq!((e.offset, e.line, e.column), (5, 2, 2));
+        assert_eq!(e.snippet, "wo\nthree");
+    }
+
+    #[test]
+    fn test_skips_leading_whitespace() {
+        let e = ParseError::at("intro\n\n   - item", 5, "something");
+        assert_eq!((e.offset, e.line, e.column), (10, 3, 4));
+        assert_eq!(e.snippet, "- item");
+    }
+
+    #[test]
+    fn test_display() {
+        let e = ParseError::at("intro\n", 5, "a recipe");
+        assert_eq!(e.to_string(), "expected a recipe at line 1, column 6, found \"\\n\"");
+    }
+}
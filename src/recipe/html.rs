@@ -0,0 +1,405 @@
+use scraper::{ElementRef, Html, Node, Selector};
+
+use super::markdown::MdElement;
+use super::recipe_parser::{find_servings, parse_ingredient, section_name, Recipe, Section, Step};
+use super::schema_org::{HowToSection, HowToStep, Instruction, OneOrMany, SchemaRecipe, TextOrNumber};
+
+
+const BLOCK_TAGS: &[&str] = &["p", "div", "li", "br", "ul", "ol", "section", "h1", "h2", "h3", "h4", "h5", "h6", "tr"];
+
+/// Reads the recipes out of a saved web page. Embedded JSON-LD is the most reliable source, then
+/// microdata, and as a last resort the page is searched for an ingredient and an instruction list.
+/// Nothing referenced by the page is fetched.
+pub fn recipes_from_html(html: &str) -> Vec<Recipe> {
+    let document = Html::parse_document(html);
+
+    let from_json_ld = json_ld_recipes(&document);
+    if !from_json_ld.is_empty() {
+        return from_json_ld;
+    }
+
+    let from_microdata = microdata_recipes(&document);
+    if !from_microdata.is_empty() {
+        return from_microdata;
+    }
+
+    heuristic_recipe(&document).into_iter().collect()
+}
+
+fn selector(s: &str) -> Selector {
+    Selector::parse(s).expect("selectors are valid")
+}
+
+fn json_ld_recipes(document: &Html) -> Vec<Recipe> {
+    document
+        .select(&selector(r#"script[type="application/ld+json"]"#))
+        // one broken script shouldn't hide the recipe in the next one
+        .filter_map(|script| SchemaRecipe::find_all(&script.text().collect::<String>()).ok())
+        .flatten()
+        .map(Recipe::from)
+        .filter(|r| !r.ingredients.is_empty() || !r.instructions.is_empty())
+        .collect()
+}
+
+// the text of an element with whitespace collapsed, and a line break wherever a block ends
+fn block_text(element: ElementRef<'_>) -> String {
+    fn walk(element: ElementRef<'_>, out: &mut String) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => {
+                    for (i, word) in text.split_whitespace().enumerate() {
+                        let starts_with_space = text.starts_with(char::is_whitespace);
+                        if (i > 0 || starts_with_space) && !out.is_empty() && !out.ends_with([' ', '\n']) {
+                            out.push(' ');
+                        }
+                        out.push_str(word);
+                    }
+                    if text.ends_with(char::is_whitespace) && !out.is_empty() && !out.ends_with([' ', '\n']) {
+                        out.push(' ');
+                    }
+                }
+                Node::Element(e) if ["script", "style"].contains(&e.name()) => {}
+                Node::Element(e) => {
+                    let block = BLOCK_TAGS.contains(&e.name());
+                    if block && !out.is_empty() && !out.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    walk(ElementRef::wrap(child).expect("is an element"), out);
+                    if block && !out.is_empty() && !out.ends_with('\n') {
+                        out.push('\n');
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut out = String::new();
+    walk(element, &mut out);
+    out.lines().map(str::trim).filter(|l| !l.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+fn inline_text(element: ElementRef<'_>) -> String {
+    block_text(element).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// `SchemaRecipe` strings are HTML, like they are in JSON-LD, so text taken out of the page goes
+// back in escaped
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;")
+}
+
+/// The `itemprop`s of an `itemscope`, without the ones that belong to scopes nested in it.
+fn item_props(scope: ElementRef<'_>) -> Vec<(&str, ElementRef<'_>)> {
+    fn walk<'a>(element: ElementRef<'a>, props: &mut Vec<(&'a str, ElementRef<'a>)>) {
+        for child in element.children().filter_map(ElementRef::wrap) {
+            if let Some(names) = child.value().attr("itemprop") {
+                props.extend(names.split_whitespace().map(|n| (n, child)));
+            }
+            if child.value().attr("itemscope").is_none() {
+                walk(child, props);
+            }
+        }
+    }
+
+    let mut props = vec![];
+    walk(scope, &mut props);
+    props
+}
+
+// https://html.spec.whatwg.org/multipage/microdata.html#values
+fn prop_value(element: ElementRef<'_>) -> String {
+    let e = element.value();
+    let attr = match e.name() {
+        "meta" => e.attr("content"),
+        "time" => e.attr("datetime"),
+        "data" | "meter" => e.attr("value"),
+        _ => e.attr("content"),
+    };
+    match attr {
+        Some(value) => escape_html(value.trim()),
+        None => escape_html(&inline_text(element)),
+    }
+}
+
+fn prop<'a>(props: &[(&str, ElementRef<'a>)], name: &str) -> Option<ElementRef<'a>> {
+    all_props(props, name).next()
+}
+
+fn all_props<'a, 'p>(props: &'p [(&str, ElementRef<'a>)], name: &'p str) -> impl Iterator<Item = ElementRef<'a>> + 'p {
+    props.iter().filter(move |(n, _)| *n == name).map(|(_, e)| *e)
+}
+
+fn microdata_instruction(element: ElementRef<'_>) -> Vec<Instruction> {
+    if element.value().attr("itemscope").is_none() {
+        let items: Vec<_> = element.select(&selector("li")).collect();
+        return match items.is_empty() {
+            true => vec![Instruction::Text(escape_html(&block_text(element)))],
+            false => items.into_iter().map(|li| Instruction::Text(escape_html(&inline_text(li)))).collect(),
+        };
+    }
+
+    let props = item_props(element);
+    let name = prop(&props, "name").map(prop_value);
+    let elements: Vec<_> = all_props(&props, "itemListElement").collect();
+    if elements.is_empty() {
+        vec![Instruction::Step(HowToStep {
+            kind: element.value().attr("itemtype").map(str::to_owned),
+            text: Some(prop(&props, "text").map(prop_value).unwrap_or_else(|| escape_html(&inline_text(element)))),
+            name,
+        })]
+    } else {
+        vec![Instruction::Section(Box::new(HowToSection {
+            kind: element.value().attr("itemtype").map(str::to_owned),
+            name,
+            item_list_element: OneOrMany::Many(elements.into_iter().flat_map(microdata_instruction).collect()),
+        }))]
+    }
+}
+
+fn microdata_recipes(document: &Html) -> Vec<Recipe> {
+    document
+        .select(&selector("[itemscope][itemtype]"))
+        .filter(|scope| scope.value().attr("itemtype").unwrap_or("").split_whitespace().any(|t| t.ends_with("schema.org/Recipe")))
+        .map(|scope| {
+            let props = item_props(scope);
+            let all = |name| all_props(&props, name);
+
+            SchemaRecipe {
+                name: prop(&props, "name").map(prop_value),
+                description: prop(&props, "description").map(prop_value),
+                recipe_ingredient: OneOrMany::Many(all("recipeIngredient").chain(all("ingredients")).map(prop_value).collect()),
+                recipe_instructions: OneOrMany::Many(all("recipeInstructions").flat_map(microdata_instruction).collect()),
+                recipe_yield: OneOrMany::Many(all("recipeYield").map(|e| TextOrNumber::Text(prop_value(e))).collect()),
+                total_time: prop(&props, "totalTime").map(prop_value),
+                prep_time: prop(&props, "prepTime").map(prop_value),
+                cook_time: prop(&props, "cookTime").map(prop_value),
+                ..SchemaRecipe::default()
+            }
+        })
+        .map(Recipe::from)
+        .filter(|r| !r.ingredients.is_empty() || !r.instructions.is_empty())
+        .collect()
+}
+
+// "Ingredients", "Method:", "Ingredients (serves 4)"
+fn section_of(heading: &str) -> Option<Section> {
+    match section_name(heading.trim()) {
+        Ok((rest, section)) if rest.is_empty() || rest.starts_with(' ') => Some(section),
+        _ => None,
+    }
+}
+
+fn is_list(element: ElementRef<'_>) -> bool {
+    ["ul", "ol"].contains(&element.value().name())
+}
+
+// the items of a list, with nested lists as substeps
+fn list_steps(list: ElementRef<'_>) -> Vec<Step> {
+    list.children()
+        .filter_map(ElementRef::wrap)
+        .filter(|li| li.value().name() == "li")
+        .map(|li| {
+            let mut text = String::new();
+            let mut substeps = vec![];
+            for child in li.children() {
+                match ElementRef::wrap(child) {
+                    Some(e) if is_list(e) => substeps.extend(list_steps(e)),
+                    Some(e) => text.push_str(&format!(" {} ", inline_text(e))),
+                    None => {
+                        if let Node::Text(t) = child.value() {
+                            text.push_str(t);
+                        }
+                    }
+                }
+            }
+            Step { text: vec![MdElement::Text(text.split_whitespace().collect::<Vec<_>>().join(" "))], substeps }
+        })
+        .filter(|s| s.text != [MdElement::Text(String::new())] || !s.substeps.is_empty())
+        .collect()
+}
+
+fn flatten(steps: Vec<Step>) -> Vec<Step> {
+    steps.into_iter().flat_map(|s| std::iter::once(Step::new(s.text)).chain(flatten(s.substeps))).collect()
+}
+
+/// An ingredient list and an instruction list found under headings that name them.
+fn heuristic_recipe(document: &Html) -> Option<Recipe> {
+    let skipped = ["nav", "header", "footer", "aside", "script", "style"];
+    let mut section = None;
+    let mut ingredients = vec![];
+    let mut instructions = vec![];
+
+    let elements = document
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|e| !e.ancestors().filter_map(ElementRef::wrap).any(|a| skipped.contains(&a.value().name()) || is_list(a)));
+
+    for element in elements {
+        let name = element.value().name();
+        if name.len() == 2 && name.starts_with('h') && name[1..].parse::<u8>().is_ok() {
+            // any other heading ends the section
+            section = section_of(&inline_text(element));
+        } else if is_list(element) {
+            match section {
+                Some(Section::Ingredients) => ingredients.extend(flatten(list_steps(element))),
+                Some(Section::Instructions) => instructions.extend(list_steps(element)),
+                None => {}
+            }
+        } else if ["p", "strong", "b", "span", "div", "dt", "label"].contains(&name) {
+            let text = inline_text(element);
+            // only short labels, not paragraphs or containers that mention the word
+            if text.len() <= 40 {
+                if let Some(s) = section_of(&text) {
+                    section = Some(s);
+                }
+            }
+        }
+    }
+
+    if ingredients.is_empty() || instructions.is_empty() {
+        return None;
+    }
+
+    let name = document
+        .select(&selector("h1"))
+        .chain(document.select(&selector("title")))
+        .map(inline_text)
+        .find(|t| !t.is_empty())
+        .unwrap_or_default();
+
+    Some(Recipe {
+        name: vec![MdElement::Text(name)],
+        servings: find_servings(&inline_text(document.root_element())),
+        ingredients: ingredients.iter().map(|s| parse_ingredient(&super::markdown::fragment_text(&s.text))).collect(),
+        instructions,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::{fragment_text, Amount, Quantity, Unit};
+
+    fn names(recipes: &[Recipe]) -> Vec<String> {
+        recipes.iter().map(|r| fragment_text(&r.name)).collect()
+    }
+
+    fn steps(recipe: &Recipe) -> Vec<String> {
+        recipe.instructions.iter().map(|s| fragment_text(&s.text)).collect()
+    }
+
+    #[test]
+    fn test_json_ld() {
+        let html = r#"<html><head>
+            <script type="application/ld+json">{ not json</script>
+            <script type="application/ld+json">
+                {"@context": "https://schema.org", "@type": "Recipe", "name": "Pancakes",
+                 "recipeIngredient": ["2 eggs", "1 cup flour"], "recipeInstructions": "Mix. Fry."}
+            </script>
+        </head><body itemscope itemtype="https://schema.org/Recipe"><h1 itemprop="name">Ignored</h1></body></html>"#;
+
+        let recipes = recipes_from_html(html);
+
+        assert_eq!(names(&recipes), vec!["Pancakes"]);
+        assert_eq!(recipes[0].ingredients[1].quantity, Some(Quantity { amount: Amount::Single(1.0), unit: Some(Unit::Cup) }));
+    }
+
+    #[test]
+    fn test_microdata() {
+        let html = r#"<html><body>
+            <div itemscope itemtype="http://schema.org/Recipe">
+                <h1 itemprop="name">Fish &amp; Chips</h1>
+                <meta itemprop="recipeYield" content="4 servings">
+                <ul>
+                    <li itemprop="recipeIngredient">500 g <b>cod</b></li>
+                    <li itemprop="recipeIngredient">4 potatoes, cut into chips</li>
+                </ul>
+                <div itemprop="recipeInstructions" itemscope itemtype="http://schema.org/HowToSection">
+                    <span itemprop="name">Frying</span>
+                    <div itemprop="itemListElement" itemscope itemtype="http://schema.org/HowToStep">
+                        <p itemprop="text">Fry the chips.</p>
+                    </div>
+                    <div itemprop="itemListElement" itemscope itemtype="http://schema.org/HowToStep">
+                        <p itemprop="text">Fry the fish &lt;3 minutes.</p>
+                    </div>
+                </div>
+                <div itemprop="author" itemscope itemtype="http://schema.org/Person"><span itemprop="name">Not the name</span></div>
+            </div>
+        </body></html>"#;
+
+        let recipes = recipes_from_html(html);
+
+        assert_eq!(names(&recipes), vec!["Fish & Chips"]);
+        assert_eq!(recipes[0].servings, Some(4));
+        assert_eq!(recipes[0].ingredients[0].name, "cod");
+        assert_eq!(recipes[0].ingredients[1].note.as_deref(), Some("cut into chips"));
+        assert_eq!(steps(&recipes[0]), vec!["Frying"]);
+        assert_eq!(
+            recipes[0].instructions[0].substeps.iter().map(|s| fragment_text(&s.text)).collect::<Vec<_>>(),
+            vec!["Fry the chips.", "Fry the fish <3 minutes."],
+        );
+    }
+
+    #[test]
+    fn test_microdata_instructions_as_text() {
+        let html = r#"<div itemscope itemtype="https://schema.org/Recipe">
+            <span itemprop="name">Toast</span>
+            <span itemprop="ingredients">1 slice bread</span>
+            <div itemprop="recipeInstructions"><p>Toast the
+                bread.</p><p>Eat it.</p></div>
+        </div>"#;
+
+        assert_eq!(steps(&recipes_from_html(html)[0]), vec!["Toast the bread.", "Eat it."]);
+    }
+
+    #[test]
+    fn test_heuristic() {
+        let html = r#"<html><head><title>Best Soup | Some Blog</title></head><body>
+            <nav><h2>Ingredients</h2><ul><li>Shop</li></ul></nav>
+            <h1>Best Soup</h1>
+            <p>My grandmother made this soup every winter. Serves 6.</p>
+            <ul><li>Share on social</li></ul>
+            <h3>Ingredients:</h3>
+            <ul><li>1 onion</li><li>2 cups stock</li></ul>
+            <p><strong>Method</strong></p>
+            <ol>
+                <li>Chop the onion.</li>
+                <li>Simmer in the stock.<ul><li>About 20 minutes.</li></ul></li>
+            </ol>
+            <h3>Comments</h3>
+            <ul><li>Great soup!</li></ul>
+        </body></html>"#;
+
+        let recipes = recipes_from_html(html);
+
+        assert_eq!(names(&recipes), vec!["Best Soup"]);
+        assert_eq!(recipes[0].servings, Some(6));
+        assert_eq!(recipes[0].ingredients.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), vec!["onion", "stock"]);
+        assert_eq!(recipes[0].instructions, vec![
+            Step::new(vec![MdElement::Text("Chop the onion.".to_owned())]),
+            Step {
+                text: vec![MdElement::Text("Simmer in the stock.".to_owned())],
+                substeps: vec![Step::new(vec![MdElement::Text("About 20 minutes.".to_owned())])],
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_no_recipe() {
+        assert_eq!(recipes_from_html("<html><body><h1>About us</h1><ul><li>Contact</li></ul></body></html>"), vec![]);
+        assert_eq!(recipes_from_html(""), vec![]);
+    }
+
+    #[test]
+    fn test_find_servings() {
+        assert_eq!(find_servings("Servings: 4-6 people"), Some(4));
+        assert_eq!(find_servings("Yield 12 cookies"), Some(12));
+        assert_eq!(find_servings("Makes 24"), Some(24));
+        assert_eq!(section_of("You will need:"), Some(Section::Ingredients));
+        assert_eq!(section_of("Stepson"), None);
+        assert_eq!(find_servings("It serves no purpose, 4 ever"), None);
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+
+use super::error::ParseError;
+use super::markdown::{fragment_text, inline, to_markdown};
+use super::recipe_parser::{parse_ingredient, sublist, Recipe, Step};
+
+
+/// A recipe the way models write it when asked for JSON. Everything is a plain string, which then
+/// goes through the same ingredient and inline Markdown parsing as a Markdown answer. Substeps
+/// are a Markdown list under the text of their step.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonRecipe {
+    pub name: String,
+    pub servings: Option<u32>,
+    pub ingredients: Vec<String>,
+    pub instructions: Vec<String>,
+}
+
+/// The whole answer. Structured output wants an object at the top, not a list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonAnswer {
+    pub recipes: Vec<JsonRecipe>,
+}
+
+impl JsonAnswer {
+    /// The JSON schema of `JsonAnswer`, in the subset OpenAI's strict structured output accepts.
+    pub fn schema() -> serde_json::Value {
+        let strings = serde_json::json!({ "type": "array", "items": { "type": "string" } });
+
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "recipes": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string" },
+                            "servings": { "type": ["integer", "null"] },
+                            "ingredients": strings,
+                            "instructions": strings,
+                        },
+                        "required": ["name", "servings", "ingredients", "instructions"],
+                        "additionalProperties": false,
+                    },
+                },
+            },
+            "required": ["recipes"],
+            "additionalProperties": false,
+        })
+    }
+}
+
+impl From<&Recipe> for JsonRecipe {
+    fn from(recipe: &Recipe) -> JsonRecipe {
+        JsonRecipe {
+            name: to_markdown(&recipe.name),
+            servings: recipe.servings,
+            ingredients: recipe.ingredients.iter().map(|i| i.to_string()).collect(),
+            instructions: recipe.instructions.iter().map(step_markdown).collect(),
+        }
+    }
+}
+
+// "Make the sauce:\n  - Whisk the eggs.\n  - Stir in the cheese."
+fn step_markdown(step: &Step) -> String {
+    fn add_substeps(text: &mut String, steps: &[Step], indent: usize) {
+        for step in steps {
+            text.push_str(&format!("\n{:indent$}- {}", "", to_markdown(&step.text), indent = indent));
+            add_substeps(text, &step.substeps, indent + 2);
+        }
+    }
+
+    let mut text = to_markdown(&step.text);
+    add_substeps(&mut text, &step.substeps, 2);
+    text
+}
+
+fn parse_step(text: &str) -> Step {
+    let substeps = text.find('\n').and_then(|i| Some((&text[..i], sublist(&text[i..])?)));
+    match substeps {
+        Some((text, substeps)) => Step { text: inline(text.trim()), substeps },
+        None => Step::new(inline(text)),
+    }
+}
+
+impl From<JsonRecipe> for Recipe {
+    fn from(json: JsonRecipe) -> Recipe {
+        Recipe {
+            name: inline(json.name.trim()),
+            servings: json.servings.filter(|s| *s > 0),
+            ingredients: json.ingredients.iter().map(|i| i.trim()).filter(|i| !i.is_empty()).map(parse_ingredient).collect(),
+            instructions: json.instructions.iter().map(|i| i.trim()).filter(|i| !i.is_empty()).map(parse_step).collect(),
+        }
+    }
+}
+
+/// Reads an answer written as a `JsonAnswer`. Models like to wrap it in a code fence, which is
+/// fine, but a recipe without a name, ingredients or instructions makes the whole answer invalid.
+pub fn parse_json(input: &str) -> Result<Vec<Recipe>, ParseError> {
+    let trimmed = input.trim();
+    let json = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    let start = json.as_ptr() as usize - input.as_ptr() as usize;
+
+    let answer: JsonAnswer = serde_json::from_str(json).map_err(|e| {
+        // serde counts lines and columns from 1, and a column of 0 means the end of a line
+        let line_start: usize = json.split_inclusive('\n').take(e.line().saturating_sub(1)).map(str::len).sum();
+        let offset = (start + line_start + e.column().saturating_sub(1)).min(input.len());
+        ParseError::at(input, offset, &format!("recipes as JSON ({})", e))
+    })?;
+
+    if answer.recipes.is_empty() {
+        return Err(ParseError::at(input, start, "at least one recipe"));
+    }
+
+    answer
+        .recipes
+        .into_iter()
+        .map(Recipe::from)
+        .map(|r| match r {
+            r if fragment_text(&r.name).is_empty() => Err(ParseError::at(input, start, "a name for every recipe")),
+            r if r.ingredients.is_empty() => Err(ParseError::at(input, start, "ingredients for every recipe")),
+            r if r.instructions.is_empty() => Err(ParseError::at(input, start, "instructions for every recipe")),
+            r => Ok(r),
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::{parse, MdElement};
+
+    const ANSWER: &str = r#"{"recipes": [
+        {"name": "Ham **Hash**", "servings": 2, "ingredients": ["200 g ham", " 3 potatoes"], "instructions": ["Dice everything.", "Fry until golden."]},
+        {"name": "Chips", "servings": null, "ingredients": ["3 potatoes"], "instructions": ["Cut and fry."]}
+    ]}"#;
+
+    #[test]
+    fn test_parse_json() {
+        let recipes = parse_json(ANSWER).unwrap();
+
+        assert_eq!(recipes.len(), 2);
+        assert_eq!(recipes[0].name, vec![MdElement::Text("Ham ".to_owned()), MdElement::Strong(vec![MdElement::Text("Hash".to_owned())])]);
+        assert_eq!(recipes[0].servings, Some(2));
+        assert_eq!(recipes[0].ingredients[0], parse_ingredient("200 g ham"));
+        assert_eq!(recipes[0].ingredients[1].name, "potatoes");
+        assert_eq!(recipes[1].servings, None);
+        assert_eq!(recipes[1].instructions, vec![Step::new(vec![MdElement::Text("Cut and fry.".to_owned())])]);
+    }
+
+    #[test]
+    fn test_code_fence() {
+        assert_eq!(parse_json(&format!("```json\n{}\n```\n", ANSWER)).unwrap(), parse_json(ANSWER).unwrap());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = parse_json("1. Ham Hash\nIngredients:\n- ham").unwrap_err();
+        assert!(err.expected.starts_with("recipes as JSON"), "{}", err);
+
+        let err = parse_json("{\"recipes\": [\n  {\"name\": \"Toast\", \"servings\": \"two\"}]}").unwrap_err();
+        assert_eq!(err.line, 2);
+
+        assert!(parse_json(r#"{"recipes": []}"#).is_err());
+        let err = parse_json(r#"{"recipes": [{"name": "Toast", "servings": 1, "ingredients": ["bread"], "instructions": [" "]}]}"#).unwrap_err();
+        assert_eq!(err.expected, "instructions for every recipe");
+    }
+
+    #[test]
+    fn test_round_trip_through_markdown() {
+        let markdown = "Here you go:\n\n1. Ham Hash\nServes: 2\nIngredients:\n- 200 g ham\n- 3 potatoes\nInstructions:\n- Dice *everything*.\n- Fry until golden.";
+        let recipes = parse(markdown).unwrap();
+
+        let answer = JsonAnswer { recipes: recipes.iter().map(JsonRecipe::from).collect() };
+        assert_eq!(parse_json(&serde_json::to_string(&answer).unwrap()).unwrap(), recipes);
+    }
+
+    #[test]
+    fn test_round_trip_substeps() {
+        let markdown = "Here you go:\n\n1. Carbonara\nIngredients:\n- 200 g spaghetti\n- 2 eggs\nInstructions:\n- Boil the pasta.\n- Make the sauce:\n  - Whisk the eggs.\n    - With a fork is fine.\n  - Stir in *the cheese*.\n- Toss it all together.";
+        let recipes = parse(markdown).unwrap();
+        assert_eq!(recipes[0].instructions[1].substeps.len(), 2);
+
+        let json = JsonRecipe::from(&recipes[0]);
+        assert_eq!(json.instructions[1], "Make the sauce:\n  - Whisk the eggs.\n    - With a fork is fine.\n  - Stir in *the cheese*.");
+        let answer = JsonAnswer { recipes: vec![json] };
+        assert_eq!(parse_json(&serde_json::to_string(&answer).unwrap()).unwrap(), recipes);
+
+        // a line break in a step without a list is still just text
+        assert_eq!(parse_step("Fry.\nThen serve.").substeps, vec![]);
+    }
+}
@@ -0,0 +1,348 @@
+use leptos::{IntoView, view};
+use nom::branch::alt;
+use nom::bytes::complete::{is_not, tag, take_till1, take_until};
+use nom::character::complete::{anychar, char, line_ending, satisfy, space0};
+use nom::combinator::{map, recognize, value, verify};
+use nom::multi::many0;
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::IResult;
+
+
+/// Inline Markdown, the parts of a paragraph or list item that can nest inside each other.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MdElement {
+    Text(String),
+    Em(MdFragment),
+    Strong(MdFragment),
+    Strike(MdFragment),
+    Code(String),
+    Link { text: MdFragment, url: String },
+    LineBreak,
+}
+
+pub type MdFragment = Vec<MdElement>;
+
+impl MdElement {
+    pub(crate) fn map_text(&self, f: &impl Fn(&str) -> String) -> MdElement {
+        let map_all = |children: &MdFragment| children.iter().map(|c| c.map_text(f)).collect();
+        match self {
+            MdElement::Text(s) => MdElement::Text(f(s)),
+            MdElement::Em(c) => MdElement::Em(map_all(c)),
+            MdElement::Strong(c) => MdElement::Strong(map_all(c)),
+            MdElement::Strike(c) => MdElement::Strike(map_all(c)),
+            MdElement::Link { text, url } => MdElement::Link { text: map_all(text), url: url.clone() },
+            // code is meant to be shown as is
+            MdElement::Code(_) | MdElement::LineBreak => self.clone(),
+        }
+    }
+}
+
+impl IntoView for MdElement {
+    fn into_view(self) -> leptos::View {
+        match self {
+            MdElement::Text(s) => s.into_view(),
+            MdElement::Em(c) => view! { <em>{c}</em> }.into_view(),
+            MdElement::Strong(c) => view! { <strong>{c}</strong> }.into_view(),
+            MdElement::Strike(c) => view! { <del>{c}</del> }.into_view(),
+            MdElement::Code(s) => view! { <code class="px-1 font-mono text-sm bg-gray-100 rounded dark:bg-gray-700">{s}</code> }.into_view(),
+            // the text comes from a model, so only follow links that can't run anything
+            MdElement::Link { text, url } if is_safe_url(&url) => view! {
+                <a href=url target="_blank" rel="noopener noreferrer" class="underline">{text}</a>
+            }.into_view(),
+            MdElement::Link { text, .. } => text.into_view(),
+            MdElement::LineBreak => view! { <br/> }.into_view(),
+        }
+    }
+}
+
+fn is_safe_url(url: &str) -> bool {
+    ["https://", "http://", "mailto:"].iter().any(|scheme| url.starts_with(scheme))
+}
+
+/// The text of the fragment without any formatting.
+pub(crate) fn fragment_text(fragment: &[MdElement]) -> String {
+    fragment
+        .iter()
+        .map(|e| match e {
+            MdElement::Text(s) | MdElement::Code(s) => s.clone(),
+            MdElement::Em(c) | MdElement::Strong(c) | MdElement::Strike(c) => fragment_text(c),
+            MdElement::Link { text, .. } => fragment_text(text),
+            MdElement::LineBreak => "\n".to_owned(),
+        })
+        .collect()
+}
+
+/// Parses inline Markdown. Never fails, markup that doesn't close is kept as text.
+pub(crate) fn inline(input: &str) -> MdFragment {
+    let (_, elements) = many0(alt((
+        md_escape,
+        md_code,
+        md_link,
+        md_autolink,
+        md_strong_em,
+        md_strong,
+        md_strike,
+        md_emphasis,
+        md_line_break,
+        md_plain_text,
+        // a stray '*' or '`' that didn't start anything
+        map(anychar, |c| MdElement::Text(c.to_string())),
+    )))(input)
+    .expect("the last alternative accepts any character");
+
+    // join the text the loop above cut into pieces
+    elements.into_iter().fold(Vec::new(), |mut fragment, e| {
+        match (fragment.last_mut(), e) {
+            (Some(MdElement::Text(prev)), MdElement::Text(t)) => prev.push_str(&t),
+            (_, e) => fragment.push(e),
+        }
+        fragment
+    })
+}
+
+/// Writes the fragment back out so that `inline` reads it the same way.
+pub(crate) fn to_markdown(fragment: &[MdElement]) -> String {
+    let mut out = String::new();
+    write_fragment(fragment, Nesting::default(), &mut out);
+    out
+}
+
+/// Backslash-escapes everything `inline` would otherwise take for markup.
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '~' | '`' | '[' | '<') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// emphasis inside strong text switches to the other delimiter, "***" would be read as both at once
+#[derive(Debug, Clone, Copy, Default)]
+struct Nesting {
+    em: bool,
+    strong: bool,
+}
+
+fn write_fragment(fragment: &[MdElement], nesting: Nesting, out: &mut String) {
+    let wrap = |delim: &str, children: &MdFragment, nesting: Nesting, out: &mut String| {
+        out.push_str(delim);
+        write_fragment(children, nesting, out);
+        out.push_str(delim);
+    };
+
+    for e in fragment {
+        match e {
+            MdElement::Text(s) => out.push_str(&escape(s)),
+            MdElement::Em(c) => wrap(if nesting.strong { "_" } else { "*" }, c, Nesting { em: true, ..nesting }, out),
+            MdElement::Strong(c) => wrap(if nesting.em { "__" } else { "**" }, c, Nesting { strong: true, ..nesting }, out),
+            MdElement::Strike(c) => wrap("~~", c, nesting, out),
+            MdElement::Code(s) if s.contains('`') => out.push_str(&format!("`` {} ``", s)),
+            MdElement::Code(s) => out.push_str(&format!("`{}`", s)),
+            MdElement::Link { text, url } => {
+                out.push('[');
+                write_fragment(text, nesting, out);
+                out.push_str(&format!("]({})", url));
+            }
+            MdElement::LineBreak => out.push_str("\\\n"),
+        }
+    }
+}
+
+// \* is a literal star
+fn md_escape(input: &str) -> IResult<&str, MdElement> {
+    map(preceded(char('\\'), satisfy(|c| c.is_ascii_punctuation())), |c| MdElement::Text(c.to_string()))(input)
+}
+
+// text between delimiters has to hug them, so "2 * 3 * 4" stays text
+fn delimited_inner<'a>(open: &'a str, close: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    delimited(
+        tag(open),
+        verify(take_until(close), |s: &str| !s.is_empty() && !s.starts_with(char::is_whitespace)),
+        tag(close),
+    )
+}
+
+fn md_code(input: &str) -> IResult<&str, MdElement> {
+    map(
+        alt((
+            delimited(tag("``"), take_until("``"), tag("``")),
+            delimited(char('`'), is_not("`"), char('`')),
+        )),
+        |s: &str| MdElement::Code(s.trim().to_owned()),
+    )(input)
+}
+
+// [text](https://example.com)
+fn md_link(input: &str) -> IResult<&str, MdElement> {
+    map(
+        pair(
+            delimited(char('['), is_not("]"), char(']')),
+            delimited(char('('), is_not(") \n"), char(')')),
+        ),
+        |(text, url): (&str, &str)| MdElement::Link { text: inline(text), url: url.to_owned() },
+    )(input)
+}
+
+// <https://example.com>
+fn md_autolink(input: &str) -> IResult<&str, MdElement> {
+    map(
+        delimited(char('<'), recognize(pair(alt((tag("https://"), tag("http://"))), is_not("> \n"))), char('>')),
+        |url: &str| MdElement::Link { text: vec![MdElement::Text(url.to_owned())], url: url.to_owned() },
+    )(input)
+}
+
+// ***both*** is the only nesting that can't be told apart by looking for the closing delimiter
+fn md_strong_em(input: &str) -> IResult<&str, MdElement> {
+    map(
+        alt((delimited_inner("***", "***"), delimited_inner("___", "___"))),
+        |s| MdElement::Strong(vec![MdElement::Em(inline(s))]),
+    )(input)
+}
+
+fn md_strong(input: &str) -> IResult<&str, MdElement> {
+    map(
+        alt((delimited_inner("**", "**"), delimited_inner("__", "__"))),
+        |s| MdElement::Strong(inline(s)),
+    )(input)
+}
+
+fn md_strike(input: &str) -> IResult<&str, MdElement> {
+    map(delimited_inner("~~", "~~"), |s| MdElement::Strike(inline(s)))(input)
+}
+
+fn md_emphasis(input: &str) -> IResult<&str, MdElement> {
+    map(
+        alt((delimited_inner("*", "*"), delimited_inner("_", "_"))),
+        |s| MdElement::Em(inline(s)),
+    )(input)
+}
+
+// two trailing spaces, a backslash or an html tag, a plain newline just continues the paragraph
+fn md_line_break(input: &str) -> IResult<&str, MdElement> {
+    value(
+        MdElement::LineBreak,
+        alt((
+            recognize(tuple((tag("  "), space0, line_ending))),
+            recognize(pair(char('\\'), line_ending)),
+            recognize(tuple((tag("<br"), space0, terminated(preceded(space0, alt((tag("/>"), tag(">")))), space0)))),
+        )),
+    )(input)
+}
+
+fn md_plain_text(input: &str) -> IResult<&str, MdElement> {
+    map(
+        take_till1(|c| matches!(c, '*' | '_' | '~' | '`' | '[' | '<' | '\\' | ' ')),
+        |s: &str| MdElement::Text(s.to_owned()),
+    )(input)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> MdElement {
+        MdElement::Text(s.to_owned())
+    }
+
+    #[test]
+    fn test_inline_plain() {
+        assert_eq!(inline("just text, 2 * 3 * 4"), vec![text("just text, 2 * 3 * 4")]);
+        assert_eq!(inline(""), vec![]);
+    }
+
+    #[test]
+    fn test_inline_emphasis() {
+        assert_eq!(inline("this is an **item** *here\nlong *line"), vec![
+            text("this is an "),
+            MdElement::Strong(vec![text("item")]),
+            text(" "),
+            MdElement::Em(vec![text("here\nlong ")]),
+            text("line"),
+        ]);
+        assert_eq!(inline("__a__ _b_"), vec![MdElement::Strong(vec![text("a")]), text(" "), MdElement::Em(vec![text("b")])]);
+    }
+
+    #[test]
+    fn test_inline_nested() {
+        assert_eq!(inline("***both***"), vec![MdElement::Strong(vec![MdElement::Em(vec![text("both")])])]);
+        assert_eq!(inline("**bold *em* inside**"), vec![MdElement::Strong(vec![
+            text("bold "),
+            MdElement::Em(vec![text("em")]),
+            text(" inside"),
+        ])]);
+        assert_eq!(inline("~~no **salt**~~"), vec![MdElement::Strike(vec![text("no "), MdElement::Strong(vec![text("salt")])])]);
+    }
+
+    #[test]
+    fn test_inline_code() {
+        assert_eq!(inline("set to `200 °C` and ``a `b` c``"), vec![
+            text("set to "),
+            MdElement::Code("200 °C".to_owned()),
+            text(" and "),
+            MdElement::Code("a `b` c".to_owned()),
+        ]);
+        assert_eq!(inline("`**not bold**`"), vec![MdElement::Code("**not bold**".to_owned())]);
+    }
+
+    #[test]
+    fn test_inline_links() {
+        assert_eq!(inline("see [the *guide*](https://example.com/a) or <https://example.com>"), vec![
+            text("see "),
+            MdElement::Link { text: vec![text("the "), MdElement::Em(vec![text("guide")])], url: "https://example.com/a".to_owned() },
+            text(" or "),
+            MdElement::Link { text: vec![text("https://example.com")], url: "https://example.com".to_owned() },
+        ]);
+        assert_eq!(inline("[not a link] (x)"), vec![text("[not a link] (x)")]);
+    }
+
+    #[test]
+    fn test_inline_line_break() {
+        assert_eq!(inline("one  \ntwo\\\nthree<br/>four\nfive"), vec![
+            text("one"),
+            MdElement::LineBreak,
+            text("two"),
+            MdElement::LineBreak,
+            text("three"),
+            MdElement::LineBreak,
+            text("four\nfive"),
+        ]);
+    }
+
+    #[test]
+    fn test_inline_unclosed() {
+        assert_eq!(inline("**never closed and `tick"), vec![text("**never closed and `tick")]);
+    }
+
+    #[test]
+    fn test_inline_escape() {
+        assert_eq!(inline("2 \\* 3 \\_not em\\_ \\\\"), vec![text("2 * 3 _not em_ \\")]);
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let f = vec![
+            MdElement::Strong(vec![text("bold "), MdElement::Em(vec![text("em")])]),
+            text(" 2*3 "),
+            MdElement::Em(vec![MdElement::Strong(vec![text("both")])]),
+            MdElement::Code("a `b`".to_owned()),
+        ];
+        assert_eq!(to_markdown(&f), "**bold _em_** 2\\*3 *__both__*`` a `b` ``");
+        assert_eq!(inline(&to_markdown(&f)), f);
+    }
+
+    #[test]
+    fn test_fragment_text() {
+        assert_eq!(fragment_text(&inline("**a** [b](https://x.y) `c`  \nd")), "a b c\nd");
+    }
+
+    #[test]
+    fn test_map_text_skips_code() {
+        let f = inline("**2 cups** `2 cups`");
+        let mapped: MdFragment = f.iter().map(|e| e.map_text(&|s| s.replace('2', "4"))).collect();
+        assert_eq!(mapped, inline("**4 cups** `2 cups`"));
+    }
+}
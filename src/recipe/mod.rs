@@ -1,6 +1,30 @@
 pub use crate::recipe::recipe_parser::*;
+pub use crate::recipe::quantity::*;
+pub use crate::recipe::scale::*;
+pub use crate::recipe::convert::*;
+pub use crate::recipe::error::*;
+pub use crate::recipe::markdown::*;
+pub use crate::recipe::write::*;
+pub use crate::recipe::cooklang::*;
+pub use crate::recipe::schema_org::*;
+pub use crate::recipe::stream::*;
+pub use crate::recipe::json::*;
+pub use crate::recipe::words::*;
+#[cfg(feature = "ssr")]
+pub use crate::recipe::html::*;
 
 
 mod recipe_parser;
-
-
+mod quantity;
+mod scale;
+mod convert;
+mod error;
+mod markdown;
+mod write;
+mod cooklang;
+mod schema_org;
+mod stream;
+mod json;
+mod words;
+#[cfg(feature = "ssr")]
+mod html;
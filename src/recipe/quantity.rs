@@ -0,0 +1,370 @@
+use std::fmt::{self, Display};
+
+use nom::branch::alt;
+use nom::bytes::complete::tag_no_case;
+use nom::character::complete::{alpha1, char, digit1, one_of, satisfy, space0, space1};
+use nom::combinator::{map, map_opt, map_res, not, opt, recognize, verify};
+use nom::sequence::{pair, preceded, separated_pair, terminated, tuple};
+use nom::IResult;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Amount {
+    Single(f64),
+    Range(f64, f64),
+}
+
+impl Amount {
+    pub fn min(&self) -> f64 {
+        match *self {
+            Amount::Single(a) => a,
+            Amount::Range(a, _) => a,
+        }
+    }
+
+    pub fn max(&self) -> f64 {
+        match *self {
+            Amount::Single(a) => a,
+            Amount::Range(_, b) => b,
+        }
+    }
+
+    pub fn map(self, f: impl Fn(f64) -> f64) -> Amount {
+        match self {
+            Amount::Single(a) => Amount::Single(f(a)),
+            Amount::Range(a, b) => Amount::Range(f(a), f(b)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum Unit {
+    Cup,
+    Tablespoon,
+    Teaspoon,
+    Gram,
+    Kilogram,
+    Milliliter,
+    Liter,
+    Ounce,
+    Pound,
+    Pinch,
+    Clove,
+    Can,
+    Slice,
+}
+
+impl Unit {
+    /// Looks up a unit by any of its spellings, e.g. "tbsp", "Tablespoons" or "g".
+    pub fn from_name(name: &str) -> Option<Unit> {
+        let unit = match name.to_lowercase().as_str() {
+            // not "c", that's the "C" in "200 C" as often as it's a cup
+            "cup" | "cups" => Unit::Cup,
+            "tablespoon" | "tablespoons" | "tbsp" | "tbsps" | "tbs" | "tbl" => Unit::Tablespoon,
+            "teaspoon" | "teaspoons" | "tsp" | "tsps" => Unit::Teaspoon,
+            "gram" | "grams" | "gramme" | "grammes" | "g" | "gr" => Unit::Gram,
+            "kilogram" | "kilograms" | "kilo" | "kilos" | "kg" | "kgs" => Unit::Kilogram,
+            "milliliter" | "milliliters" | "millilitre" | "millilitres" | "ml" => Unit::Milliliter,
+            "liter" | "liters" | "litre" | "litres" | "l" => Unit::Liter,
+            "ounce" | "ounces" | "oz" => Unit::Ounce,
+            "pound" | "pounds" | "lb" | "lbs" => Unit::Pound,
+            "pinch" | "pinches" => Unit::Pinch,
+            "clove" | "cloves" => Unit::Clove,
+            "can" | "cans" | "tin" | "tins" => Unit::Can,
+            "slice" | "slices" => Unit::Slice,
+            _ => return None,
+        };
+        Some(unit)
+    }
+
+    pub fn name(&self, plural: bool) -> &'static str {
+        match (self, plural) {
+            (Unit::Cup, false) => "cup",
+            (Unit::Cup, true) => "cups",
+            (Unit::Tablespoon, _) => "tbsp",
+            (Unit::Teaspoon, _) => "tsp",
+            (Unit::Gram, _) => "g",
+            (Unit::Kilogram, _) => "kg",
+            (Unit::Milliliter, _) => "ml",
+            (Unit::Liter, _) => "l",
+            (Unit::Ounce, _) => "oz",
+            (Unit::Pound, _) => "lb",
+            (Unit::Pinch, false) => "pinch",
+            (Unit::Pinch, true) => "pinches",
+            (Unit::Clove, false) => "clove",
+            (Unit::Clove, true) => "cloves",
+            (Unit::Can, false) => "can",
+            (Unit::Can, true) => "cans",
+            (Unit::Slice, false) => "slice",
+            (Unit::Slice, true) => "slices",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Quantity {
+    pub amount: Amount,
+    pub unit: Option<Unit>,
+}
+
+impl Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Amount::Single(a) => write!(f, "{}", format_number(a)),
+            Amount::Range(a, b) => write!(f, "{}-{}", format_number(a), format_number(b)),
+        }
+    }
+}
+
+impl Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.unit {
+            Some(unit) => write!(f, "{} {}", self.amount, unit.name(self.amount.max() > 1.0)),
+            None => write!(f, "{}", self.amount),
+        }
+    }
+}
+
+const FRACTION_DENOMINATORS: &[u32] = &[2, 3, 4, 8];
+
+// 1.5 -> "1 1/2", 0.333 -> "1/3", 2.45 -> "2.45"
+fn format_number(n: f64) -> String {
+    let whole = n.trunc();
+    let rest = n - whole;
+
+    if rest.abs() < 0.01 {
+        return format!("{}", whole);
+    }
+    // 1.999 is 2, not "1 2/2"
+    if 1.0 - rest < 0.01 {
+        return format!("{}", whole + 1.0);
+    }
+
+    for &den in FRACTION_DENOMINATORS {
+        let num = (rest * den as f64).round();
+        if num > 0.0 && (rest - num / den as f64).abs() < 0.004 {
+            return match whole as i64 {
+                0 => format!("{}/{}", num, den),
+                w => format!("{} {}/{}", w, num, den),
+            };
+        }
+    }
+
+    let s = format!("{:.2}", n);
+    s.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
+
+fn unicode_fraction(input: &str) -> IResult<&str, f64> {
+    map_opt(one_of("½⅓⅔¼¾⅕⅖⅗⅘⅙⅚⅛⅜⅝⅞"), |c| {
+        let v = match c {
+            '½' => 1.0 / 2.0,
+            '⅓' => 1.0 / 3.0,
+            '⅔' => 2.0 / 3.0,
+            '¼' => 1.0 / 4.0,
+            '¾' => 3.0 / 4.0,
+            '⅕' => 1.0 / 5.0,
+            '⅖' => 2.0 / 5.0,
+            '⅗' => 3.0 / 5.0,
+            '⅘' => 4.0 / 5.0,
+            '⅙' => 1.0 / 6.0,
+            '⅚' => 5.0 / 6.0,
+            '⅛' => 1.0 / 8.0,
+            '⅜' => 3.0 / 8.0,
+            '⅝' => 5.0 / 8.0,
+            '⅞' => 7.0 / 8.0,
+            _ => return None,
+        };
+        Some(v)
+    })(input)
+}
+
+fn integer(input: &str) -> IResult<&str, f64> {
+    map_res(digit1, str::parse::<f64>)(input)
+}
+
+fn decimal(input: &str) -> IResult<&str, f64> {
+    map_res(recognize(tuple((digit1, char('.'), digit1))), str::parse::<f64>)(input)
+}
+
+fn fraction(input: &str) -> IResult<&str, f64> {
+    map(
+        verify(separated_pair(integer, char('/'), integer), |(_, den)| *den != 0.0),
+        |(num, den)| num / den,
+    )(input)
+}
+
+// "1 1/2", "1½", "1 ½"
+fn mixed_number(input: &str) -> IResult<&str, f64> {
+    map(
+        pair(integer, alt((preceded(space0, unicode_fraction), preceded(space1, fraction)))),
+        |(whole, frac)| whole + frac,
+    )(input)
+}
+
+pub(crate) fn number(input: &str) -> IResult<&str, f64> {
+    alt((
+        mixed_number,
+        fraction,
+        decimal,
+        integer,
+        unicode_fraction,
+    ))(input)
+}
+
+fn range_separator(input: &str) -> IResult<&str, char> {
+    alt((
+        preceded(space0, terminated(one_of("-–"), space0)),
+        map(tuple((space1, tag_no_case("to"), space1)), |_| '-'),
+    ))(input)
+}
+
+fn amount(input: &str) -> IResult<&str, Amount> {
+    alt((
+        map(tuple((number, range_separator, number)), |(a, _, b)| Amount::Range(a, b)),
+        map(number, Amount::Single),
+    ))(input)
+}
+
+fn unit(input: &str) -> IResult<&str, Unit> {
+    terminated(map_opt(alpha1, Unit::from_name), opt(char('.')))(input)
+}
+
+/// Parses a quantity from the start of the input, e.g. "1 1/2 cups" in "1 1/2 cups of rice".
+pub(crate) fn quantity(input: &str) -> IResult<&str, Quantity> {
+    map(
+        pair(amount, opt(preceded(space0, unit))),
+        |(amount, unit)| Quantity { amount, unit },
+    )(input)
+}
+
+/// Parses a whole string as a quantity, returning `None` if anything is left over.
+pub fn parse_quantity(input: &str) -> Option<Quantity> {
+    match quantity(input.trim()) {
+        Ok(("", q)) => Some(q),
+        _ => None,
+    }
+}
+
+// "200 C", "350°F" or "180 °c", an oven temperature and never an amount of anything
+fn temperature(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((
+        amount,
+        space0,
+        alt((recognize(pair(char('°'), opt(one_of("CFcf")))), recognize(one_of("CFcf")))),
+        not(satisfy(char::is_alphanumeric)),
+    )))(input)
+}
+
+// only quantities with a unit are touched, "bake for 20 minutes" or "step 2" stay as they are
+pub(crate) fn map_quantities(text: &str, f: impl Fn(Quantity) -> Quantity) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut prev: Option<char> = None;
+
+    while let Some(c) = rest.chars().next() {
+        let at_boundary = prev.is_none_or(|p| !p.is_alphanumeric() && p != '.' && p != '/');
+        if at_boundary && c.is_ascii_digit() {
+            if let Ok((after, t)) = temperature(rest) {
+                out.push_str(t);
+                prev = t.chars().last();
+                rest = after;
+                continue;
+            }
+            if let Ok((after, q)) = quantity(rest) {
+                if q.unit.is_some() {
+                    out.push_str(&f(q).to_string());
+                    prev = rest[..rest.len() - after.len()].chars().last();
+                    rest = after;
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+        prev = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number() {
+        assert_eq!(number("2 eggs").unwrap(), (" eggs", 2.0));
+        assert_eq!(number("1.25 kg").unwrap(), (" kg", 1.25));
+        assert_eq!(number("3/4 cup").unwrap(), (" cup", 0.75));
+        assert_eq!(number("1 1/2 cups").unwrap(), (" cups", 1.5));
+        assert_eq!(number("½ tsp").unwrap(), (" tsp", 0.5));
+        assert_eq!(number("1½ tsp").unwrap(), (" tsp", 1.5));
+    }
+
+    #[test]
+    fn test_number_not_a_fraction() {
+        assert_eq!(number("1 potato").unwrap(), (" potato", 1.0));
+        assert!(number("potato").is_err());
+        assert!(fraction("1/0").is_err());
+    }
+
+    #[test]
+    fn test_amount_range() {
+        assert_eq!(amount("2-3 cloves").unwrap(), (" cloves", Amount::Range(2.0, 3.0)));
+        assert_eq!(amount("2 - 3").unwrap(), ("", Amount::Range(2.0, 3.0)));
+        assert_eq!(amount("1 to 1 1/2").unwrap(), ("", Amount::Range(1.0, 1.5)));
+    }
+
+    #[test]
+    fn test_quantity_units() {
+        assert_eq!(parse_quantity("2 cups"), Some(Quantity { amount: Amount::Single(2.0), unit: Some(Unit::Cup) }));
+        assert_eq!(parse_quantity("1 Tbsp."), Some(Quantity { amount: Amount::Single(1.0), unit: Some(Unit::Tablespoon) }));
+        assert_eq!(parse_quantity("200g"), Some(Quantity { amount: Amount::Single(200.0), unit: Some(Unit::Gram) }));
+        assert_eq!(parse_quantity("1.5 litres"), Some(Quantity { amount: Amount::Single(1.5), unit: Some(Unit::Liter) }));
+        assert_eq!(parse_quantity("2 lbs"), Some(Quantity { amount: Amount::Single(2.0), unit: Some(Unit::Pound) }));
+        assert_eq!(parse_quantity("3"), Some(Quantity { amount: Amount::Single(3.0), unit: None }));
+        assert_eq!(parse_quantity("3 potatoes"), None);
+    }
+
+    #[test]
+    fn test_quantity_prefix() {
+        let (rest, q) = quantity("2 large eggs").unwrap();
+        assert_eq!(q, Quantity { amount: Amount::Single(2.0), unit: None });
+        assert_eq!(rest, " large eggs");
+    }
+
+    #[test]
+    fn test_quantity_display() {
+        assert_eq!(parse_quantity("1 1/2 cups").unwrap().to_string(), "1 1/2 cups");
+        assert_eq!(parse_quantity("1 cup").unwrap().to_string(), "1 cup");
+        assert_eq!(parse_quantity("⅓ tsp").unwrap().to_string(), "1/3 tsp");
+        assert_eq!(parse_quantity("2-3 cloves").unwrap().to_string(), "2-3 cloves");
+        assert_eq!(parse_quantity("0.45 kg").unwrap().to_string(), "0.45 kg");
+        assert_eq!(format_number(1.999), "2");
+        assert_eq!(format_number(0.997), "1");
+        assert_eq!(format_number(1.99), "1.99");
+    }
+
+    #[test]
+    fn test_temperatures() {
+        use crate::recipe::{MdElement, Recipe, Step, UnitSystem};
+
+        assert_eq!(parse_quantity("200 C"), None);
+        assert_eq!(parse_quantity("1 c"), None);
+        for text in ["Preheat the oven to 200 C.", "Bake at 350°F", "Heat to 180 °c, then rest"] {
+            assert_eq!(map_quantities(text, |_| panic!("{} has no quantity", text)), text);
+        }
+
+        let text = "Preheat the oven to 200 C, then pour in 2 cups of stock.";
+        assert_eq!(super::super::scale::scale_text(text, 2.0), "Preheat the oven to 200 C, then pour in 4 cups of stock.");
+        let recipe = Recipe {
+            name: vec![MdElement::Text("Soup".to_owned())],
+            servings: None,
+            ingredients: vec![],
+            instructions: vec![Step::new(vec![MdElement::Text(text.to_owned())])],
+        };
+        let metric = recipe.to_system(UnitSystem::Metric);
+        assert_eq!(metric.instructions[0].text, vec![MdElement::Text("Preheat the oven to 200 C, then pour in 475 ml of stock.".to_owned())]);
+    }
+}
@@ -1,83 +1,252 @@
-use leptos::{IntoView, template, view};
-use nom::{Parser};
+use leptos::{CollectView, IntoView, view};
 use nom::branch::alt;
-use nom::bytes::complete::tag;
-use nom::character::complete::{char, anychar, multispace0, digit1, newline};
-use nom::combinator::{peek, recognize, eof};
-use nom::error::ParseError;
+use nom::bytes::complete::{tag, tag_no_case, take_till, take_while_m_n};
+use nom::character::complete::{char, anychar, multispace0, digit1, newline, line_ending, not_line_ending, space0, space1};
+use nom::combinator::{peek, recognize, eof, map, map_opt, map_res, not, opt, value, verify};
 use nom::multi::{many_till, many1};
-use nom::sequence::{delimited, preceded};
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
 use nom::IResult;
 
-use anyhow::Result;
+use super::error::ParseError;
+use super::markdown::{fragment_text, inline, MdElement, MdFragment};
+use super::quantity::{quantity, Quantity};
 
 
-pub fn parse(input: &str) -> Result<Vec<Recipe>> {
-    let (rest, _foreword) = md_text(input).map_err(|e| e.to_owned())?;
-    let mut rest = rest;
+const EXPECTED_RECIPE_BODY: &str = "a bullet list or an \"Ingredients:\" / \"Instructions:\" section";
 
-    let mut recipes: Vec<Recipe> = Vec::new();
+/// How the recipes in a response are laid out. Models switch between these depending on how the
+/// prompt is phrased, so `parse` tries all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    /// "1. **Name:**" followed by bullet lists
+    OrderedList,
+    /// "## Name" headings of the given level, with "### Ingredients" style sub-headings
+    Headings(usize),
+}
 
-    loop {
-        let (rest_name, recipe_name) = match ordered_list_item(rest).map_err(|e| e.to_owned()) {
-            Ok(r) => r,
-            Err(_) => break,
-        };
+impl Layout {
+    // the layout that looks most likely goes first
+    fn candidates(input: &str) -> Vec<Layout> {
+        match recipe_heading_level(input) {
+            // "### Ingredients" headings leave no doubt, numbered lists under them are just steps
+            Some(level) if has_section_headings(input) => vec![Layout::Headings(level)],
+            Some(level) => vec![Layout::Headings(level), Layout::OrderedList],
+            None => vec![Layout::OrderedList],
+        }
+    }
+
+    fn recipe_name(self, input: &str) -> IResult<&str, MdFragment> {
+        match self {
+            Layout::OrderedList => ordered_list_item(input),
+            Layout::Headings(level) => recipe_heading(level, input),
+        }
+    }
+
+    fn recipe_body(self, input: &str) -> IResult<&str, Vec<BodyLine>> {
+        match self {
+            Layout::OrderedList => recipe_body(input),
+            Layout::Headings(level) => heading_body(level, input),
+        }
+    }
+
+    fn expected_name(self) -> String {
+        match self {
+            Layout::OrderedList => "a numbered recipe name like \"1. **Name:**\"".to_owned(),
+            Layout::Headings(level) => format!("a recipe heading like \"{} Name\"", "#".repeat(level)),
+        }
+    }
+
+    // the start of the next recipe after the beginning of the input
+    fn next_recipe(self, input: &str) -> Option<&str> {
+        input
+            .char_indices()
+            .skip(1)
+            .map(|(i, _)| &input[i..])
+            .find(|s| s.starts_with('\n') && self.recipe_name(s).is_ok())
+    }
+
+    // the response may open straight with the first recipe, otherwise skip the model's preamble
+    fn skip_foreword(self, input: &str) -> IResult<&str, MdFragment> {
+        if self.recipe_name(input).is_ok() {
+            return Ok((input, vec![]));
+        }
+        match (self, self.next_recipe(input)) {
+            // a title heading can be followed by any number of paragraphs before the first recipe
+            (Layout::Headings(_), Some(rest)) => Ok((rest, vec![])),
+            _ => md_text(input),
+        }
+    }
+
+    fn parse(self, input: &str) -> Result<Vec<Recipe>, ParseError> {
+        let (rest, _foreword) = self.skip_foreword(input)
+            .map_err(|e| ParseError::from_nom(input, e, "introductory text"))?;
+        let mut rest = rest;
+
+        let mut recipes: Vec<Recipe> = Vec::new();
+
+        // once at least one recipe made it through, whatever follows is treated as closing remarks
+        loop {
+            let (rest_name, recipe_name) = match self.recipe_name(rest) {
+                Ok(r) => r,
+                Err(e) if recipes.is_empty() => return Err(ParseError::from_nom(input, e, &self.expected_name())),
+                Err(_) => break,
+            };
+
+            let (rest_body, recipe_body) = match self.recipe_body(rest_name) {
+                Ok(r) => r,
+                Err(e) if recipes.is_empty() => return Err(ParseError::from_nom(input, e, EXPECTED_RECIPE_BODY)),
+                Err(_) => break,
+            };
 
-        let (rest_body, recipe_body) = match unordered_list(rest_name).map_err(|e| e.to_owned()) {
-            Ok(r) => r,
-            Err(_) => break,
+            recipes.push(Recipe::from_body(recipe_name, recipe_body, rest_body.trim().is_empty()));
+
+            rest = rest_body;
+
+            if rest.is_empty() {
+                break;
+            }
+        }
+
+        Ok(recipes)
+    }
+
+    fn parse_lenient(self, input: &str) -> ParseReport {
+        let mut report = ParseReport::default();
+
+        let mut rest = match self.skip_foreword(input) {
+            Ok((rest, _foreword)) => rest,
+            Err(e) => {
+                report.warnings.push(ParseError::from_nom(input, e, "introductory text"));
+                input
+            }
         };
 
-        recipes.push(Recipe { name: recipe_name, instructions: recipe_body });
+        while !rest.is_empty() {
+            let (rest_name, recipe_name) = match self.recipe_name(rest) {
+                Ok(r) => r,
+                Err(e) => match self.next_recipe(rest) {
+                    Some(next) => {
+                        report.warnings.push(ParseError::from_nom(input, e, &self.expected_name()));
+                        rest = next;
+                        continue;
+                    }
+                    // trailing text after the last recipe is just the model signing off
+                    None => break,
+                },
+            };
+
+            let (rest_body, recipe_body) = match self.recipe_body(rest_name) {
+                Ok(r) => r,
+                Err(e) => {
+                    report.warnings.push(ParseError::from_nom(input, e, EXPECTED_RECIPE_BODY));
+                    match self.next_recipe(rest_name) {
+                        Some(next) => {
+                            rest = next;
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+            };
 
-        rest = rest_body;
+            report.recipes.push(Recipe::from_body(recipe_name, recipe_body, rest_body.trim().is_empty()));
 
-        if rest.is_empty() {
-            break;
+            rest = rest_body;
         }
+
+        if report.recipes.is_empty() && report.warnings.is_empty() {
+            report.warnings.push(ParseError::at(input, input.len() - rest.len(), &self.expected_name()));
+        }
+
+        report
     }
+}
+
+pub fn parse(input: &str) -> Result<Vec<Recipe>, ParseError> {
+    let mut best: Option<Vec<Recipe>> = None;
+    let mut first_error = None;
 
-    Ok(recipes)
+    for layout in Layout::candidates(input) {
+        match layout.parse(input) {
+            Ok(recipes) if best.as_ref().is_none_or(|b| recipes.len() > b.len()) => best = Some(recipes),
+            Ok(_) => {}
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    match (best, first_error) {
+        (Some(recipes), _) => Ok(recipes),
+        (None, Some(e)) => Err(e),
+        (None, None) => unreachable!("there is always at least one layout to try"),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ParseReport {
+    pub recipes: Vec<Recipe>,
+    /// Everything that had to be skipped to get to the recipes.
+    pub warnings: Vec<ParseError>,
+}
+
+/// Like `parse`, but instead of giving up at the first malformed recipe it skips ahead to the
+/// next one and keeps going.
+pub fn parse_lenient(input: &str) -> ParseReport {
+    Layout::candidates(input)
+        .into_iter()
+        .map(|layout| layout.parse_lenient(input))
+        .reduce(|best, report| {
+            let better = (report.recipes.len(), usize::MAX - report.warnings.len())
+                > (best.recipes.len(), usize::MAX - best.warnings.len());
+            if better { report } else { best }
+        })
+        .unwrap_or_default()
 }
 
 pub fn dummy_recipes() -> Vec<Recipe> {
     let rec = vec![
             Recipe{
-                name: vec![MdElement::Strong("rec1:".to_owned())],
+                name: vec![MdElement::Strong(vec![MdElement::Text("rec1:".to_owned())])],
+                servings: None,
+                ingredients: vec![],
                 instructions: vec![
-                    vec![MdElement::Text("a.".to_owned())],
-                    vec![MdElement::Text("b.".to_owned())],
-                    vec![MdElement::Text("c.".to_owned())],
-                    vec![MdElement::Text("d.".to_owned())],
-                    vec![MdElement::Text("e.".to_owned())],
+                    Step::new(vec![MdElement::Text("a.".to_owned())]),
+                    Step::new(vec![MdElement::Text("b.".to_owned())]),
+                    Step::new(vec![MdElement::Text("c.".to_owned())]),
+                    Step::new(vec![MdElement::Text("d.".to_owned())]),
+                    Step::new(vec![MdElement::Text("e.".to_owned())]),
             ]},
             Recipe{
-                name: vec![MdElement::Strong("rec2:".to_owned())],
+                name: vec![MdElement::Strong(vec![MdElement::Text("rec2:".to_owned())])],
+                servings: None,
+                ingredients: vec![],
                 instructions: vec![
-                    vec![MdElement::Text("a.".to_owned())],
-                    vec![MdElement::Text("b.".to_owned())],
-                    vec![MdElement::Text("c.".to_owned())],
-                    vec![MdElement::Text("d.".to_owned())],
-                    vec![MdElement::Text("e.".to_owned())],
-                    vec![MdElement::Text("f.".to_owned())],
+                    Step::new(vec![MdElement::Text("a.".to_owned())]),
+                    Step::new(vec![MdElement::Text("b.".to_owned())]),
+                    Step::new(vec![MdElement::Text("c.".to_owned())]),
+                    Step::new(vec![MdElement::Text("d.".to_owned())]),
+                    Step::new(vec![MdElement::Text("e.".to_owned())]),
+                    Step::new(vec![MdElement::Text("f.".to_owned())]),
             ]},
             Recipe{
-                name: vec![MdElement::Strong("rec3:".to_owned())],
+                name: vec![MdElement::Strong(vec![MdElement::Text("rec3:".to_owned())])],
+                servings: None,
+                ingredients: vec![],
                 instructions: vec![
-                    vec![MdElement::Text("a.".to_owned())],
-                    vec![MdElement::Text("b.".to_owned())],
-                    vec![MdElement::Text("c.".to_owned())],
-                    vec![MdElement::Text("d.".to_owned())],
+                    Step::new(vec![MdElement::Text("a.".to_owned())]),
+                    Step::new(vec![MdElement::Text("b.".to_owned())]),
+                    Step::new(vec![MdElement::Text("c.".to_owned())]),
+                    Step::new(vec![MdElement::Text("d.".to_owned())]),
             ]},
     ];
-    return rec;
+    rec
 }
 
 
 
 fn ordered_list_bullet(input: &str) -> IResult<&str, &str> {
-    delimited(preceded(newline, multispace0), digit1, tag(". "))(input)
+    delimited(preceded(newline, multispace0), digit1, alt((tag(". "), tag(") "))))(input)
 }
 
 fn ordered_list_item(input: &str) -> IResult<&str, Vec<MdElement>> {
@@ -85,154 +254,434 @@ fn ordered_list_item(input: &str) -> IResult<&str, Vec<MdElement>> {
 }
 
 fn unordered_list_bullet(input: &str) -> IResult<&str, &str> {
-    preceded(preceded(newline, multispace0), tag("- "))(input)
+    preceded(preceded(newline, multispace0), alt((tag("- "), tag("* "), tag("+ "))))(input)
 }
 
-fn unordered_list_item(input: &str) -> IResult<&str, Vec<MdElement>> {
-    preceded(unordered_list_bullet, md_text)(input)
+struct ListItem {
+    indent: usize,
+    numbered: bool,
+    text: MdFragment,
 }
 
-fn unordered_list(input: &str) -> IResult<&str, Vec<Vec<MdElement>>> {
-    many1(unordered_list_item)(input)
+// how far the bullet is indented, blank lines before it don't count
+fn bullet_indent(input: &str) -> IResult<&str, usize> {
+    map(preceded(newline, multispace0), |ws: &str| {
+        ws.rsplit('\n').next().unwrap_or("").chars().map(|c| if c == '\t' { 4 } else { 1 }).sum()
+    })(input)
 }
 
-
-fn md_emphasis(input: &str) -> IResult<&str, &str> {
+fn list_item(input: &str) -> IResult<&str, ListItem> {
+    let (_, indent) = bullet_indent(input)?;
     alt((
-        delimited(char('*'), md_elem_inside("*"), char('*')),
-        delimited(char('_'), md_elem_inside("_"), char('_')),
+        map(preceded(unordered_list_bullet, md_text), move |text| ListItem { indent, numbered: false, text }),
+        map(ordered_list_item, move |text| ListItem { indent, numbered: true, text }),
     ))(input)
 }
 
+// folds the flat items into a tree, an item belongs to the closest item above it that is indented less
+fn list_tree(items: Vec<ListItem>) -> Vec<Step> {
+    fn close(stack: &mut Vec<(usize, Step)>, roots: &mut Vec<Step>) {
+        if let Some((_, step)) = stack.pop() {
+            match stack.last_mut() {
+                Some((_, parent)) => parent.substeps.push(step),
+                None => roots.push(step),
+            }
+        }
+    }
 
-fn md_strong(input: &str) -> IResult<&str, &str> {
-    alt((
-        // delimited(tag("**"), take_till(|c| c == '*'), tag("**")),
-        delimited(tag("**"), md_elem_inside("*"), tag("**")),
-        delimited(tag("__"), md_elem_inside("_"), tag("__")),
-    ))(input)
+    let mut roots = Vec::new();
+    let mut stack: Vec<(usize, Step)> = Vec::new();
+
+    for item in items {
+        while stack.last().is_some_and(|(indent, _)| *indent >= item.indent) {
+            close(&mut stack, &mut roots);
+        }
+        stack.push((item.indent, Step::new(item.text)));
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut roots);
+    }
+
+    roots
 }
 
-// TODO(filip): i don't know if this is the best way to do this, just want to try
-// to get this working
-// TODO(filip): I really don't like the generics here. has to be a better way of doing this
-fn md_elem_inside<'a, 'b, E: ParseError<&'a str> + 'b>(
-    stop_tag: &'b str,
-) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str, E> + 'b
-where
-    'a: 'b
-    
-{
-    recognize(many_till(
-        anychar, 
-        peek(
-            alt((
-                // stop_tag,
-                tag(stop_tag),
-                tag("\n\n")
-            ))
-        )))
-}
-//
-// fn ws<'a, F, O, E: ParseError<&'a str>>(inner: F) -> impl Parser<&'a str>
-fn ws<'a, F, O, E: ParseError<&'a str>>(inner: F) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
-  where
-    F: Parser<&'a str, O, E>,
-{
-  delimited(
-    nom::character::complete::multispace0,
-    inner,
-    nom::character::complete::multispace0
-  )
-}
-
-
-// TODO(filip): move to its own file
-#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
-pub enum MdElement {
-    Em(String),
-    Strong(String),
-    Text(String)
-}
-
-impl IntoView for MdElement {
-    fn into_view(self) -> leptos::View {
-        match self {
-            MdElement::Em(s) => view! { <em>{s}</em> }.into_view(),
-            MdElement::Strong(s) => view! { <strong>{s}</strong>}.into_view(),
-            MdElement::Text(s) => s.into_view(),
+/// The steps in `input` when it's nothing but a (nested) list, each item on a line of its own.
+pub(super) fn sublist(input: &str) -> Option<Vec<Step>> {
+    match many1(list_item)(input) {
+        Ok((rest, items)) if rest.trim().is_empty() => Some(list_tree(items)),
+        _ => None,
+    }
+}
+
+fn list(input: &str) -> IResult<&str, Vec<Step>> {
+    map(many1(list_item), list_tree)(input)
+}
+
+// in the numbered layout a number at the left margin starts the next recipe, so numbered steps
+// have to be indented
+fn indented_list(input: &str) -> IResult<&str, Vec<Step>> {
+    map(many1(verify(list_item, |i| !i.numbered || i.indent > 0)), list_tree)(input)
+}
+
+/// The part of a recipe a heading starts, for the HTML importer as much as for answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Section {
+    Ingredients,
+    Instructions,
+}
+
+const INGREDIENT_HEADINGS: &[&str] = &["ingredients", "you will need", "what you need"];
+const INSTRUCTION_HEADINGS: &[&str] = &["instructions", "directions", "steps", "method", "preparation", "how to make it"];
+const SERVINGS_WORDS: &[&str] = &["serves", "servings", "yield", "makes"];
+
+// any of `words`, in any case
+fn one_of<'a>(words: &'static [&'static str]) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    move |input: &'a str| {
+        words.iter()
+            .find_map(|w| tag_no_case::<_, _, nom::error::Error<&str>>(*w)(input).ok())
+            .ok_or_else(|| nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BodyLine {
+    Section(Section),
+    Servings(u32),
+    Items(Vec<Step>),
+    /// Free text, either a description or steps written as prose.
+    Paragraph(MdFragment),
+}
+
+pub(super) fn section_name(input: &str) -> IResult<&str, Section> {
+    let (rest, (_, section, _, _, _)) = tuple((
+        opt(alt((tag("**"), tag("__")))),
+        alt((
+            value(Section::Ingredients, one_of(INGREDIENT_HEADINGS)),
+            value(Section::Instructions, one_of(INSTRUCTION_HEADINGS)),
+        )),
+        opt(alt((tag("**"), tag("__")))),
+        opt(char(':')),
+        opt(alt((tag("**"), tag("__")))),
+    ))(input)?;
+    Ok((rest, section))
+}
+
+// "Ingredients:" on its own line, not as a list item
+fn section_header(input: &str) -> IResult<&str, Section> {
+    terminated(
+        preceded(preceded(newline, space0), section_name),
+        pair(space0, peek(alt((line_ending, eof)))),
+    )(input)
+}
+
+// "Serves 4", "Servings: 2", "Yield: 6 servings", "Makes 12"
+fn servings(input: &str) -> IResult<&str, u32> {
+    preceded(
+        tuple((
+            opt(alt((tag("**"), tag("__")))),
+            one_of(SERVINGS_WORDS),
+            opt(alt((tag("**"), tag("__")))),
+            opt(char(':')),
+            opt(alt((tag("**"), tag("__")))),
+            space0,
+        )),
+        map_res(digit1, str::parse),
+    )(input)
+}
+
+fn servings_header(input: &str) -> IResult<&str, u32> {
+    terminated(
+        preceded(preceded(newline, space0), servings),
+        pair(not_line_ending, peek(alt((line_ending, eof)))),
+    )(input)
+}
+
+pub(super) fn find_servings(text: &str) -> Option<u32> {
+    text.char_indices()
+        .find_map(|(i, _)| servings(&text[i..]).ok())
+        .map(|(_, n)| n)
+}
+
+fn recipe_body(input: &str) -> IResult<&str, Vec<BodyLine>> {
+    many1(alt((
+        map(section_header, BodyLine::Section),
+        map(servings_header, BodyLine::Servings),
+        map(indented_list, BodyLine::Items),
+    )))(input)
+}
+
+// "## Ham Hash", the text stops at the end of the line
+fn heading(input: &str) -> IResult<&str, (usize, MdFragment)> {
+    let (rest, hashes) = terminated(take_while_m_n(1, 6, |c| c == '#'), space1)(input)?;
+    let (rest, line) = not_line_ending(rest)?;
+    let (_, text) = md_text(line.trim_end_matches([' ', '#']))?;
+    Ok((rest, (hashes.len(), text)))
+}
+
+fn heading_line(input: &str) -> IResult<&str, (usize, MdFragment)> {
+    preceded(pair(newline, multispace0), heading)(input)
+}
+
+fn as_section(text: &MdFragment) -> Option<Section> {
+    match section_name(fragment_text(text).trim()) {
+        Ok(("", section)) => Some(section),
+        _ => None,
+    }
+}
+
+fn section_heading(input: &str) -> IResult<&str, Section> {
+    map_opt(heading_line, |(_, text)| as_section(&text))(input)
+}
+
+fn recipe_heading(level: usize, input: &str) -> IResult<&str, MdFragment> {
+    map(
+        preceded(multispace0, verify(heading, |(l, text)| *l == level && as_section(text).is_none())),
+        |(_, text)| text,
+    )(input)
+}
+
+// deeper headings that don't name a section, like "### For the sauce"
+fn sub_heading(level: usize, input: &str) -> IResult<&str, MdFragment> {
+    map(verify(heading_line, |(l, _)| *l > level), |(_, text)| text)(input)
+}
+
+fn paragraph(input: &str) -> IResult<&str, MdFragment> {
+    preceded(
+        pair(newline, multispace0),
+        preceded(not(char('#')), verify(md_text, |t: &MdFragment| !t.is_empty())),
+    )(input)
+}
+
+fn heading_body(level: usize, input: &str) -> IResult<&str, Vec<BodyLine>> {
+    many1(alt((
+        map(section_heading, BodyLine::Section),
+        map(section_header, BodyLine::Section),
+        map(servings_header, BodyLine::Servings),
+        map(list, BodyLine::Items),
+        map(|i| sub_heading(level, i), BodyLine::Paragraph),
+        map(paragraph, BodyLine::Paragraph),
+    )))(input)
+}
+
+fn has_section_headings(input: &str) -> bool {
+    input
+        .lines()
+        .filter_map(|l| heading(l.trim_start()).ok())
+        .any(|(_, (_, text))| as_section(&text).is_some())
+}
+
+// the shallowest heading level that isn't just a title above the recipes
+fn recipe_heading_level(input: &str) -> Option<usize> {
+    let levels: Vec<usize> = input
+        .lines()
+        .filter_map(|l| heading(l.trim_start()).ok())
+        .filter(|(_, (_, text))| as_section(text).is_none())
+        .map(|(_, (level, _))| level)
+        .collect();
+
+    let top = *levels.iter().min()?;
+    let at_top = levels.iter().filter(|&&l| l == top).count();
+
+    match levels.iter().filter(|&&l| l > top).min() {
+        Some(&next) if at_top == 1 => Some(next),
+        _ => Some(top),
+    }
+}
+
+fn ingredient_line(input: &str) -> IResult<&str, RecipeIngredient> {
+    let (rest, quantity) = opt(terminated(quantity, space0))(input)?;
+    let (rest, _) = opt(tag_no_case("of "))(rest)?;
+    let (rest, name) = take_till(|c| c == ',' || c == '(')(rest)?;
+
+    let note = rest
+        .replace(['(', ')'], "")
+        .trim_start_matches([',', ' '])
+        .trim()
+        .to_owned();
+
+    Ok(("", RecipeIngredient {
+        quantity,
+        name: name.trim().to_owned(),
+        note: if note.is_empty() { None } else { Some(note) },
+    }))
+}
+
+/// Splits a single ingredient line like "2 cups rice, rinsed" into its parts.
+pub fn parse_ingredient(input: &str) -> RecipeIngredient {
+    let (_, ingredient) = ingredient_line(input.trim())
+        .expect("ingredient_line accepts any input");
+    ingredient
+}
+
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct RecipeIngredient {
+    pub quantity: Option<Quantity>,
+    pub name: String,
+    pub note: Option<String>,
+}
+
+/// A numbered or bulleted instruction, with whatever was nested under it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Step {
+    pub text: MdFragment,
+    pub substeps: Vec<Step>,
+}
+
+impl Step {
+    pub fn new(text: MdFragment) -> Step {
+        Step { text, substeps: vec![] }
+    }
+
+    /// Rewrites the text of the step and of all its sub-steps, keeping the formatting.
+    pub fn map_text(&self, f: &impl Fn(&str) -> String) -> Step {
+        Step {
+            text: self.text.iter().map(|e| e.map_text(f)).collect(),
+            substeps: self.substeps.iter().map(|s| s.map_text(f)).collect(),
         }
     }
 }
 
-type MdFragment = Vec<MdElement>;
+impl IntoView for Step {
+    fn into_view(self) -> leptos::View {
+        let substeps = (!self.substeps.is_empty()).then(|| view! {
+            <ul class="ml-4 list-disc">
+                {self.substeps.into_iter().map(|s| view! {<li>{s.into_view()}</li>}).collect_view()}
+            </ul>
+        });
+        view! { {self.text.into_view()}{substeps} }.into_view()
+    }
+}
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct Recipe {
     pub name: MdFragment,
-    pub instructions: Vec<MdFragment>,
-}
-
-fn md_special_text(input: &str) -> IResult<&str, MdElement> {
-    // bold **abc**
-    // italics *abc*
-    // text ahoj*jak jse mas\n\n
-    if let Ok((rest, strong)) = md_strong(input) {
-        return Ok((rest, MdElement::Strong(strong.to_owned())))
-    };
-
-    match md_emphasis(input) {
-        Ok((rest, em)) => return Ok((rest, MdElement::Em(em.to_owned()))),
-        Err(e) => return Err(e),
-    };
-}
-
-fn md_plain_text(input: &str) -> IResult<&str, MdElement> {
-    let (rest, t) = recognize(many_till(
-        anychar,
-        peek(
-            alt((
-                tag("\n\n"),
-                tag("*"),
-                tag("_"),
-                // delimited(multispace0, digit1, tag(". ")),
-                ordered_list_bullet,
-                unordered_list_bullet,
-                eof
-            ))
-        )
-    ))(input)?;
-    Ok((rest, MdElement::Text(t.to_owned())))
+    pub servings: Option<u32>,
+    pub ingredients: Vec<RecipeIngredient>,
+    pub instructions: Vec<Step>,
+}
+
+// list items are instructions until an "Ingredients:" section says otherwise, so recipes without
+// any sections keep working the way they used to
+struct RecipeBuilder {
+    section: Section,
+    servings: Option<u32>,
+    ingredients: Vec<RecipeIngredient>,
+    instructions: Vec<Step>,
+}
+
+impl RecipeBuilder {
+    fn add(&mut self, item: Step) {
+        // "- Ingredients:" as a bullet, possibly followed by an inline list or a nested one
+        let text = fragment_text(&item.text);
+        let trimmed = text.trim();
+        if let Ok((_, n)) = servings(trimmed) {
+            self.servings = Some(n);
+            return;
+        }
+        if let Ok((inline, s)) = section_name(trimmed) {
+            let header = &trimmed[..trimmed.len() - inline.len()];
+            if inline.is_empty() || header.contains(':') {
+                self.section = s;
+                let inline = inline.trim();
+                match (s, inline.is_empty()) {
+                    (_, true) => {}
+                    (Section::Ingredients, false) => self.ingredients.extend(
+                        inline.split(',').filter(|i| !i.trim().is_empty()).map(parse_ingredient)
+                    ),
+                    (Section::Instructions, false) => self.instructions.push(Step::new(vec![MdElement::Text(inline.to_owned())])),
+                }
+                item.substeps.into_iter().for_each(|i| self.add(i));
+                return;
+            }
+        }
+
+        match self.section {
+            Section::Ingredients => {
+                // "For the sauce:" only groups the ingredients under it
+                if item.substeps.is_empty() || !trimmed.ends_with(':') {
+                    self.ingredients.push(parse_ingredient(&text));
+                }
+                item.substeps.into_iter().for_each(|i| self.add(i));
+            }
+            Section::Instructions => self.instructions.push(item),
+        }
+    }
+}
+
+impl Recipe {
+    /// `last` is for the recipe that ends the answer, where the model signs off.
+    fn from_body(name: MdFragment, body: Vec<BodyLine>, last: bool) -> Recipe {
+        let mut builder = RecipeBuilder {
+            section: Section::Instructions,
+            servings: find_servings(&fragment_text(&name)),
+            ingredients: Vec::new(),
+            instructions: Vec::new(),
+        };
+        // paragraphs only count when a section heading asked for them and no list followed, so
+        // descriptions and closing remarks don't end up in the recipe. This is how many the
+        // section took so far, `None` outside of one.
+        let mut prose_steps: Option<usize> = None;
+        let lines = body.len();
+
+        for (i, line) in body.into_iter().enumerate() {
+            match line {
+                BodyLine::Section(s) => {
+                    builder.section = s;
+                    prose_steps = Some(0);
+                }
+                BodyLine::Servings(n) => builder.servings = Some(n),
+                // a paragraph after the steps at the very end of the answer is the "Enjoy!"
+                BodyLine::Paragraph(_) if last && i + 1 == lines && prose_steps > Some(0) => {}
+                BodyLine::Paragraph(p) if prose_steps.is_some() => {
+                    builder.add(Step::new(p));
+                    prose_steps = prose_steps.map(|n| n + 1);
+                }
+                BodyLine::Paragraph(_) => {}
+                BodyLine::Items(items) => {
+                    prose_steps = None;
+                    items.into_iter().for_each(|i| builder.add(i));
+                }
+            }
+        }
+
+        Recipe {
+            name,
+            servings: builder.servings,
+            ingredients: builder.ingredients,
+            instructions: builder.instructions,
+        }
+    }
 }
 
 // TODO(filip): this requires to explicitly specify, what is not text anymore
 // it is probably pretty expensive, since it has to keep checking if some of the parsers match for
 // each byte i imagine
-fn md_text(input: &str) -> IResult<&str, Vec<MdElement>> {
-    let (rest, (t, _)) = many_till(
-        alt((
-            md_special_text,
-            md_plain_text
+fn md_text(input: &str) -> IResult<&str, MdFragment> {
+    map(
+        recognize(many_till(
+            anychar,
+            peek(
+                alt((
+                    tag("\n\n"),
+                    eof,
+                    ordered_list_bullet,
+                    unordered_list_bullet,
+                    recognize(section_header),
+                    recognize(servings_header),
+                    recognize(heading_line),
+                ))
+            )
         )),
-        peek(
-            alt((
-                tag("\n\n"),
-                eof,
-                ordered_list_bullet,
-                unordered_list_bullet,
-            ))
-        )
-    )(input)?;
-    println!("rest: {:?}, t: {:?}", rest, t);
-    Ok((rest, t))
+        inline,
+    )(input)
 }
 
 
-
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::recipe::{Amount, Unit};
 
     #[test]
     fn test_ordered_list_bullet() {
@@ -275,9 +724,9 @@ mod tests {
         let (rest, li) = ordered_list_item("\n1. this is an **item** *here\nlong *line\n\ntext").unwrap();
         assert_eq!(li, vec![
             MdElement::Text("this is an ".to_string()),
-            MdElement::Strong("item".to_string()),
+            MdElement::Strong(vec![MdElement::Text("item".to_string())]),
             MdElement::Text(" ".to_string()),
-            MdElement::Em("here\nlong ".to_string()),
+            MdElement::Em(vec![MdElement::Text("here\nlong ".to_string())]),
             MdElement::Text("line".to_string())
         ]);
         assert_eq!(rest, "\n\ntext");
@@ -285,82 +734,61 @@ mod tests {
 
     #[test]
     fn test_unordered_list() {
-        let (rest, li) = unordered_list("\n- this is an item here\nlong - line\n- another item\n- another\n\n").unwrap();
+        let (rest, li) = list("\n- this is an item here\nlong - line\n- another item\n- another\n\n").unwrap();
         assert_eq!(li, vec![
-            vec![MdElement::Text("this is an item here\nlong - line".to_string())],
-            vec![MdElement::Text("another item".to_string())],
-            vec![MdElement::Text("another".to_string())],
+            Step::new(vec![MdElement::Text("this is an item here\nlong - line".to_string())]),
+            Step::new(vec![MdElement::Text("another item".to_string())]),
+            Step::new(vec![MdElement::Text("another".to_string())]),
         ]);
         assert_eq!(rest, "\n\n");
     }
 
     #[test]
     fn test_unordered_list_no_newline_end() {
-        let (rest, li) = unordered_list("\n- this is an item here\nlong - line\n- another item\n- another").unwrap();
+        let (rest, li) = list("\n- this is an item here\nlong - line\n- another item\n- another").unwrap();
         assert_eq!(li, vec![
-            vec![MdElement::Text("this is an item here\nlong - line".to_string())],
-            vec![MdElement::Text("another item".to_string())],
-            vec![MdElement::Text("another".to_string())],
+            Step::new(vec![MdElement::Text("this is an item here\nlong - line".to_string())]),
+            Step::new(vec![MdElement::Text("another item".to_string())]),
+            Step::new(vec![MdElement::Text("another".to_string())]),
         ]);
         assert_eq!(rest, "");
     }
 
     #[test]
-    fn test_strong_elem() {
-        let (rest, strong) = md_strong("**this is strong** and this is not").unwrap();
-        assert_eq!(strong, "this is strong");
-        assert_eq!(rest, " and this is not");
-    }
-
-    #[test]
-    fn test_strong_elem_newline() {
-        let (rest, strong) = md_strong("**this is strong\nthis should still be strong** and this is not").unwrap();
-        assert_eq!(strong, "this is strong\nthis should still be strong");
-        assert_eq!(rest, " and this is not");
-    }
-
-    #[test]
-    fn test_md_elem_inside_delimited() {
-        let res: IResult<&str, &str> = md_elem_inside("**")("this is strong\nthis should still be strong** and this is not");
-        let (rest, strong) = res.unwrap();
-        assert_eq!(strong, "this is strong\nthis should still be strong");
-        assert_eq!(rest, "** and this is not");
+    fn test_md_text_strong_newline() {
+        let (rest, t) = md_text("**this is strong\nthis should still be strong** and this is not").unwrap();
+        assert_eq!(t, vec![
+            MdElement::Strong(vec![MdElement::Text("this is strong\nthis should still be strong".to_string())]),
+            MdElement::Text(" and this is not".to_string()),
+        ]);
+        assert_eq!(rest, "");
     }
 
     #[test]
-    fn test_md_elem_inside_nl_block() {
-        let res: IResult<&str, &str> = md_elem_inside("**")("this is strong\nthis should still be strong\n\n and this is not");
-        let (rest, strong) = res.unwrap();
-        assert_eq!(strong, "this is strong\nthis should still be strong");
-        assert_eq!(rest, "\n\n and this is not");
+    fn test_md_text_unclosed_strong_nl_block() {
+        let (rest, t) = md_text("**this is strong\nthis should still be strong\n\n and this is not**").unwrap();
+        assert_eq!(t, vec![MdElement::Text("**this is strong\nthis should still be strong".to_string())]);
+        assert_eq!(rest, "\n\n and this is not**");
     }
 
     #[test]
-    fn test_md_plain_text() {
-        let (rest, text) = md_plain_text("this is text\nand this is also\n\nthis is not").unwrap();
-        match text {
-            MdElement::Em(_) => todo!(),
-            MdElement::Strong(_) => todo!(),
-            MdElement::Text(t) => assert_eq!(t, "this is text\nand this is also"),
-        }
+    fn test_md_text_plain() {
+        let (rest, t) = md_text("this is text\nand this is also\n\nthis is not").unwrap();
+        assert_eq!(t, vec![MdElement::Text("this is text\nand this is also".to_string())]);
         assert_eq!(rest, "\n\nthis is not");
     }
 
     #[test]
-    fn test_md_plain_text_inline() {
-        let (rest, text) = md_plain_text("this is text\nand this is also").unwrap();
-        match text {
-            MdElement::Em(_) => todo!(),
-            MdElement::Strong(_) => todo!(),
-            MdElement::Text(t) => assert_eq!(t, "this is text\nand this is also"),
-        }
+    fn test_md_text_plain_inline() {
+        let (rest, t) = md_text("this is text\nand this is also").unwrap();
+        assert_eq!(t, vec![MdElement::Text("this is text\nand this is also".to_string())]);
         assert_eq!(rest, "");
     }
 
     #[test]
     fn test_md_text() {
         let (rest, t) = md_text("what about this *this is an item here*\n1. this not").unwrap();
-        assert_eq!(t, vec![MdElement::Text("what about this ".to_string()), MdElement::Em("this is an item here".to_string())]);
+        assert_eq!(t, vec![MdElement::Text("what about this ".to_string()), MdElement::Em(vec![MdElement::Text("this is an item here".to_string())])]);
         assert_eq!(rest, "\n1. this not");
     }
 
@@ -380,31 +808,37 @@ mod tests {
 
         assert_eq!(r, vec![
             Recipe{
-                name: vec![MdElement::Strong("rec1:".to_owned())],
+                name: vec![MdElement::Strong(vec![MdElement::Text("rec1:".to_owned())])],
+                servings: None,
+                ingredients: vec![],
                 instructions: vec![
-                    vec![MdElement::Text("a.".to_owned())],
-                    vec![MdElement::Text("b.".to_owned())],
-                    vec![MdElement::Text("c.".to_owned())],
-                    vec![MdElement::Text("d.".to_owned())],
-                    vec![MdElement::Text("e.".to_owned())],
+                    Step::new(vec![MdElement::Text("a.".to_owned())]),
+                    Step::new(vec![MdElement::Text("b.".to_owned())]),
+                    Step::new(vec![MdElement::Text("c.".to_owned())]),
+                    Step::new(vec![MdElement::Text("d.".to_owned())]),
+                    Step::new(vec![MdElement::Text("e.".to_owned())]),
             ]},
             Recipe{
-                name: vec![MdElement::Strong("rec2:".to_owned())],
+                name: vec![MdElement::Strong(vec![MdElement::Text("rec2:".to_owned())])],
+                servings: None,
+                ingredients: vec![],
                 instructions: vec![
-                    vec![MdElement::Text("a.".to_owned())],
-                    vec![MdElement::Text("b.".to_owned())],
-                    vec![MdElement::Text("c.".to_owned())],
-                    vec![MdElement::Text("d.".to_owned())],
-                    vec![MdElement::Text("e.".to_owned())],
-                    vec![MdElement::Text("f.".to_owned())],
+                    Step::new(vec![MdElement::Text("a.".to_owned())]),
+                    Step::new(vec![MdElement::Text("b.".to_owned())]),
+                    Step::new(vec![MdElement::Text("c.".to_owned())]),
+                    Step::new(vec![MdElement::Text("d.".to_owned())]),
+                    Step::new(vec![MdElement::Text("e.".to_owned())]),
+                    Step::new(vec![MdElement::Text("f.".to_owned())]),
             ]},
             Recipe{
-                name: vec![MdElement::Strong("rec3:".to_owned())],
+                name: vec![MdElement::Strong(vec![MdElement::Text("rec3:".to_owned())])],
+                servings: None,
+                ingredients: vec![],
                 instructions: vec![
-                    vec![MdElement::Text("a.".to_owned())],
-                    vec![MdElement::Text("b.".to_owned())],
-                    vec![MdElement::Text("c.".to_owned())],
-                    vec![MdElement::Text("d.".to_owned())],
+                    Step::new(vec![MdElement::Text("a.".to_owned())]),
+                    Step::new(vec![MdElement::Text("b.".to_owned())]),
+                    Step::new(vec![MdElement::Text("c.".to_owned())]),
+                    Step::new(vec![MdElement::Text("d.".to_owned())]),
             ]},
         ]);
     }
@@ -417,32 +851,294 @@ mod tests {
 
         assert_eq!(r, vec![
             Recipe{
-                name: vec![MdElement::Strong("rec1:".to_owned())],
+                name: vec![MdElement::Strong(vec![MdElement::Text("rec1:".to_owned())])],
+                servings: None,
+                ingredients: vec![],
                 instructions: vec![
-                    vec![MdElement::Text("a.".to_owned())],
-                    vec![MdElement::Text("b.".to_owned())],
-                    vec![MdElement::Text("c.".to_owned())],
-                    vec![MdElement::Text("d.".to_owned())],
-                    vec![MdElement::Text("e.".to_owned())],
+                    Step::new(vec![MdElement::Text("a.".to_owned())]),
+                    Step::new(vec![MdElement::Text("b.".to_owned())]),
+                    Step::new(vec![MdElement::Text("c.".to_owned())]),
+                    Step::new(vec![MdElement::Text("d.".to_owned())]),
+                    Step::new(vec![MdElement::Text("e.".to_owned())]),
             ]},
             Recipe{
-                name: vec![MdElement::Strong("rec2:".to_owned())],
+                name: vec![MdElement::Strong(vec![MdElement::Text("rec2:".to_owned())])],
+                servings: None,
+                ingredients: vec![],
                 instructions: vec![
-                    vec![MdElement::Text("a.".to_owned())],
-                    vec![MdElement::Text("b.".to_owned())],
-                    vec![MdElement::Text("c.".to_owned())],
-                    vec![MdElement::Text("d.".to_owned())],
-                    vec![MdElement::Text("e.".to_owned())],
-                    vec![MdElement::Text("f.".to_owned())],
+                    Step::new(vec![MdElement::Text("a.".to_owned())]),
+                    Step::new(vec![MdElement::Text("b.".to_owned())]),
+                    Step::new(vec![MdElement::Text("c.".to_owned())]),
+                    Step::new(vec![MdElement::Text("d.".to_owned())]),
+                    Step::new(vec![MdElement::Text("e.".to_owned())]),
+                    Step::new(vec![MdElement::Text("f.".to_owned())]),
             ]},
             Recipe{
-                name: vec![MdElement::Strong("rec3:".to_owned())],
+                name: vec![MdElement::Strong(vec![MdElement::Text("rec3:".to_owned())])],
+                servings: None,
+                ingredients: vec![],
                 instructions: vec![
-                    vec![MdElement::Text("a.".to_owned())],
-                    vec![MdElement::Text("b.".to_owned())],
-                    vec![MdElement::Text("c.".to_owned())],
-                    vec![MdElement::Text("d.".to_owned())],
+                    Step::new(vec![MdElement::Text("a.".to_owned())]),
+                    Step::new(vec![MdElement::Text("b.".to_owned())]),
+                    Step::new(vec![MdElement::Text("c.".to_owned())]),
+                    Step::new(vec![MdElement::Text("d.".to_owned())]),
             ]},
         ]);
     }
+
+    #[test]
+    fn test_section_header() {
+        let (rest, section) = section_header("\n   **Ingredients:**\n   - 2 cups rice").unwrap();
+        assert_eq!(section, Section::Ingredients);
+        assert_eq!(rest, "\n   - 2 cups rice");
+    }
+
+    #[test]
+    fn test_section_header_not_alone() {
+        let res = section_header("\nSteps are easy");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_parse_ingredient() {
+        assert_eq!(parse_ingredient("1 1/2 cups of rice, rinsed"), RecipeIngredient {
+            quantity: Some(Quantity { amount: Amount::Single(1.5), unit: Some(Unit::Cup) }),
+            name: "rice".to_owned(),
+            note: Some("rinsed".to_owned()),
+        });
+        assert_eq!(parse_ingredient("2 potatoes (peeled)"), RecipeIngredient {
+            quantity: Some(Quantity { amount: Amount::Single(2.0), unit: None }),
+            name: "potatoes".to_owned(),
+            note: Some("peeled".to_owned()),
+        });
+        assert_eq!(parse_ingredient("Salt"), RecipeIngredient {
+            quantity: None,
+            name: "Salt".to_owned(),
+            note: None,
+        });
+    }
+
+    #[test]
+    fn test_parse_recipe_sections() {
+        let inp = "Here you go:\n\n1. **Ham Hash:**\n   Ingredients:\n   - 200 g ham, diced\n   - 3 potatoes\n   Instructions:\n   - Fry the potatoes.\n   - Add the ham.\n\n2. **Rice:**\n   - **Ingredients:**\n     - 1 cup rice\n   - **Instructions:**\n     - Cook the rice.\n\nEnjoy!";
+
+        let r = parse(inp).unwrap();
+
+        assert_eq!(r, vec![
+            Recipe{
+                name: vec![MdElement::Strong(vec![MdElement::Text("Ham Hash:".to_owned())])],
+                servings: None,
+                ingredients: vec![
+                    RecipeIngredient { quantity: Some(Quantity { amount: Amount::Single(200.0), unit: Some(Unit::Gram) }), name: "ham".to_owned(), note: Some("diced".to_owned()) },
+                    RecipeIngredient { quantity: Some(Quantity { amount: Amount::Single(3.0), unit: None }), name: "potatoes".to_owned(), note: None },
+                ],
+                instructions: vec![
+                    Step::new(vec![MdElement::Text("Fry the potatoes.".to_owned())]),
+                    Step::new(vec![MdElement::Text("Add the ham.".to_owned())]),
+            ]},
+            Recipe{
+                name: vec![MdElement::Strong(vec![MdElement::Text("Rice:".to_owned())])],
+                servings: None,
+                ingredients: vec![
+                    RecipeIngredient { quantity: Some(Quantity { amount: Amount::Single(1.0), unit: Some(Unit::Cup) }), name: "rice".to_owned(), note: None },
+                ],
+                instructions: vec![
+                    Step::new(vec![MdElement::Text("Cook the rice.".to_owned())]),
+            ]},
+        ]);
+    }
+
+    #[test]
+    fn test_parse_recipe_servings() {
+        let inp = "Ideas:\n\n1. **Ham Hash:**\n   Serves 4\n   - Fry the potatoes.\n\n2. **Rice (servings: 3):**\n   - Cook the rice.\n\n3. **Eggs:**\n   - Servings: 1\n   - Boil the eggs.";
+
+        let r = parse(inp).unwrap();
+
+        assert_eq!(r.iter().map(|r| r.servings).collect::<Vec<_>>(), vec![Some(4), Some(3), Some(1)]);
+        assert_eq!(r[0].instructions, vec![Step::new(vec![MdElement::Text("Fry the potatoes.".to_owned())])]);
+        assert_eq!(r[2].instructions, vec![Step::new(vec![MdElement::Text("Boil the eggs.".to_owned())])]);
+    }
+
+    #[test]
+    fn test_parse_no_recipes() {
+        let e = parse("Sorry, I can't help with that.\n\nMaybe try again?").unwrap_err();
+        assert_eq!((e.line, e.column), (3, 1));
+        assert_eq!(e.snippet, "Maybe try again?");
+    }
+
+    #[test]
+    fn test_parse_recipe_without_body() {
+        let e = parse("Ideas:\n\n1. **Ham Hash:**\n\nThat's all.").unwrap_err();
+        assert_eq!((e.line, e.column), (5, 1));
+        assert!(e.expected.contains("bullet list"));
+    }
+
+    #[test]
+    fn test_next_recipe() {
+        assert_eq!(Layout::OrderedList.next_recipe("\n1. a\nb\n\n2. c"), Some("\n\n2. c"));
+        assert_eq!(Layout::OrderedList.next_recipe("\n1. a\nb"), None);
+    }
+
+    #[test]
+    fn test_parse_lenient_skips_broken_recipe() {
+        let inp = "Ideas:\n\n1. **rec1:**\n   - a.\n\n2. **rec2:**\n\nThis one has no steps.\n\n3. **rec3:**\n   - c.\n\nEnjoy!";
+
+        let report = parse_lenient(inp);
+
+        assert_eq!(report.recipes.iter().map(|r| r.name.clone()).collect::<Vec<_>>(), vec![
+            vec![MdElement::Strong(vec![MdElement::Text("rec1:".to_owned())])],
+            vec![MdElement::Strong(vec![MdElement::Text("rec3:".to_owned())])],
+        ]);
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].line, 8);
+        assert_eq!(report.warnings[0].expected, EXPECTED_RECIPE_BODY);
+        assert!(report.warnings[0].snippet.starts_with("This one has no steps."));
+    }
+
+    #[test]
+    fn test_parse_lenient_matches_parse() {
+        let inp = "Sure! Here are a few recipe ideas using:\n\n1. **rec1:**\n   - a.\n   - b.\n\n2. **rec2:**\n   - a.\n\nRemember to adjust. Enjoy your meal!";
+
+        let report = parse_lenient(inp);
+
+        assert_eq!(report.recipes, parse(inp).unwrap());
+        assert_eq!(report.warnings, vec![]);
+    }
+
+    #[test]
+    fn test_parse_lenient_nothing() {
+        let report = parse_lenient("Sorry, I can't help with that.");
+        assert_eq!(report.recipes, vec![]);
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_lenient_skips_text_between_recipes() {
+        let inp = "Ideas:\n\n1. **rec1:**\n   - a.\n\nOr, if you have more time:\n\n2. **rec2:**\n   - b.";
+
+        let report = parse_lenient(inp);
+
+        assert_eq!(report.recipes.len(), 2);
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!((report.warnings[0].line, report.warnings[0].column), (6, 1));
+        assert_eq!(report.warnings[0].expected, Layout::OrderedList.expected_name());
+    }
+
+    #[test]
+    fn test_heading() {
+        assert_eq!(heading("## **Ham Hash** ##\nrest"), Ok(("\nrest", (2, vec![MdElement::Strong(vec![MdElement::Text("Ham Hash".to_owned())])]))));
+        assert!(heading("##Ham").is_err());
+        assert!(heading("####### Ham").is_err());
+    }
+
+    #[test]
+    fn test_recipe_heading_level() {
+        assert_eq!(recipe_heading_level("## a\n### Ingredients\n## b"), Some(2));
+        assert_eq!(recipe_heading_level("# Ideas\n## a\n### Steps\n## b"), Some(2));
+        assert_eq!(recipe_heading_level("# Only one"), Some(1));
+        assert_eq!(recipe_heading_level("1. **a:**\n   - b"), None);
+    }
+
+    #[test]
+    fn test_parse_headings() {
+        let inp = "# Dinner ideas\n\nHere are two recipes.\n\n## Ham Hash\n\nA quick breakfast.\n\n### Ingredients\n- 2 cups diced ham\n- 3 potatoes\n\n### Steps\n1. Fry the ham.\n2. Add the **potatoes**.\n\n## Boiled Eggs\nServes 2\n\n### Ingredients\n- 4 eggs\n\n### Instructions\nBoil the eggs for 8 minutes.\n\nEnjoy!";
+
+        let r = parse(inp).unwrap();
+
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].name, vec![MdElement::Text("Ham Hash".to_owned())]);
+        assert_eq!(r[0].ingredients.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), vec!["diced ham", "potatoes"]);
+        assert_eq!(r[0].instructions, vec![
+            Step::new(vec![MdElement::Text("Fry the ham.".to_owned())]),
+            Step::new(vec![MdElement::Text("Add the ".to_owned()), MdElement::Strong(vec![MdElement::Text("potatoes".to_owned())]), MdElement::Text(".".to_owned())]),
+        ]);
+        assert_eq!(r[1].servings, Some(2));
+        assert_eq!(r[1].ingredients.len(), 1);
+        // the closing remark is not a step
+        assert_eq!(r[1].instructions, vec![Step::new(vec![MdElement::Text("Boil the eggs for 8 minutes.".to_owned())])]);
+        assert_eq!(parse_lenient(inp).recipes, r);
+    }
+
+    #[test]
+    fn test_parse_prose_instructions() {
+        let inp = "## Eggs
+### Instructions
+Boil the eggs.
+
+Peel them.
+
+## Toast
+### Instructions
+Toast the bread.";
+        let r = parse(inp).unwrap();
+        assert_eq!(r[0].instructions.len(), 2);
+        assert_eq!(r[1].instructions, vec![Step::new(vec![MdElement::Text("Toast the bread.".to_owned())])]);
+    }
+
+    #[test]
+    fn test_parse_headings_without_foreword() {
+        let inp = "## rec1\n### Ingredients\n- a\n### Steps\n- b\n## rec2\n### Steps\n- c";
+
+        let r = parse(inp).unwrap();
+
+        assert_eq!(r.iter().map(|r| fragment_text(&r.name)).collect::<Vec<_>>(), vec!["rec1", "rec2"]);
+        assert_eq!(r[1].instructions, vec![Step::new(vec![MdElement::Text("c".to_owned())])]);
+    }
+
+    #[test]
+    fn test_parse_prefers_layout_with_more_recipes() {
+        // a title heading above a numbered list shouldn't be mistaken for a recipe
+        let inp = "## Recipes\n\n1. **rec1:**\n   - a.\n\n2. **rec2:**\n   - b.";
+
+        assert_eq!(parse(inp).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_ordered_list_bullet_paren() {
+        let (rest, bullet) = ordered_list_bullet("\n   12) this is an item here").unwrap();
+        assert_eq!(bullet, "12");
+        assert_eq!(rest, "this is an item here");
+        assert!(ordered_list_bullet("\n1.5 cups").is_err());
+    }
+
+    #[test]
+    fn test_unordered_list_bullet() {
+        for inp in ["\n- item", "\n* item", "\n  + item"] {
+            let (rest, _) = unordered_list_bullet(inp).unwrap();
+            assert_eq!(rest, "item");
+        }
+        assert!(unordered_list_bullet("\n*item*").is_err());
+        assert!(unordered_list_bullet("\n-5 degrees").is_err());
+    }
+
+    #[test]
+    fn test_list_nested() {
+        let (rest, li) = list("\n1. Make the sauce:\n   * Melt the butter.\n   * Whisk in flour.\n     + Keep whisking.\n2. Pour it over.\n\nDone").unwrap();
+        let text = |s: &str| vec![MdElement::Text(s.to_owned())];
+        assert_eq!(li, vec![
+            Step {
+                text: text("Make the sauce:"),
+                substeps: vec![
+                    Step::new(text("Melt the butter.")),
+                    Step { text: text("Whisk in flour."), substeps: vec![Step::new(text("Keep whisking."))] },
+                ],
+            },
+            Step::new(text("Pour it over.")),
+        ]);
+        assert_eq!(rest, "\n\nDone");
+    }
+
+    #[test]
+    fn test_parse_recipe_nested_steps() {
+        let inp = "Ideas:\n\n1. **Pasta:**\n   Ingredients:\n   * 200 g pasta\n   * For the sauce:\n     * 2 tbsp butter\n     * 1 cup milk\n   Instructions:\n   1. Boil the pasta.\n   2. For the sauce:\n      - Melt the butter.\n      - Add the milk.\n\n2. **Toast:**\n   + Toast the bread.";
+
+        let r = parse(inp).unwrap();
+
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].ingredients.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), vec!["pasta", "butter", "milk"]);
+        assert_eq!(r[0].instructions.len(), 2);
+        assert_eq!(r[0].instructions[1].text, vec![MdElement::Text("For the sauce:".to_owned())]);
+        assert_eq!(r[0].instructions[1].substeps.len(), 2);
+        assert_eq!(r[1].instructions, vec![Step::new(vec![MdElement::Text("Toast the bread.".to_owned())])]);
+    }
 }
@@ -0,0 +1,137 @@
+use super::quantity::{map_quantities, Quantity, Unit};
+use super::recipe_parser::Recipe;
+
+
+/// Recipes that don't say how many people they feed are assumed to serve this many.
+pub const DEFAULT_SERVINGS: u32 = 2;
+
+impl Recipe {
+    /// Multiplies every ingredient amount, and every quantity mentioned in the instructions, by
+    /// `factor`.
+    pub fn scale(&self, factor: f64) -> Recipe {
+        Recipe {
+            name: self.name.clone(),
+            servings: self.servings.map(|s| ((s as f64 * factor).round() as u32).max(1)),
+            ingredients: self.ingredients
+                .iter()
+                .map(|i| {
+                    let mut i = i.clone();
+                    i.quantity = i.quantity.map(|q| q.scale(factor));
+                    i
+                })
+                .collect(),
+            instructions: self.instructions
+                .iter()
+                .map(|step| step.map_text(&|s| scale_text(s, factor)))
+                .collect(),
+        }
+    }
+
+    pub fn with_servings(&self, servings: u32) -> Recipe {
+        let current = self.servings.unwrap_or(DEFAULT_SERVINGS);
+        let mut scaled = self.scale(servings as f64 / current as f64);
+        scaled.servings = Some(servings);
+        scaled
+    }
+}
+
+impl Quantity {
+    pub fn scale(self, factor: f64) -> Quantity {
+        Quantity {
+            amount: self.amount.map(|a| kitchen_round(a * factor, self.unit)),
+            unit: self.unit,
+        }
+    }
+}
+
+// nobody measures 0.37 of a cup, so snap to whatever step makes sense for the unit
+pub(crate) fn kitchen_round(value: f64, unit: Option<Unit>) -> f64 {
+    let step = match unit {
+        Some(Unit::Gram) | Some(Unit::Milliliter) => match value {
+            v if v >= 100.0 => 5.0,
+            v if v >= 10.0 => 1.0,
+            _ => 0.5,
+        },
+        Some(Unit::Kilogram) | Some(Unit::Liter) => 0.05,
+        Some(Unit::Cup) | Some(Unit::Tablespoon) | Some(Unit::Teaspoon) | Some(Unit::Ounce) | Some(Unit::Pound) => match value {
+            v if v >= 4.0 => 0.5,
+            v if v >= 1.0 => 0.25,
+            _ => 0.125,
+        },
+        Some(Unit::Pinch) | Some(Unit::Clove) | Some(Unit::Can) | Some(Unit::Slice) => 1.0,
+        None => 0.5,
+    };
+
+    // dividing by the inverse keeps 24 * 0.05 from turning into 1.2000000000000002
+    let steps = (value / step).round().max(1.0);
+    if step < 1.0 {
+        steps / (1.0 / step).round()
+    } else {
+        steps * step
+    }
+}
+
+pub(crate) fn scale_text(text: &str, factor: f64) -> String {
+    map_quantities(text, |q| q.scale(factor))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::{parse_ingredient, Amount, MdElement, Step};
+
+    fn recipe() -> Recipe {
+        Recipe {
+            name: vec![MdElement::Strong(vec![MdElement::Text("Rice:".to_owned())])],
+            servings: Some(2),
+            ingredients: vec![parse_ingredient("1 cup rice"), parse_ingredient("3 eggs"), parse_ingredient("150 g ham")],
+            instructions: vec![
+                Step::new(vec![MdElement::Text("Boil 2 cups of water for 10 minutes.".to_owned())]),
+                Step {
+                    text: vec![MdElement::Text("Add ".to_owned()), MdElement::Strong(vec![MdElement::Text("150g ham".to_owned())])],
+                    substeps: vec![Step::new(vec![MdElement::Text("Fry 100 g of it first.".to_owned())])],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_kitchen_round() {
+        assert_eq!(kitchen_round(0.37, Some(Unit::Cup)), 0.375);
+        assert_eq!(kitchen_round(1.1, Some(Unit::Cup)), 1.0);
+        assert_eq!(kitchen_round(0.01, Some(Unit::Teaspoon)), 0.125);
+        assert_eq!(kitchen_round(223.0, Some(Unit::Gram)), 225.0);
+        assert_eq!(kitchen_round(1.2, None), 1.0);
+        assert_eq!(kitchen_round(1.3, Some(Unit::Clove)), 1.0);
+    }
+
+    #[test]
+    fn test_scale_text() {
+        assert_eq!(scale_text("Boil 2 cups of water for 10 minutes.", 1.5), "Boil 3 cups of water for 10 minutes.");
+        assert_eq!(scale_text("Add 150g ham, then 1/2 tsp salt", 2.0), "Add 300 g ham, then 1 tsp salt");
+        assert_eq!(scale_text("Step 2: preheat to 200 degrees", 2.0), "Step 2: preheat to 200 degrees");
+    }
+
+    #[test]
+    fn test_with_servings() {
+        let r = recipe().with_servings(3);
+
+        assert_eq!(r.servings, Some(3));
+        assert_eq!(r.ingredients[0].quantity.unwrap().amount, Amount::Single(1.5));
+        assert_eq!(r.ingredients[1].quantity.unwrap().amount, Amount::Single(4.5));
+        assert_eq!(r.ingredients[2].quantity.unwrap().amount, Amount::Single(225.0));
+        assert_eq!(r.instructions[0].text, vec![MdElement::Text("Boil 3 cups of water for 10 minutes.".to_owned())]);
+        assert_eq!(r.instructions[1].text[1], MdElement::Strong(vec![MdElement::Text("225 g ham".to_owned())]));
+        assert_eq!(r.instructions[1].substeps[0].text, vec![MdElement::Text("Fry 150 g of it first.".to_owned())]);
+    }
+
+    #[test]
+    fn test_scale_unknown_servings() {
+        let mut r = recipe();
+        r.servings = None;
+
+        let r = r.with_servings(4);
+        assert_eq!(r.ingredients[0].quantity.unwrap().amount, Amount::Single(2.0));
+    }
+}
@@ -0,0 +1,436 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::markdown::{fragment_text, MdElement};
+use super::recipe_parser::{parse_ingredient, Recipe, Step};
+
+
+const CONTEXT: &str = "https://schema.org";
+
+/// A schema.org `Recipe`, https://schema.org/Recipe. Only the parts we can use are modelled, and
+/// the types are loose because pages in the wild fill them in every way the spec allows.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaRecipe {
+    #[serde(rename = "@context", default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Value>,
+    #[serde(rename = "@type", default)]
+    pub kind: OneOrMany<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, alias = "ingredients", skip_serializing_if = "OneOrMany::is_empty")]
+    pub recipe_ingredient: OneOrMany<String>,
+    #[serde(default, skip_serializing_if = "OneOrMany::is_empty")]
+    pub recipe_instructions: OneOrMany<Instruction>,
+    #[serde(default, skip_serializing_if = "OneOrMany::is_empty")]
+    pub recipe_yield: OneOrMany<TextOrNumber>,
+    /// ISO 8601 duration, like "PT1H30M".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prep_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cook_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nutrition: Option<NutritionInformation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> Default for OneOrMany<T> {
+    fn default() -> Self {
+        OneOrMany::Many(vec![])
+    }
+}
+
+impl<T> OneOrMany<T> {
+    pub fn as_slice(&self) -> &[T] {
+        match self {
+            OneOrMany::One(t) => std::slice::from_ref(t),
+            OneOrMany::Many(ts) => ts,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_slice().is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TextOrNumber {
+    Number(serde_json::Number),
+    Text(String),
+}
+
+impl Display for TextOrNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextOrNumber::Number(n) => write!(f, "{}", n),
+            TextOrNumber::Text(t) => write!(f, "{}", t),
+        }
+    }
+}
+
+/// An entry of `recipeInstructions`. Sections are tried first, they are the only ones with
+/// `itemListElement`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Instruction {
+    Section(Box<HowToSection>),
+    Step(HowToStep),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HowToStep {
+    #[serde(rename = "@type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HowToSection {
+    #[serde(rename = "@type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub item_list_element: OneOrMany<Instruction>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NutritionInformation {
+    #[serde(rename = "@type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serving_size: Option<TextOrNumber>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calories: Option<TextOrNumber>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fat_content: Option<TextOrNumber>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub carbohydrate_content: Option<TextOrNumber>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protein_content: Option<TextOrNumber>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sugar_content: Option<TextOrNumber>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fiber_content: Option<TextOrNumber>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sodium_content: Option<TextOrNumber>,
+}
+
+impl SchemaRecipe {
+    fn is_recipe(value: &Value) -> bool {
+        match value.get("@type") {
+            Some(Value::String(t)) => t == "Recipe",
+            Some(Value::Array(ts)) => ts.iter().any(|t| t == "Recipe"),
+            _ => false,
+        }
+    }
+
+    /// Every `Recipe` in a JSON-LD document, wherever it is. Pages put them at the top level, in
+    /// an array or in an `@graph`.
+    pub fn find_all(json_ld: &str) -> Result<Vec<SchemaRecipe>, serde_json::Error> {
+        fn collect(value: Value, out: &mut Vec<SchemaRecipe>) {
+            match value {
+                Value::Array(values) => values.into_iter().for_each(|v| collect(v, out)),
+                Value::Object(mut object) => {
+                    if SchemaRecipe::is_recipe(&Value::Object(object.clone())) {
+                        // skip the ones that are too broken to read rather than failing them all
+                        if let Ok(recipe) = serde_json::from_value(Value::Object(object)) {
+                            out.push(recipe);
+                        }
+                    } else if let Some(graph) = object.remove("@graph") {
+                        collect(graph, out);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut recipes = vec![];
+        collect(serde_json::from_str(json_ld)?, &mut recipes);
+        Ok(recipes)
+    }
+
+    /// The `<script type="application/ld+json">` body for `recipes`.
+    pub fn to_json_ld(recipes: &[Recipe]) -> String {
+        let recipes: Vec<SchemaRecipe> = recipes.iter().map(SchemaRecipe::from).collect();
+        let json = match recipes.as_slice() {
+            [recipe] => serde_json::to_string(recipe),
+            _ => serde_json::to_string(&serde_json::json!({ "@context": CONTEXT, "@graph": recipes })),
+        };
+        // '<' can only appear inside strings, escaping it keeps "</script>" from ending the tag
+        json.expect("recipes always serialize").replace('<', "\\u003c")
+    }
+}
+
+fn instructions_from_steps(steps: &[Step]) -> Vec<Instruction> {
+    steps
+        .iter()
+        .map(|step| match step.substeps.is_empty() {
+            true => Instruction::Step(HowToStep {
+                kind: Some("HowToStep".to_owned()),
+                name: None,
+                text: Some(fragment_text(&step.text)),
+            }),
+            false => Instruction::Section(Box::new(HowToSection {
+                kind: Some("HowToSection".to_owned()),
+                name: Some(fragment_text(&step.text)),
+                item_list_element: OneOrMany::Many(instructions_from_steps(&step.substeps)),
+            })),
+        })
+        .collect()
+}
+
+impl From<&Recipe> for SchemaRecipe {
+    fn from(recipe: &Recipe) -> SchemaRecipe {
+        SchemaRecipe {
+            context: Some(Value::String(CONTEXT.to_owned())),
+            kind: OneOrMany::One("Recipe".to_owned()),
+            name: Some(fragment_text(&recipe.name)),
+            recipe_ingredient: OneOrMany::Many(recipe.ingredients.iter().map(|i| i.to_string()).collect()),
+            recipe_instructions: OneOrMany::Many(instructions_from_steps(&recipe.instructions)),
+            recipe_yield: match recipe.servings {
+                Some(n) => OneOrMany::One(TextOrNumber::Number(n.into())),
+                None => OneOrMany::default(),
+            },
+            ..SchemaRecipe::default()
+        }
+    }
+}
+
+fn steps_from_instructions(instructions: &[Instruction]) -> Vec<Step> {
+    instructions
+        .iter()
+        .flat_map(|i| match i {
+            // some sites put all the steps in one string, one per line
+            Instruction::Text(text) => clean_text(text)
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(|l| Step::new(vec![MdElement::Text(l.to_owned())]))
+                .collect(),
+            Instruction::Step(step) => step.text
+                .as_ref()
+                .or(step.name.as_ref())
+                .map(|t| Step::new(vec![MdElement::Text(clean_text(t).trim().to_owned())]))
+                .into_iter()
+                .collect(),
+            Instruction::Section(section) => {
+                let substeps = steps_from_instructions(section.item_list_element.as_slice());
+                match &section.name {
+                    Some(name) => vec![Step { text: vec![MdElement::Text(clean_text(name).trim().to_owned())], substeps }],
+                    None => substeps,
+                }
+            }
+        })
+        .filter(|s| !fragment_text(&s.text).is_empty() || !s.substeps.is_empty())
+        .collect()
+}
+
+// "4 servings", "Serves 4-6", 4
+fn servings_from_yield(recipe_yield: &[TextOrNumber]) -> Option<u32> {
+    recipe_yield.iter().find_map(|y| match y {
+        TextOrNumber::Number(n) => n.as_f64().map(|n| n.round() as u32).filter(|n| *n > 0),
+        TextOrNumber::Text(t) => t
+            .split(|c: char| !c.is_ascii_digit())
+            .find(|s| !s.is_empty())
+            .and_then(|n| n.parse().ok()),
+    })
+}
+
+impl From<SchemaRecipe> for Recipe {
+    /// Times and nutrition have no place in `Recipe` yet and are dropped.
+    fn from(schema: SchemaRecipe) -> Recipe {
+        Recipe {
+            name: vec![MdElement::Text(clean_text(schema.name.as_deref().unwrap_or("")).trim().to_owned())],
+            servings: servings_from_yield(schema.recipe_yield.as_slice()),
+            ingredients: schema.recipe_ingredient
+                .as_slice()
+                .iter()
+                .map(|i| clean_text(i))
+                .filter(|i| !i.trim().is_empty())
+                .map(|i| parse_ingredient(&i))
+                .collect(),
+            instructions: steps_from_instructions(schema.recipe_instructions.as_slice()),
+        }
+    }
+}
+
+/// Drops HTML tags and decodes the entities pages leave in their JSON-LD strings.
+pub(crate) fn clean_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(i) = rest.find(['<', '&']) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if rest.starts_with('<') {
+            match rest.find('>') {
+                Some(end) => {
+                    // block tags separate lines, inline ones just go away
+                    let tag = rest[1..end].trim_start_matches('/').to_ascii_lowercase();
+                    if ["br", "p", "li", "div"].iter().any(|t| tag == *t || tag.starts_with(&format!("{} ", t)) || tag.starts_with(&format!("{}/", t))) {
+                        out.push('\n');
+                    }
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    out.push_str(rest);
+                    rest = "";
+                }
+            }
+            continue;
+        }
+
+        let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                "frac12" => Some('½'),
+                "frac14" => Some('¼'),
+                "frac34" => Some('¾'),
+                "deg" => Some('°'),
+                entity => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::{Amount, Quantity, Unit};
+
+    const PAGE_JSON_LD: &str = r#"{
+        "@context": "https://schema.org",
+        "@graph": [
+            {"@type": "WebPage", "name": "Not a recipe"},
+            {
+                "@type": ["Recipe", "NewsArticle"],
+                "name": "Ham &amp; Potato Hash",
+                "recipeYield": ["4", "4 servings"],
+                "totalTime": "PT30M",
+                "recipeIngredient": ["200 g ham, diced", "3 potatoes", "<b>1 tsp</b> salt"],
+                "recipeInstructions": [
+                    {"@type": "HowToStep", "text": "Dice the potatoes."},
+                    {"@type": "HowToSection", "name": "Frying", "itemListElement": [
+                        {"@type": "HowToStep", "text": "Fry the potatoes."},
+                        {"@type": "HowToStep", "name": "Add ham", "text": "Add the ham."}
+                    ]}
+                ],
+                "nutrition": {"@type": "NutritionInformation", "calories": "420 kcal", "proteinContent": 21}
+            }
+        ]
+    }"#;
+
+    fn text(s: &str) -> Vec<MdElement> {
+        vec![MdElement::Text(s.to_owned())]
+    }
+
+    #[test]
+    fn test_find_all() {
+        let recipes = SchemaRecipe::find_all(PAGE_JSON_LD).unwrap();
+
+        assert_eq!(recipes.len(), 1);
+        assert_eq!(recipes[0].total_time.as_deref(), Some("PT30M"));
+        assert_eq!(recipes[0].nutrition.as_ref().unwrap().calories, Some(TextOrNumber::Text("420 kcal".to_owned())));
+        assert_eq!(recipes[0].nutrition.as_ref().unwrap().protein_content.as_ref().unwrap().to_string(), "21");
+    }
+
+    #[test]
+    fn test_to_recipe() {
+        let recipe = Recipe::from(SchemaRecipe::find_all(PAGE_JSON_LD).unwrap().remove(0));
+
+        assert_eq!(recipe.name, text("Ham & Potato Hash"));
+        assert_eq!(recipe.servings, Some(4));
+        assert_eq!(recipe.ingredients[2].quantity, Some(Quantity { amount: Amount::Single(1.0), unit: Some(Unit::Teaspoon) }));
+        assert_eq!(recipe.ingredients[2].name, "salt");
+        assert_eq!(recipe.instructions, vec![
+            Step::new(text("Dice the potatoes.")),
+            Step {
+                text: text("Frying"),
+                substeps: vec![Step::new(text("Fry the potatoes.")), Step::new(text("Add the ham."))],
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_instructions_as_text() {
+        let schema: SchemaRecipe = serde_json::from_str(r#"{"@type": "Recipe", "recipeInstructions": "Boil water.<br/>Add pasta.\n\nDrain.", "recipeYield": 2}"#).unwrap();
+        let recipe = Recipe::from(schema);
+
+        assert_eq!(recipe.servings, Some(2));
+        assert_eq!(recipe.instructions.iter().map(|s| fragment_text(&s.text)).collect::<Vec<_>>(), vec!["Boil water.", "Add pasta.", "Drain."]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let recipe = Recipe::from(SchemaRecipe::find_all(PAGE_JSON_LD).unwrap().remove(0));
+
+        let json_ld = SchemaRecipe::to_json_ld(std::slice::from_ref(&recipe));
+        let back = SchemaRecipe::find_all(&json_ld).unwrap();
+
+        assert_eq!(back.len(), 1);
+        assert_eq!(back[0].context, Some(Value::String(CONTEXT.to_owned())));
+        assert_eq!(Recipe::from(back[0].clone()), recipe);
+    }
+
+    #[test]
+    fn test_to_json_ld_escapes_script() {
+        let recipe = Recipe { name: text("</script><b>"), servings: None, ingredients: vec![], instructions: vec![] };
+
+        let json_ld = SchemaRecipe::to_json_ld(&[recipe.clone(), recipe]);
+
+        assert!(!json_ld.contains('<'));
+        assert_eq!(SchemaRecipe::find_all(&json_ld).unwrap()[0].name.as_deref(), Some("</script><b>"));
+    }
+
+    #[test]
+    fn test_clean_text() {
+        assert_eq!(clean_text("a &amp; b &#189; &#xBD; &bogus; &"), "a & b ½ ½ &bogus; &");
+        assert_eq!(clean_text("<p>one</p><p>two <em>2</em></p>"), "\none\n\ntwo 2\n");
+    }
+}
@@ -0,0 +1,155 @@
+use super::recipe_parser::{parse_lenient, ParseReport, Recipe};
+
+
+/// Parses an answer while it is still arriving. A recipe counts as complete once enough of the
+/// next one has arrived to parse, since until then more steps could still be added to it.
+#[derive(Debug, Clone, Default)]
+pub struct RecipeStream {
+    text: String,
+    // where the recipe still growing starts, once `push` has found it
+    start: usize,
+    // how many recipes after `start` `push` has handed out, when it couldn't find it
+    complete: usize,
+}
+
+impl RecipeStream {
+    pub fn new() -> RecipeStream {
+        RecipeStream::default()
+    }
+
+    /// Adds the next piece of the answer and returns the recipes it completed, if any.
+    pub fn push(&mut self, chunk: &str) -> Vec<Recipe> {
+        let had_line_break = self.text.rfind('\n');
+        self.text.push_str(chunk);
+
+        // nothing new can start before a new line does
+        let end = match self.text.rfind('\n') {
+            Some(end) if Some(end) != had_line_break => end,
+            _ => return vec![],
+        };
+
+        // only the recipe still growing is parsed again, not the whole answer
+        let mut recipes = parse_lenient(&self.text[self.start..end]).recipes;
+        // the last one could still be growing
+        let growing = match recipes.pop() {
+            Some(growing) if recipes.len() > self.complete => growing,
+            _ => return vec![],
+        };
+
+        let new = recipes.split_off(self.complete);
+        match self.find_start(end, &growing) {
+            Some(start) => (self.start, self.complete) = (start, 0),
+            None => self.complete += new.len(),
+        }
+        new
+    }
+
+    // the line in `text[start..end]` that `recipe` starts at: the last one where the rest parses
+    // to just that recipe
+    fn find_start(&self, end: usize, recipe: &Recipe) -> Option<usize> {
+        self.text[self.start..end]
+            .match_indices('\n')
+            .map(|(i, _)| self.start + i + 1)
+            .rev()
+            .find(|&i| parse_lenient(&self.text[i..end]).recipes == [recipe.clone()])
+    }
+
+    /// Everything received so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Parses the whole answer once it's all there. The recipes already handed out by `push` are
+    /// included again, and this report is the one to keep.
+    pub fn finish(self) -> ParseReport {
+        parse_lenient(&self.text)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::{fragment_text, parse};
+
+    const ANSWER: &str = "Here are some ideas:
+
+1. Ham Hash
+Serves: 2
+Ingredients:
+- 200 g ham
+- 3 potatoes
+Instructions:
+- Dice everything.
+- Fry until golden.
+
+2. Potato Soup
+Ingredients:
+- 3 potatoes
+Instructions:
+- Boil the potatoes.
+
+3. Chips
+Ingredients:
+- 3 potatoes
+Instructions:
+- Cut and fry.";
+
+    fn names(recipes: &[Recipe]) -> Vec<String> {
+        recipes.iter().map(|r| fragment_text(&r.name)).collect()
+    }
+
+    #[test]
+    fn test_push_in_small_chunks() {
+        let mut stream = RecipeStream::new();
+        let mut seen = vec![];
+        let mut completed_at = vec![];
+
+        let chars: Vec<char> = ANSWER.chars().collect();
+        for (i, chunk) in chars.chunks(3).enumerate() {
+            let new = stream.push(&chunk.iter().collect::<String>());
+            completed_at.extend(new.iter().map(|_| i * 3));
+            seen.extend(new);
+        }
+
+        assert_eq!(names(&seen), vec!["Ham Hash", "Potato Soup"]);
+        // each one shows up while the next is still arriving
+        assert!(completed_at[0] > ANSWER.find("2. Potato Soup").unwrap());
+        assert!(completed_at[0] < ANSWER.find("3. Chips").unwrap());
+
+        // only "3. Chips" is parsed again as more of it arrives
+        assert!(ANSWER[stream.start..].trim_start().starts_with("3. Chips"), "{}", stream.start);
+
+        let report = stream.finish();
+        assert_eq!(report.recipes, parse(ANSWER).unwrap());
+        assert_eq!(report.recipes[..2], seen[..]);
+    }
+
+    #[test]
+    fn test_push_everything_at_once() {
+        let mut stream = RecipeStream::new();
+
+        assert_eq!(names(&stream.push(ANSWER)), vec!["Ham Hash", "Potato Soup"]);
+        assert_eq!(stream.push(""), vec![]);
+        assert_eq!(stream.text(), ANSWER);
+        assert_eq!(names(&stream.finish().recipes), vec!["Ham Hash", "Potato Soup", "Chips"]);
+    }
+
+    #[test]
+    fn test_push_headings() {
+        let answer = "# Dinner\n\n## Ham Hash\n### Ingredients\n- ham\n### Instructions\n1. Fry.\n\n## Soup\n### Ingredients\n- water\n### Instructions\n1. Boil.\n\n## Chips\n### Ingredients\n- potatoes\n### Instructions\n1. Fry.\n2. Salt.";
+        let mut stream = RecipeStream::new();
+        let seen: Vec<_> = answer.split_inclusive('\n').flat_map(|line| stream.push(line)).collect();
+
+        assert_eq!(names(&seen), vec!["Ham Hash", "Soup"]);
+        assert!(answer[stream.start..].trim_start().starts_with("## Chips"), "{}", stream.start);
+        assert_eq!(stream.finish().recipes[..2], seen[..]);
+    }
+
+    #[test]
+    fn test_nothing_before_a_line_break() {
+        let mut stream = RecipeStream::new();
+        assert_eq!(stream.push("1. Ham Hash"), vec![]);
+        assert_eq!(stream.push("\nIngredients:\n- ham\nInstructions:\n- Fry.\n\n2. Chips"), vec![]);
+    }
+}
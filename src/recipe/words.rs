@@ -0,0 +1,54 @@
+/// The words in `text`, lowercase and singular, so "Eggs," and "egg" are the same word.
+pub fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| singular(&w.to_lowercase()))
+        .collect()
+}
+
+/// Whether the words of `name` come up one after the other in `text`, as whole words. "egg" is
+/// in "2 eggs, beaten" but not in "eggplant".
+pub fn mentions(text: &str, name: &str) -> bool {
+    let (text, name) = (words(text), words(name));
+    !name.is_empty() && text.windows(name.len()).any(|w| w == name)
+}
+
+// good enough for ingredient names, both sides of a comparison go through it anyway
+fn singular(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix("ies").filter(|s| s.len() > 1) {
+        return format!("{}y", stem);
+    }
+    for suffix in ["oes", "ches", "shes", "sses", "xes"] {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            return format!("{}{}", stem, &suffix[..suffix.len() - 2]);
+        }
+    }
+    match word.strip_suffix('s') {
+        Some(stem) if word.len() > 3 && !stem.ends_with(['s', 'u']) => stem.to_owned(),
+        _ => word.to_owned(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words() {
+        assert_eq!(words("2 Eggs, beaten"), vec!["2", "egg", "beaten"]);
+        assert_eq!(words("berries tomatoes peaches glasses boxes"), vec!["berry", "tomato", "peach", "glass", "box"]);
+        assert_eq!(words("oats gas hummus"), vec!["oat", "gas", "hummus"]);
+    }
+
+    #[test]
+    fn test_mentions() {
+        assert!(mentions("2 eggs, beaten", "egg"));
+        assert!(mentions("Extra virgin olive oil", "olive oil"));
+        assert!(!mentions("olive and sunflower oil", "olive oil"));
+        assert!(!mentions("eggplant", "egg"));
+        assert!(!mentions("boiled eggs", "oil"));
+        assert!(!mentions("unsalted butter", "salt"));
+        assert!(!mentions("anything", ""));
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt::{self, Display};
+
+use super::markdown::{escape, to_markdown};
+use super::recipe_parser::{Recipe, RecipeIngredient, Step};
+
+
+impl Recipe {
+    /// Writes the recipe out as Markdown that `parse` reads back into the same recipe.
+    pub fn to_markdown(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Writes several recipes into one document, the way `parse` expects a whole response.
+pub fn recipes_to_markdown(recipes: &[Recipe]) -> String {
+    recipes.iter().map(Recipe::to_markdown).collect::<Vec<_>>().join("\n\n")
+}
+
+// "## Name", then "### Ingredients" / "### Instructions", which is the layout least likely to be
+// confused with the steps themselves
+impl Display for Recipe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "## {}", to_markdown(&self.name))?;
+        if let Some(servings) = self.servings {
+            write!(f, "\nServes: {}", servings)?;
+        }
+
+        if !self.ingredients.is_empty() {
+            write!(f, "\n\n### Ingredients\n")?;
+            for i in &self.ingredients {
+                write!(f, "\n- {}", escape(&i.to_string()))?;
+            }
+        }
+
+        if !self.instructions.is_empty() {
+            write!(f, "\n\n### Instructions\n")?;
+            for (n, step) in self.instructions.iter().enumerate() {
+                write!(f, "\n{}. {}", n + 1, to_markdown(&step.text))?;
+                write_substeps(f, &step.substeps, 3)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_substeps(f: &mut fmt::Formatter<'_>, steps: &[Step], indent: usize) -> fmt::Result {
+    for step in steps {
+        write!(f, "\n{:indent$}- {}", "", to_markdown(&step.text), indent = indent)?;
+        write_substeps(f, &step.substeps, indent + 2)?;
+    }
+    Ok(())
+}
+
+// "2 cups rice, rinsed"
+impl Display for RecipeIngredient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(q) = self.quantity {
+            write!(f, "{} ", q)?;
+        }
+        write!(f, "{}", self.name)?;
+        if let Some(note) = &self.note {
+            write!(f, ", {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::{parse, parse_ingredient, Amount, MdElement, MdFragment, Quantity, Unit};
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_to_markdown() {
+        let recipe = Recipe {
+            name: vec![MdElement::Text("Ham ".to_owned()), MdElement::Em(vec![MdElement::Text("Hash".to_owned())])],
+            servings: Some(2),
+            ingredients: vec![parse_ingredient("200 g ham, diced"), parse_ingredient("salt")],
+            instructions: vec![
+                Step {
+                    text: vec![MdElement::Text("Fry the ham.".to_owned())],
+                    substeps: vec![Step::new(vec![MdElement::Text("Until golden.".to_owned())])],
+                },
+                Step::new(vec![MdElement::Text("Serve.".to_owned())]),
+            ],
+        };
+
+        assert_eq!(recipe.to_markdown(), "## Ham *Hash*\nServes: 2\n\n### Ingredients\n\n- 200 g ham, diced\n- salt\n\n### Instructions\n\n1. Fry the ham.\n   - Until golden.\n2. Serve.");
+        assert_eq!(parse(&recipe.to_markdown()).unwrap(), vec![recipe]);
+    }
+
+    // the generators stick to what the dialect can express: words that don't name sections or
+    // units, amounts that fractions print exactly, and no markup inside code or link text
+    const WORDS: &[&str] = &["fry", "the", "golden", "slowly", "pan", "oven", "hot", "until", "done", "with", "a", "lid"];
+    const SYMBOLS: &[&str] = &["2*3", "snake_case", "~50", "[aside]", "<3", "back\\slash", "`tick", "50%", "#1"];
+    const NAMES: &[&str] = &["rice", "ham", "potatoes", "basil", "olive oil", "tomato sauce", "eggs", "garlic"];
+
+    fn words() -> impl Strategy<Value = String> {
+        prop::collection::vec(prop::sample::select(WORDS), 1..4).prop_map(|w| w.join(" "))
+    }
+
+    fn text_with_symbols() -> impl Strategy<Value = String> {
+        prop::collection::vec(prop_oneof![prop::sample::select(WORDS), prop::sample::select(SYMBOLS)], 1..4)
+            .prop_map(|w| w.join(" "))
+    }
+
+    fn leaf() -> impl Strategy<Value = MdElement> {
+        prop_oneof![
+            words().prop_map(MdElement::Text),
+            words().prop_map(MdElement::Code),
+            words().prop_map(|t| MdElement::Link { text: vec![MdElement::Text(t)], url: "https://example.com/a".to_owned() }),
+        ]
+    }
+
+    fn element() -> impl Strategy<Value = MdElement> {
+        let children = || prop::collection::vec(leaf(), 1..3).prop_map(merge_text);
+        prop_oneof![
+            4 => text_with_symbols().prop_map(MdElement::Text),
+            2 => leaf(),
+            1 => children().prop_map(MdElement::Em),
+            1 => children().prop_map(MdElement::Strong),
+            1 => children().prop_map(MdElement::Strike),
+            1 => (words(), words()).prop_map(|(a, b)| MdElement::Strong(vec![
+                MdElement::Text(a + " "),
+                MdElement::Em(vec![MdElement::Text(b)]),
+            ])),
+            1 => (words(), words()).prop_map(|(a, b)| MdElement::Em(vec![
+                MdElement::Text(a + " "),
+                MdElement::Strong(vec![MdElement::Text(b)]),
+            ])),
+        ]
+    }
+
+    fn merge_text(fragment: MdFragment) -> MdFragment {
+        fragment.into_iter().fold(Vec::new(), |mut merged, e| {
+            match (merged.last_mut(), e) {
+                (Some(MdElement::Text(prev)), MdElement::Text(t)) => prev.push_str(&t),
+                (_, e) => merged.push(e),
+            }
+            merged
+        })
+    }
+
+    fn fragment() -> impl Strategy<Value = MdFragment> {
+        prop::collection::vec(element(), 1..4).prop_map(merge_text)
+    }
+
+    fn amount() -> impl Strategy<Value = Amount> {
+        prop_oneof![
+            (1..80u32).prop_map(|eighths| Amount::Single(eighths as f64 / 8.0)),
+            (1..10u32, 1..10u32).prop_map(|(a, d)| Amount::Range(a as f64, (a + d) as f64)),
+        ]
+    }
+
+    fn unit() -> impl Strategy<Value = Unit> {
+        prop::sample::select(vec![Unit::Cup, Unit::Tablespoon, Unit::Teaspoon, Unit::Gram, Unit::Kilogram, Unit::Milliliter, Unit::Liter, Unit::Ounce, Unit::Pound, Unit::Pinch, Unit::Clove, Unit::Can, Unit::Slice])
+    }
+
+    fn ingredient() -> impl Strategy<Value = RecipeIngredient> {
+        (
+            prop::option::of((amount(), prop::option::of(unit())).prop_map(|(amount, unit)| Quantity { amount, unit })),
+            prop::sample::select(NAMES),
+            prop::option::of(words()),
+        )
+            .prop_map(|(quantity, name, note)| RecipeIngredient { quantity, name: name.to_owned(), note })
+    }
+
+    fn step() -> impl Strategy<Value = Step> {
+        let leaf = fragment().prop_map(Step::new);
+        leaf.prop_recursive(2, 8, 3, |inner| {
+            (fragment(), prop::collection::vec(inner, 0..3)).prop_map(|(text, substeps)| Step { text, substeps })
+        })
+    }
+
+    fn recipe() -> impl Strategy<Value = Recipe> {
+        (
+            fragment(),
+            prop::option::of(1..13u32),
+            prop::collection::vec(ingredient(), 0..4),
+            prop::collection::vec(step(), 1..4),
+        )
+            .prop_map(|(name, servings, ingredients, instructions)| Recipe { name, servings, ingredients, instructions })
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(recipes in prop::collection::vec(recipe(), 1..4)) {
+            let markdown = recipes_to_markdown(&recipes);
+            prop_assert_eq!(parse(&markdown).unwrap(), recipes, "{}", markdown);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Someone with an account. Their pantry, book and preferences are theirs alone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub id: Uuid,
+    pub email: String,
+}
+
+/// The email as accounts are looked up by, so the same address always finds the same account.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// What's wrong with an email and password for a new account, if anything.
+pub fn check_new_account(email: &str, password: &str) -> Result<(), String> {
+    let email = normalize_email(email);
+    let valid_email = match email.split_once('@') {
+        Some((name, domain)) => !name.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.'),
+        None => false,
+    };
+    if !valid_email || email.contains(char::is_whitespace) {
+        return Err("That doesn't look like an email address".to_owned());
+    }
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!("A password needs at least {} characters", MIN_PASSWORD_LENGTH));
+    }
+    Ok(())
+}
+
+/// Where something kept in the browser's storage under `key` lives for `user`, so people
+/// sharing a browser don't see each other's.
+pub fn storage_key(key: &str, user: Option<&User>) -> String {
+    match user {
+        Some(user) => format!("{}/{}", key, user.id),
+        None => key.to_owned(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_new_account() {
+        assert_eq!(check_new_account(" Bonnie@Example.com ", "correct horse"), Ok(()));
+        assert!(check_new_account("bonnie", "correct horse").is_err());
+        assert!(check_new_account("bonnie@localhost", "correct horse").is_err());
+        assert!(check_new_account("bon nie@example.com", "correct horse").is_err());
+        assert_eq!(check_new_account("bonnie@example.com", "short").unwrap_err(), "A password needs at least 8 characters");
+    }
+
+    #[test]
+    fn test_storage_key() {
+        let user = User { id: Uuid::nil(), email: "bonnie@example.com".to_owned() };
+        assert_eq!(storage_key("ingredients", None), "ingredients");
+        assert_eq!(storage_key("ingredients", Some(&user)), "ingredients/00000000-0000-0000-0000-000000000000");
+    }
+}